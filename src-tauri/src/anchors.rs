@@ -39,78 +39,120 @@ const MARKER: &str = "%press:";
 /// needs anyway.
 pub fn mark(markdown: &str) -> String {
     let mut out = String::with_capacity(markdown.len() + markdown.len() / 8);
+    for line in lines(markdown) {
+        if line.starts_block {
+            out.push_str(OPEN);
+            out.push('\n');
+            out.push_str(MARKER);
+            out.push_str(&line.number.to_string());
+            out.push('\n');
+            out.push_str(CLOSE);
+            out.push_str("\n\n");
+        }
+        out.push_str(line.text);
+        out.push('\n');
+    }
+    out
+}
+
+/// What one line of a markdown document is, as far as its block structure
+/// goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    /// The YAML block at the top, delimiters included.
+    FrontMatter,
+    /// Inside a fenced block, or one of its fences.
+    Code,
+    Blank,
+    /// Everything else: paragraphs, headings, list items, quotes.
+    Text,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line<'a> {
+    /// 1-based.
+    pub number: u32,
+    pub text: &'a str,
+    pub kind: LineKind,
+    /// A top-level block begins here, which is where `mark` puts a marker.
+    pub starts_block: bool,
+}
+
+/// The block structure `mark` follows, line by line. Shared with anything else
+/// that has to tell the prose of a markdown document from its metadata and its
+/// code, so that the two never disagree about where a fence ends.
+pub fn lines(markdown: &str) -> Vec<Line<'_>> {
+    let mut out = Vec::new();
     let mut fence: Option<String> = None;
     let mut in_front_matter = false;
     let mut at_block_start = true;
     let mut in_list = false;
 
-    for (index, line) in markdown.lines().enumerate() {
+    for (index, text) in markdown.lines().enumerate() {
         let number = index as u32 + 1;
-        let trimmed = line.trim_start();
+        let trimmed = text.trim_start();
+        let line = |kind, starts_block| Line {
+            number,
+            text,
+            kind,
+            starts_block,
+        };
 
         // Metadata, not content. It is one block from the first `---` to the
         // second, and a marker inside it would be read as a field.
-        if number == 1 && line.trim_end() == "---" {
+        if number == 1 && text.trim_end() == "---" {
             in_front_matter = true;
-            out.push_str(line);
-            out.push('\n');
+            out.push(line(LineKind::FrontMatter, false));
             continue;
         }
         if in_front_matter {
-            if line.trim_end() == "---" || line.trim_end() == "..." {
+            if text.trim_end() == "---" || text.trim_end() == "..." {
                 in_front_matter = false;
                 at_block_start = true;
             }
-            out.push_str(line);
-            out.push('\n');
+            out.push(line(LineKind::FrontMatter, false));
             continue;
         }
 
         // Inside a fence everything is content, including blank lines.
         if let Some(open) = &fence {
-            out.push_str(line);
-            out.push('\n');
             if closes_fence(trimmed, open) {
                 fence = None;
                 at_block_start = false;
             }
+            out.push(line(LineKind::Code, false));
             continue;
         }
 
-        if line.trim().is_empty() {
-            out.push_str(line);
-            out.push('\n');
+        if text.trim().is_empty() {
+            out.push(line(LineKind::Blank, false));
             at_block_start = true;
             continue;
         }
 
+        let mut starts_block = false;
         if at_block_start {
-            let indented = line.starts_with(' ') || line.starts_with('\t');
+            let indented = text.starts_with(' ') || text.starts_with('\t');
             let listish = starts_list(trimmed) || trimmed.starts_with(':');
             // A block that belongs to a list is left alone; one that starts a
             // list is marked, because the marker then sits before the list
             // rather than inside it.
             let inside_list = in_list && (indented || listish);
-            if !inside_list && !indented {
-                out.push_str(OPEN);
-                out.push('\n');
-                out.push_str(MARKER);
-                out.push_str(&number.to_string());
-                out.push('\n');
-                out.push_str(CLOSE);
-                out.push_str("\n\n");
-            }
+            starts_block = !inside_list && !indented;
             if !inside_list {
                 in_list = starts_list(trimmed);
             }
             at_block_start = false;
         }
 
-        if let Some(open) = opens_fence(trimmed) {
-            fence = Some(open);
-        }
-        out.push_str(line);
-        out.push('\n');
+        let kind = match opens_fence(trimmed) {
+            Some(open) => {
+                fence = Some(open);
+                LineKind::Code
+            }
+            None => LineKind::Text,
+        };
+        out.push(line(kind, starts_block));
     }
     out
 }
@@ -149,6 +191,19 @@ pub fn source_line(anchors: &[Anchor], generated: u32) -> Option<u32> {
         .map(|anchor| anchor.source)
 }
 
+/// The line of generated LaTeX a markdown line went to: the block under the
+/// nearest marker at or above it. The reverse of `source_line`, for asking
+/// SyncTeX where a line of the markdown was typeset.
+pub fn generated_line(anchors: &[Anchor], source: u32) -> Option<u32> {
+    anchors
+        .iter()
+        .filter(|anchor| anchor.source <= source)
+        .max_by_key(|anchor| (anchor.source, anchor.generated))
+        // The marker itself is a comment, which typesets as nothing; the block
+        // starts on the line after it.
+        .map(|anchor| anchor.generated + 1)
+}
+
 /// `generated:source` pairs, one per line — the form stored beside a built PDF.
 pub fn encode(anchors: &[Anchor]) -> String {
     let mut out = String::new();
@@ -255,6 +310,27 @@ mod tests {
         assert!(marked.contains("x = 1\n\ny = 2"), "the fence is untouched");
     }
 
+    #[test]
+    fn classifies_metadata_code_and_text() {
+        let markdown = "---\ntitle: A Paper\n---\n\n# Heading\n\n```\nteh code\n```\nAfter.\n";
+        let kinds: Vec<LineKind> = lines(markdown).iter().map(|line| line.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                LineKind::FrontMatter,
+                LineKind::FrontMatter,
+                LineKind::FrontMatter,
+                LineKind::Blank,
+                LineKind::Text,
+                LineKind::Blank,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Code,
+                LineKind::Text,
+            ]
+        );
+    }
+
     #[test]
     fn a_generated_line_resolves_to_the_block_above_it() {
         let anchors = vec![
@@ -274,6 +350,24 @@ mod tests {
         assert_eq!(source_line(&anchors, 900), Some(9));
     }
 
+    #[test]
+    fn a_markdown_line_goes_to_the_block_its_marker_opens() {
+        let anchors = vec![
+            Anchor {
+                generated: 10,
+                source: 5,
+            },
+            Anchor {
+                generated: 20,
+                source: 9,
+            },
+        ];
+        assert_eq!(generated_line(&anchors, 4), None);
+        assert_eq!(generated_line(&anchors, 5), Some(11));
+        assert_eq!(generated_line(&anchors, 8), Some(11));
+        assert_eq!(generated_line(&anchors, 12), Some(21));
+    }
+
     #[test]
     fn anchors_survive_a_round_trip_through_storage() {
         let anchors = vec![
@@ -0,0 +1,299 @@
+//! A reader's highlights and comments, carried between versions and out into
+//! the PDF.
+//!
+//! An annotation is made on one version's PDF and stored against that version,
+//! so it stays where it was put however far the working tree moves on. What
+//! lets it follow the text anyway is its anchor: the source line SyncTeX put
+//! under it, and what that line said. In the working tree the line is found
+//! again by its words rather than its number — a paragraph added above it moves
+//! every number below — and SyncTeX, asked the other way, says where that line
+//! is typeset now.
+//!
+//! Exported, the annotations become ordinary PDF annotations: a highlight per
+//! marked line with the comment as its note, and a note icon for a comment on
+//! its own. That is what a supervisor's PDF reader shows and what it writes
+//! back, so a marked-up copy can go out and come back without Press on the
+//! other end.
+
+use std::collections::HashSet;
+
+use mupdf::pdf::{PdfDocument, PdfObject};
+
+use crate::{
+    error::AppResult,
+    impose,
+    model::{Annotation, AnnotationKind, SourceAnchor},
+    render::mupdf_error,
+};
+
+/// The yellow of a highlighter pen, as most readers draw one.
+const HIGHLIGHT: [f32; 3] = [1.0, 0.85, 0.2];
+
+/// The side of a comment's note icon, in points.
+const NOTE_SIZE: f32 = 20.0;
+
+/// The 1-based line of `current` that an anchor's line has become.
+///
+/// The same words win, nearest the old line number first, since a line that
+/// repeats — a `\item`, a `\end{proof}` — repeats near itself. Failing that,
+/// the line that shares most of its words with the anchor, as long as that is
+/// most of them: a line with a typo fixed is the same line, one rewritten from
+/// scratch is not.
+pub fn relocate(anchor: &SourceAnchor, current: &str) -> Option<usize> {
+    let wanted = anchor.text.trim();
+    if wanted.is_empty() {
+        return None;
+    }
+    let lines: Vec<&str> = current.lines().collect();
+    let distance = |index: usize| (index + 1).abs_diff(anchor.line);
+
+    if let Some(index) = (0..lines.len())
+        .filter(|&index| lines[index].trim() == wanted)
+        .min_by_key(|&index| distance(index))
+    {
+        return Some(index + 1);
+    }
+
+    let ours = words(wanted);
+    if ours.is_empty() {
+        return None;
+    }
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let theirs = words(line);
+            let shared = ours.intersection(&theirs).count();
+            let score = shared as f32 / ours.union(&theirs).count().max(1) as f32;
+            (score >= 0.5).then_some((index, score))
+        })
+        .max_by(|left, right| {
+            left.1
+                .total_cmp(&right.1)
+                .then_with(|| distance(right.0).cmp(&distance(left.0)))
+        })
+        .map(|(index, _)| index + 1)
+}
+
+fn words(line: &str) -> HashSet<String> {
+    line.split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Writes `annotations` into the document as PDF annotations and hands back
+/// the whole PDF.
+///
+/// Each carries its own appearance, so a reader that does not draw highlights
+/// itself still shows them. An annotation on a page the document no longer
+/// has is left out: the working tree's PDF can be shorter than it was.
+pub fn write(document: &mut PdfDocument, annotations: &[Annotation]) -> AppResult<Vec<u8>> {
+    let failed = |error| mupdf_error("could not write the annotations", error);
+    let count = document.page_count().map_err(failed)? as u32;
+    for annotation in annotations.iter().filter(|found| found.page < count) {
+        let page_box = impose::page_box(document, annotation.page as usize)?;
+        let object = match annotation.kind {
+            AnnotationKind::Highlight => highlight(document, page_box, annotation)?,
+            AnnotationKind::Comment => note(document, page_box, annotation)?,
+        };
+        let object = document.add_object(&object).map_err(failed)?;
+
+        let mut page = document.find_page(annotation.page as i32).map_err(failed)?;
+        match impose::dictionary(&page, "Annots") {
+            Some(mut existing) if existing.is_array().map_err(failed)? => {
+                existing.array_push(object).map_err(failed)?;
+            }
+            _ => {
+                let mut created = document.new_array().map_err(failed)?;
+                created.array_push(object).map_err(failed)?;
+                page.dict_put("Annots", created).map_err(failed)?;
+            }
+        }
+    }
+
+    let mut bytes = Vec::new();
+    document
+        .write_to(&mut bytes)
+        .map_err(|error| mupdf_error("could not write the PDF", error))?;
+    Ok(bytes)
+}
+
+/// A highlight over every marked line, drawn multiplied so the text under it
+/// stays black.
+fn highlight(
+    document: &mut PdfDocument,
+    page_box: [f32; 4],
+    annotation: &Annotation,
+) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not write a highlight", error);
+    let corners: Vec<[f32; 4]> = annotation
+        .rects
+        .iter()
+        .map(|rect| {
+            let left = page_box[0] + rect.x;
+            let top = page_box[3] - rect.y;
+            [left, top - rect.height, left + rect.width, top]
+        })
+        .collect();
+    let bounds = corners.iter().fold(
+        [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+        |bounds, corner| {
+            [
+                bounds[0].min(corner[0]),
+                bounds[1].min(corner[1]),
+                bounds[2].max(corner[2]),
+                bounds[3].max(corner[3]),
+            ]
+        },
+    );
+
+    // Upper left, upper right, lower left, lower right: the order the
+    // specification's figure draws and every reader expects, whatever its
+    // prose says.
+    let mut quads = document.new_array().map_err(failed)?;
+    let mut drawing = format!(
+        "/Multiply gs {} {} {} rg\n",
+        HIGHLIGHT[0], HIGHLIGHT[1], HIGHLIGHT[2]
+    );
+    for [x0, y0, x1, y1] in &corners {
+        for value in [*x0, *y1, *x1, *y1, *x0, *y0, *x1, *y0] {
+            quads
+                .array_push(document.new_real(value).map_err(failed)?)
+                .map_err(failed)?;
+        }
+        drawing.push_str(&format!("{x0} {y0} {} {} re f\n", x1 - x0, y1 - y0));
+    }
+
+    let mut object = base(document, "Highlight", bounds, annotation)?;
+    object.dict_put("QuadPoints", quads).map_err(failed)?;
+
+    let mut multiply = document.new_dict().map_err(failed)?;
+    multiply
+        .dict_put("BM", document.new_name("Multiply").map_err(failed)?)
+        .map_err(failed)?;
+    let mut states = document.new_dict().map_err(failed)?;
+    states.dict_put("Multiply", multiply).map_err(failed)?;
+    let mut resources = document.new_dict().map_err(failed)?;
+    resources.dict_put("ExtGState", states).map_err(failed)?;
+
+    let mut form = document.new_dict().map_err(failed)?;
+    form.dict_put("Type", document.new_name("XObject").map_err(failed)?)
+        .map_err(failed)?;
+    form.dict_put("Subtype", document.new_name("Form").map_err(failed)?)
+        .map_err(failed)?;
+    form.dict_put("BBox", impose::rectangle(document, bounds)?)
+        .map_err(failed)?;
+    form.dict_put("Resources", resources).map_err(failed)?;
+    let form = impose::stream(document, form, drawing.as_bytes())?;
+    let mut appearance = document.new_dict().map_err(failed)?;
+    appearance.dict_put("N", form).map_err(failed)?;
+    object.dict_put("AP", appearance).map_err(failed)?;
+    Ok(object)
+}
+
+/// A note icon hanging from the top left of the comment's place. Readers draw
+/// their own icon for a note, so it needs no appearance of its own.
+fn note(
+    document: &mut PdfDocument,
+    page_box: [f32; 4],
+    annotation: &Annotation,
+) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not write a comment", error);
+    let place = annotation.rects.first().copied().unwrap_or_default();
+    let left = page_box[0] + place.x;
+    let top = page_box[3] - place.y;
+    let mut object = base(
+        document,
+        "Text",
+        [left, top - NOTE_SIZE, left + NOTE_SIZE, top],
+        annotation,
+    )?;
+    object
+        .dict_put("Name", document.new_name("Comment").map_err(failed)?)
+        .map_err(failed)?;
+    Ok(object)
+}
+
+/// What every annotation has: its kind, its place, its colour and its text.
+/// Printed, like the marks on a paper copy.
+fn base(
+    document: &mut PdfDocument,
+    subtype: &str,
+    bounds: [f32; 4],
+    annotation: &Annotation,
+) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not write an annotation", error);
+    let mut object = document.new_dict().map_err(failed)?;
+    object
+        .dict_put("Type", document.new_name("Annot").map_err(failed)?)
+        .map_err(failed)?;
+    object
+        .dict_put("Subtype", document.new_name(subtype).map_err(failed)?)
+        .map_err(failed)?;
+    object
+        .dict_put("Rect", impose::rectangle(document, bounds)?)
+        .map_err(failed)?;
+    object
+        .dict_put("F", document.new_int(4).map_err(failed)?)
+        .map_err(failed)?;
+    let mut colour = document.new_array().map_err(failed)?;
+    for value in HIGHLIGHT {
+        colour
+            .array_push(document.new_real(value).map_err(failed)?)
+            .map_err(failed)?;
+    }
+    object.dict_put("C", colour).map_err(failed)?;
+    if !annotation.body.is_empty() {
+        object
+            .dict_put(
+                "Contents",
+                document.new_string(&annotation.body).map_err(failed)?,
+            )
+            .map_err(failed)?;
+    }
+    Ok(object)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn anchor(line: usize, text: &str) -> SourceAnchor {
+        SourceAnchor {
+            file: "main.tex".into(),
+            line,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn a_line_is_found_again_after_the_text_around_it_moves() {
+        let current =
+            "\\section{New}\nA new paragraph.\n\n\\item First\nThe claim.\n\\item First\n";
+        assert_eq!(relocate(&anchor(3, "The claim."), current), Some(5));
+        assert_eq!(
+            relocate(&anchor(3, "\\item First"), current),
+            Some(4),
+            "of two copies, the nearer one"
+        );
+        assert_eq!(relocate(&anchor(6, "\\item First"), current), Some(6));
+    }
+
+    #[test]
+    fn an_edited_line_is_the_same_line_and_a_rewritten_one_is_not() {
+        let current = "Intro.\nWe prove the main theorem in two steps here.\nOther.\n";
+        assert_eq!(
+            relocate(
+                &anchor(2, "We prove the main theorem in two steps."),
+                current
+            ),
+            Some(2)
+        );
+        assert_eq!(
+            relocate(&anchor(2, "Entirely different words now."), current),
+            None
+        );
+        assert_eq!(relocate(&anchor(2, "   "), current), None);
+    }
+}
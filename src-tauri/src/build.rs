@@ -25,19 +25,23 @@ use std::{
 };
 
 use notify::{Event, EventKind, RecursiveMode, Watcher};
-use tauri::{AppHandle, Emitter};
+use tauri::{AppHandle, Emitter, Manager};
 use tokio::sync::{Mutex, Semaphore, mpsc};
 
 use crate::{
+    AppState, changes,
     database::{NewArtifact, Repository},
     diagnostics::ProgressSnapshot,
     error::{AppError, AppResult},
-    files,
+    files, library,
     model::{
         ArtifactSummary, BuildProgress, BuildState, BuildStatus, BuildUpdate, Diagnostic, Project,
         SourceRef,
     },
-    runner::{self, BuildInputs, BuildOutcome, Cancel, CancelHandle, PidRegistry, ProgressSink},
+    runner::{
+        self, BuildInputs, BuildOutcome, Cancel, CancelHandle, CheckInputs, PidRegistry,
+        ProgressSink,
+    },
     sources,
 };
 
@@ -46,6 +50,20 @@ use crate::{
 const MAX_CONCURRENT_BUILDS: usize = 3;
 const DEBOUNCE: Duration = Duration::from_millis(250);
 
+/// How many replaced working-tree PDFs are kept for going back to.
+pub const KEPT_SETTING: &str = "builds.kept";
+pub const DEFAULT_KEPT: usize = 5;
+
+/// The number of earlier working-tree builds to keep, as set or by default.
+pub fn kept_builds(repository: &Repository) -> usize {
+    repository
+        .setting(KEPT_SETTING)
+        .ok()
+        .flatten()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(DEFAULT_KEPT)
+}
+
 type BuildKey = (i64, SourceRef);
 
 pub struct BuildManager {
@@ -232,6 +250,7 @@ impl BuildManager {
                 diagnostics: Vec::new(),
             },
             None,
+            None,
         )
         .await;
 
@@ -278,7 +297,8 @@ impl BuildManager {
         // on the way in has to be undone. Skipped when a newer build has taken
         // over, which would otherwise be overwritten with stale state.
         if cancel.is_cancelled() && !superseded {
-            self.record(&app, build_id, project.id, settled, None).await;
+            self.record(&app, build_id, project.id, settled, None, None)
+                .await;
         }
     }
 
@@ -306,6 +326,7 @@ impl BuildManager {
                 diagnostics: Vec::new(),
             },
             None,
+            None,
         )
         .await;
 
@@ -396,37 +417,130 @@ impl BuildManager {
                     let page_count = product.page_count;
                     let byte_size = product.byte_size;
                     tauri::async_runtime::spawn_blocking(move || {
-                        repository.record_artifact(NewArtifact {
+                        let recorded = repository.record_artifact(NewArtifact {
                             project_id,
                             source_ref: &source_ref,
                             engine,
                             pdf_path: &pdf_path,
                             page_count,
                             byte_size,
-                        })
+                        })?;
+                        // The working tree's last few PDFs go into the ring
+                        // instead of away, and whatever falls out of it goes.
+                        let discarded = match recorded.1.clone() {
+                            Some(previous) if source_ref == SourceRef::Worktree => {
+                                keep_previous(&repository, &recorded.0, &previous)
+                            }
+                            superseded => superseded.into_iter().collect(),
+                        };
+                        Ok((recorded.0, discarded))
                     })
                     .await
                     .unwrap_or_else(|error| Err(AppError::Task(error.to_string())))
                 };
                 match recorded {
-                    Ok((artifact, superseded)) => {
-                        if let Some(previous) = superseded {
+                    Ok((artifact, discarded)) => {
+                        for previous in discarded {
                             runner::discard_publication(&previous).await;
                         }
+                        let state = BuildState {
+                            source_ref: source_ref.clone(),
+                            status: BuildStatus::Success,
+                            started_at: Some(started_at),
+                            finished_at: Some(now()),
+                            duration_ms: Some(elapsed.as_millis() as i64),
+                            error_summary: None,
+                            diagnostics,
+                        };
+                        // Indexed behind the build rather than as part of it:
+                        // nobody is waiting on the library's search, and
+                        // everyone is waiting on the page.
+                        let repository = Arc::clone(&self.repository);
+                        let (artifact_id, revision) = (artifact.id, artifact.revision);
+                        let pdf_path = product.pdf_path.clone();
+                        tauri::async_runtime::spawn_blocking(move || {
+                            if let Err(error) =
+                                library::index(&repository, artifact_id, revision, &pdf_path)
+                            {
+                                eprintln!("Press could not index {}: {error}", pdf_path.display());
+                            }
+                        });
+                        // The viewer is about to ask for the new PDF's opening
+                        // pages; they can be drawn while it hears about it.
+                        app.state::<AppState>().renderer.prewarm(&product.pdf_path);
+                        self.record(
+                            app,
+                            build_id,
+                            project.id,
+                            state.clone(),
+                            Some(artifact.clone()),
+                            None,
+                        )
+                        .await;
+
+                        // Which pages changed is worked out behind the page as
+                        // well, beside the advice, and follows it out. Only the
+                        // working tree is built over itself; a version's PDF is
+                        // the same every time it is built.
+                        let fingerprinting = (*source_ref == SourceRef::Worktree).then(|| {
+                            let repository = Arc::clone(&self.repository);
+                            let artifact_id = artifact.id;
+                            let pdf_path = product.pdf_path.clone();
+                            tauri::async_runtime::spawn_blocking(move || {
+                                changes::record(&repository, artifact_id, &pdf_path)
+                            })
+                        });
+
+                        // The page is out; advice about it follows into the
+                        // same build's state. Read now rather than before the
+                        // build, for the same reason as the frontmatter.
+                        let checks = CheckInputs {
+                            project,
+                            source: &source,
+                            work_directory: self.work_directory(project.id, source_ref),
+                            lint: crate::lint::enabled(&self.repository),
+                            lint_ignores: self
+                                .repository
+                                .lint_ignores(project.id)
+                                .unwrap_or_default(),
+                            dictionary: self.repository.dictionary(project.id).unwrap_or_default(),
+                            page_count: product.page_count,
+                            pdf: product.pdf_path.clone(),
+                            constraints: self
+                                .repository
+                                .page_constraints(project.id)
+                                .unwrap_or_default(),
+                        };
+                        let advice = runner::check(checks, &cancel).await;
+                        // Awaited even when the advice was cancelled: the
+                        // fingerprints are stored by now, and pages left
+                        // unmarked would never be compared again.
+                        let changed_pages = match fingerprinting {
+                            Some(task) => task.await.ok().flatten(),
+                            None => None,
+                        };
+                        if advice.is_none() && changed_pages.is_none() {
+                            return;
+                        }
+                        let mut state = state;
+                        if let Some(advice) = advice {
+                            // A word count that could not be stored is a history
+                            // row without one, not a build that failed.
+                            let repository = Arc::clone(&self.repository);
+                            let artifact_id = artifact.id;
+                            let _ = tauri::async_runtime::spawn_blocking(move || {
+                                repository.record_statistics(artifact_id, &advice.statistics)
+                            })
+                            .await;
+                            state.diagnostics.extend(advice.diagnostics);
+                        }
                         self.record(
                             app,
                             build_id,
                             project.id,
-                            BuildState {
-                                source_ref: source_ref.clone(),
-                                status: BuildStatus::Success,
-                                started_at: Some(started_at),
-                                finished_at: Some(now()),
-                                duration_ms: Some(elapsed.as_millis() as i64),
-                                error_summary: None,
-                                diagnostics,
-                            },
+                            state,
                             Some(artifact),
+                            changed_pages,
                         )
                         .await;
                     }
@@ -506,6 +620,7 @@ impl BuildManager {
                 diagnostics,
             },
             None,
+            None,
         )
         .await;
     }
@@ -520,6 +635,7 @@ impl BuildManager {
         project_id: i64,
         state: BuildState,
         artifact: Option<ArtifactSummary>,
+        changed_pages: Option<Vec<u32>>,
     ) {
         // The four queries below go together and go off the runtime together —
         // one hop rather than four, and none of them on a thread that is meant
@@ -539,11 +655,18 @@ impl BuildManager {
                     .flatten()
                     .map(|stored| stored.summary)
             });
+            let statistics = artifact
+                .as_ref()
+                .and_then(|artifact| repository.artifact_statistics(artifact.id).ok().flatten());
             // The library grid shows per-project state, so keep it in step.
-            (artifact, repository.project_summary(project_id).ok())
+            (
+                artifact,
+                statistics,
+                repository.project_summary(project_id).ok(),
+            )
         })
         .await;
-        let (artifact, summary) = written.unwrap_or_default();
+        let (artifact, statistics, summary) = written.unwrap_or_default();
 
         let _ = app.emit(
             "build-updated",
@@ -553,6 +676,8 @@ impl BuildManager {
                 source_ref: state.source_ref.clone(),
                 build: state,
                 artifact,
+                statistics,
+                changed_pages,
             },
         );
         if let Some(summary) = summary {
@@ -745,6 +870,32 @@ async fn watch_loop(
     }
 }
 
+/// Keeps a replaced working-tree PDF in the ring, returning what to delete.
+fn keep_previous(
+    repository: &Repository,
+    artifact: &ArtifactSummary,
+    previous: &Path,
+) -> Vec<PathBuf> {
+    let Ok(metadata) = std::fs::metadata(previous) else {
+        return Vec::new();
+    };
+    let built_at = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map_or_else(now, |since| since.as_secs() as i64);
+    repository
+        .keep_previous_build(
+            artifact.id,
+            artifact.revision - 1,
+            previous,
+            metadata.len() as i64,
+            built_at,
+            kept_builds(repository),
+        )
+        .unwrap_or_else(|_| vec![previous.to_path_buf()])
+}
+
 /// A broken watcher is a Press problem, not a document problem, and is reported
 /// on its own channel so it never appears as a compile error.
 fn emit_watcher_error(app: &AppHandle, project_id: i64, message: &str) {
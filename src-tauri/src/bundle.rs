@@ -0,0 +1,424 @@
+//! A PDF that carries the source it was built from.
+//!
+//! Press treats a PDF as a view of a version, never the thing itself. Sent to a
+//! co-author, though, the PDF is usually all that travels, and the version it
+//! views stays behind on this machine. Exported this way it goes with it: every
+//! file of the version's manifest, read back out of the object store exactly as
+//! it was kept, attached to the PDF as an embedded file under its
+//! project-relative path. An XMP packet on the catalogue records which version
+//! that is — the revision, the engine it was built with and when — so the
+//! attachments can be told apart from anything else a PDF might carry.
+//!
+//! Each attachment is marked as the document's source in the PDF 2.0 sense, and
+//! listed in the catalogue's associated files, so a PDF/A-3 reader shows them
+//! for what they are.
+//!
+//! The other way round, any PDF whose attachments include a document Press
+//! compiles can be unpacked into a folder and become a project. That is not
+//! only Press's own bundles: `embedfile`, `attachfile` with a name tree and
+//! most tools that attach sources all list them the same way.
+
+use std::{
+    io::Write as _,
+    path::{Component, Path, PathBuf},
+};
+
+use mupdf::{
+    Buffer,
+    pdf::{PdfDocument, PdfObject},
+};
+
+use crate::{
+    error::{AppError, AppResult},
+    model::Engine,
+    render::mupdf_error,
+};
+
+/// Which version a bundle holds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    /// The manifest's hash: the version, independent of its title.
+    pub revision: String,
+    pub engine: Engine,
+    /// Unix seconds.
+    pub built_at: i64,
+    /// The file the document is compiled from, relative to the project.
+    pub document: String,
+}
+
+/// Name trees inside name trees. Deeper than any writer goes; past it the tree
+/// is a cycle.
+const MAX_DEPTH: usize = 16;
+
+/// Where Press's own XMP properties live. A URN, because the properties are
+/// read by Press and nothing else, and a URL would promise a page.
+const NAMESPACE: &str = "urn:press:source:1.0:";
+
+/// Attaches `files`, each a project-relative path and its contents, and the
+/// XMP packet for `provenance`, and hands back the whole PDF.
+///
+/// The document's own name tree of attachments is replaced. TeX output rarely
+/// has one, and a bundle that mixed a version's source with files it did not
+/// come from would not be the version any more.
+pub fn embed(
+    document: &mut PdfDocument,
+    files: &[(String, Vec<u8>)],
+    provenance: &Provenance,
+) -> AppResult<Vec<u8>> {
+    let failed = |error| mupdf_error("could not attach the source", error);
+    let mut catalogue = document
+        .trailer()
+        .and_then(|trailer| trailer.get_dict("Root"))
+        .map_err(failed)?
+        .ok_or_else(|| AppError::Build("the PDF has no document catalogue".into()))?;
+
+    // A name tree's keys are kept in order, and its leaves are (key, value)
+    // pairs in one flat array.
+    let mut sorted = files.iter().collect::<Vec<_>>();
+    sorted.sort_by(|left, right| left.0.as_bytes().cmp(right.0.as_bytes()));
+    let mut names = document.new_array().map_err(failed)?;
+    let mut associated = document.new_array().map_err(failed)?;
+    for (path, contents) in sorted {
+        let specification = attachment(document, path, contents)?;
+        names
+            .array_push(document.new_string(path).map_err(failed)?)
+            .map_err(failed)?;
+        names.array_push(specification.clone()).map_err(failed)?;
+        associated.array_push(specification).map_err(failed)?;
+    }
+
+    let mut tree = document.new_dict().map_err(failed)?;
+    tree.dict_put("Names", names).map_err(failed)?;
+    let mut catalogue_names = match catalogue.get_dict("Names").map_err(failed)? {
+        Some(existing) => existing,
+        None => {
+            let created = document.new_dict().map_err(failed)?;
+            let created = document.add_object(&created).map_err(failed)?;
+            catalogue
+                .dict_put("Names", created.clone())
+                .map_err(failed)?;
+            created
+        }
+    };
+    catalogue_names
+        .dict_put("EmbeddedFiles", tree)
+        .map_err(failed)?;
+    catalogue.dict_put("AF", associated).map_err(failed)?;
+
+    let mut metadata = document.new_dict().map_err(failed)?;
+    metadata
+        .dict_put("Type", document.new_name("Metadata").map_err(failed)?)
+        .map_err(failed)?;
+    metadata
+        .dict_put("Subtype", document.new_name("XML").map_err(failed)?)
+        .map_err(failed)?;
+    let metadata = stream(document, metadata, xmp(provenance).as_bytes())?;
+    catalogue.dict_put("Metadata", metadata).map_err(failed)?;
+
+    let mut bytes = Vec::new();
+    document
+        .write_to(&mut bytes)
+        .map_err(|error| mupdf_error("could not write the PDF", error))?;
+    Ok(bytes)
+}
+
+/// One file as a file specification, its contents in an embedded file stream.
+fn attachment(document: &mut PdfDocument, path: &str, contents: &[u8]) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not attach a file", error);
+    let mut parameters = document.new_dict().map_err(failed)?;
+    parameters
+        .dict_put(
+            "Size",
+            document.new_int(contents.len() as i32).map_err(failed)?,
+        )
+        .map_err(failed)?;
+    let mut embedded = document.new_dict().map_err(failed)?;
+    embedded
+        .dict_put("Type", document.new_name("EmbeddedFile").map_err(failed)?)
+        .map_err(failed)?;
+    embedded.dict_put("Params", parameters).map_err(failed)?;
+    let embedded = stream(document, embedded, contents)?;
+
+    let mut streams = document.new_dict().map_err(failed)?;
+    streams.dict_put("F", embedded.clone()).map_err(failed)?;
+    streams.dict_put("UF", embedded).map_err(failed)?;
+
+    let mut specification = document.new_dict().map_err(failed)?;
+    specification
+        .dict_put("Type", document.new_name("Filespec").map_err(failed)?)
+        .map_err(failed)?;
+    specification
+        .dict_put("F", document.new_string(path).map_err(failed)?)
+        .map_err(failed)?;
+    specification
+        .dict_put("UF", document.new_string(path).map_err(failed)?)
+        .map_err(failed)?;
+    specification
+        .dict_put(
+            "AFRelationship",
+            document.new_name("Source").map_err(failed)?,
+        )
+        .map_err(failed)?;
+    specification.dict_put("EF", streams).map_err(failed)?;
+    document.add_object(&specification).map_err(failed)
+}
+
+/// `dictionary` made an indirect object holding `bytes` as its stream.
+fn stream(document: &mut PdfDocument, dictionary: PdfObject, bytes: &[u8]) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not write a stream", error);
+    let mut object = document.add_object(&dictionary).map_err(failed)?;
+    let mut buffer = Buffer::with_capacity(bytes.len());
+    buffer.write_all(bytes)?;
+    object.write_stream_buffer(&buffer).map_err(failed)?;
+    Ok(object)
+}
+
+/// The names of a PDF's attachments, when one of them is a document Press could
+/// compile. Nothing otherwise: a PDF that only attaches a data set or a video
+/// has nothing to offer as a project.
+pub fn sources(document: &PdfDocument) -> Vec<String> {
+    let names = listed(document)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    let compilable = names.iter().any(|name| {
+        let path = Path::new(name);
+        crate::documents::is_latex(path) || crate::documents::is_markdown(path)
+    });
+    if compilable { names } else { Vec::new() }
+}
+
+/// Every attachment that can be written inside a folder, as a path relative to
+/// it and its contents. One whose name would land outside — absolute, or
+/// climbing with `..` — is left out rather than trusted, as is a second file
+/// under a name already taken.
+pub fn attachments(document: &PdfDocument) -> Vec<(PathBuf, Vec<u8>)> {
+    let mut found = Vec::<(PathBuf, Vec<u8>)>::new();
+    for (name, specification) in listed(document) {
+        let Some(path) = inside(&name) else {
+            continue;
+        };
+        if found.iter().any(|(taken, _)| *taken == path) {
+            continue;
+        }
+        if let Some(contents) = contents(&specification) {
+            found.push((path, contents));
+        }
+    }
+    found
+}
+
+/// The document's XMP packet, if it has one.
+pub fn metadata(document: &PdfDocument) -> Option<String> {
+    let root = dictionary(&document.trailer().ok()?, "Root")?;
+    let bytes = dictionary(&root, "Metadata")?.read_stream().ok()?;
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// One of Press's properties out of an XMP packet, as [`xmp`] wrote it.
+pub fn property(xmp: &str, name: &str) -> Option<String> {
+    let open = format!("<press:{name}>");
+    let start = xmp.find(&open)? + open.len();
+    let end = start + xmp[start..].find("</press:")?;
+    Some(unescape(&xmp[start..end]))
+}
+
+/// A name relative to the folder it is unpacked into, or `None` when it would
+/// not stay inside it. Backslashes are taken as separators: a file attached on
+/// Windows is named with them.
+pub fn inside(name: &str) -> Option<PathBuf> {
+    let name = name.replace('\\', "/");
+    if name.starts_with('/') {
+        return None;
+    }
+    let mut path = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => path.push(part),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    // `C:` is an ordinary name to a Unix path and a drive to Windows.
+    let first = path.components().next()?;
+    if first.as_os_str().to_string_lossy().contains(':') {
+        return None;
+    }
+    Some(path)
+}
+
+/// Every entry of the embedded files name tree: its name and its file
+/// specification.
+fn listed(document: &PdfDocument) -> Vec<(String, PdfObject)> {
+    let mut found = Vec::new();
+    let tree = document
+        .trailer()
+        .ok()
+        .and_then(|trailer| dictionary(&trailer, "Root"))
+        .and_then(|root| dictionary(&root, "Names"))
+        .and_then(|names| dictionary(&names, "EmbeddedFiles"));
+    if let Some(tree) = tree {
+        collect(&tree, 0, &mut found);
+    }
+    found
+}
+
+fn collect(node: &PdfObject, depth: usize, found: &mut Vec<(String, PdfObject)>) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    if let Some(names) = dictionary(node, "Names") {
+        let length = names.len().unwrap_or_default();
+        for at in (0..length.saturating_sub(1)).step_by(2) {
+            let key = names.get_array(at as i32).ok().flatten();
+            let Some(specification) = names.get_array(at as i32 + 1).ok().flatten() else {
+                continue;
+            };
+            // The file's own name over the tree's key, which is often a
+            // generated one.
+            let name = file_name(&specification)
+                .or_else(|| key.and_then(|key| key.as_string().ok().map(str::to_owned)));
+            if let Some(name) = name {
+                found.push((name, specification));
+            }
+        }
+    }
+    if let Some(kids) = dictionary(node, "Kids") {
+        for at in 0..kids.len().unwrap_or_default() {
+            if let Some(kid) = kids.get_array(at as i32).ok().flatten() {
+                collect(&kid, depth + 1, found);
+            }
+        }
+    }
+}
+
+fn file_name(specification: &PdfObject) -> Option<String> {
+    ["UF", "F"].into_iter().find_map(|key| {
+        let value = dictionary(specification, key)?;
+        value.as_string().ok().map(str::to_owned)
+    })
+}
+
+fn contents(specification: &PdfObject) -> Option<Vec<u8>> {
+    let streams = dictionary(specification, "EF")?;
+    ["UF", "F"]
+        .into_iter()
+        .find_map(|key| dictionary(&streams, key)?.read_stream().ok())
+}
+
+fn dictionary(object: &PdfObject, key: &str) -> Option<PdfObject> {
+    object.get_dict(key).ok().flatten()
+}
+
+/// The XMP packet that says which version a bundle holds.
+pub fn xmp(provenance: &Provenance) -> String {
+    format!(
+        "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+         <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+         <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+         <rdf:Description rdf:about=\"\" xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\" \
+         xmlns:press=\"{NAMESPACE}\">\n\
+         <xmp:CreatorTool>Press</xmp:CreatorTool>\n\
+         <xmp:CreateDate>{}</xmp:CreateDate>\n\
+         <press:Revision>{}</press:Revision>\n\
+         <press:Engine>{}</press:Engine>\n\
+         <press:Document>{}</press:Document>\n\
+         </rdf:Description>\n\
+         </rdf:RDF>\n\
+         </x:xmpmeta>\n\
+         <?xpacket end=\"w\"?>",
+        timestamp(provenance.built_at),
+        escape(&provenance.revision),
+        provenance.engine.as_token(),
+        escape(&provenance.document),
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+/// Unix seconds as an ISO 8601 date and time in UTC, which is what XMP takes.
+fn timestamp(seconds: i64) -> String {
+    let (days, rest) = (seconds.div_euclid(86_400), seconds.rem_euclid(86_400));
+    // Days since 1970 to a civil date, after Howard Hinnant's `civil_from_days`:
+    // counted in 400-year eras from 0000-03-01, so leap days fall at the end of
+    // each year.
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let of_era = shifted.rem_euclid(146_097);
+    let year_of_era = (of_era - of_era / 1_460 + of_era / 36_524 - of_era / 146_096) / 365;
+    let day_of_year = of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rest / 3_600,
+        rest % 3_600 / 60,
+        rest % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_times_are_written_as_xmp_dates() {
+        assert_eq!(timestamp(0), "1970-01-01T00:00:00Z");
+        // A leap day, and the turn of a century that is one.
+        assert_eq!(timestamp(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(timestamp(1_793_000_000), "2026-10-26T07:33:20Z");
+    }
+
+    #[test]
+    fn the_packet_names_the_version_and_escapes_what_it_quotes() {
+        let packet = xmp(&Provenance {
+            revision: "3f9a".into(),
+            engine: Engine::LuaLatex,
+            built_at: 0,
+            document: "drafts/R&D <v2>.tex".into(),
+        });
+        assert!(packet.starts_with("<?xpacket begin="));
+        assert!(packet.contains("<press:Revision>3f9a</press:Revision>"));
+        assert!(packet.contains("<press:Engine>lualatex</press:Engine>"));
+        assert!(packet.contains("<press:Document>drafts/R&amp;D &lt;v2&gt;.tex</press:Document>"));
+        assert!(packet.contains("<xmp:CreateDate>1970-01-01T00:00:00Z</xmp:CreateDate>"));
+
+        // And read back the way an unpacked bundle finds its document.
+        assert_eq!(
+            property(&packet, "Document").as_deref(),
+            Some("drafts/R&D <v2>.tex")
+        );
+        assert_eq!(property(&packet, "Engine").as_deref(), Some("lualatex"));
+        assert_eq!(property(&packet, "Title"), None);
+    }
+
+    #[test]
+    fn attachments_only_unpack_inside_their_folder() {
+        assert_eq!(inside("main.tex"), Some(PathBuf::from("main.tex")));
+        assert_eq!(
+            inside("./figures\\plot.pdf"),
+            Some(PathBuf::from("figures/plot.pdf"))
+        );
+        assert_eq!(inside("../.ssh/config"), None);
+        assert_eq!(inside("chapters/../../escape.tex"), None);
+        assert_eq!(inside("/etc/passwd"), None);
+        assert_eq!(inside("C:\\Windows\\win.ini"), None);
+        assert_eq!(inside(""), None);
+    }
+}
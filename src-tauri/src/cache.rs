@@ -0,0 +1,652 @@
+//! Drawn pages kept for drawing again.
+//!
+//! Scrolling back to a page, or flicking between two pages of a comparison,
+//! used to send it through MuPDF every time — tens of milliseconds for a page
+//! that was on screen a moment ago. So what the render pool draws is kept here,
+//! newest last, until a memory budget says otherwise; and, when the disk tier
+//! has been turned on, written under the cache root as well, where a page
+//! thrown out of memory or drawn in an earlier session is a file read away
+//! rather than a render. On disk a page is packed — runs of one colour written
+//! once, which is most of a page of text — so that a tier meant to save work
+//! does not cost a dozen megabytes of writing for every page drawn.
+//!
+//! A page is kept against the [`Stamp`] of the file it was drawn from. The
+//! working tree's PDF is republished under a fresh name, but a loose PDF is
+//! rebuilt under its own by whatever owns it, and a page from before that is a
+//! page of a different document. A stamp that no longer matches is a miss, and
+//! on disk the stamp is part of the file's name, so a stale file is never even
+//! looked at: it ages out with the rest.
+
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::Repository,
+    error::AppResult,
+    model::CacheSettings,
+    render::{RenderedPage, Stamp},
+    snapshot,
+};
+
+/// The memory budget in megabytes, as set.
+pub const MEMORY_SETTING: &str = "render.cache_megabytes";
+/// Whether drawn pages are also kept on disk: `"1"` or `"0"`.
+pub const DISK_SETTING: &str = "render.disk_cache";
+
+/// About twenty pages at ordinary zoom on a retina display.
+pub const DEFAULT_MEGABYTES: usize = 256;
+
+/// How much the disk tier may hold before the pages least recently used are
+/// deleted. Packed, a page of text is a few hundred kilobytes at retina zoom.
+const MOST_DISK_BYTES: u64 = 1 << 30;
+
+/// How much the thumbnails kept on disk may come to. At a few kilobytes each,
+/// the strips of a good many documents.
+const MOST_THUMBNAIL_BYTES: u64 = 64 << 20;
+
+/// The file extension of a packed page.
+const PACKED: &str = "packed";
+
+/// What a drawn page depends on besides the file's contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageKey {
+    pub path: PathBuf,
+    pub page: usize,
+    /// How large it is drawn: the scale's bits, since the viewer asks for the
+    /// same scale to the same four decimals every time, or a thumbnail's width.
+    pub scale: u32,
+    pub invert: bool,
+}
+
+impl PageKey {
+    pub fn new(path: &Path, page: usize, scale: f32, invert: bool) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            page,
+            scale: scale.to_bits(),
+            invert,
+        }
+    }
+
+    pub fn thumbnail(path: &Path, page: usize, width: u32, invert: bool) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            page,
+            scale: width,
+            invert,
+        }
+    }
+
+    /// The file this page is kept in on disk, which names the stamp as well,
+    /// or `None` for a file whose stamp cannot be told apart from a rewrite.
+    fn file_name(&self, stamp: &Stamp, extension: &str) -> Option<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(self.path.to_string_lossy().as_bytes());
+        hasher.update([0]);
+        hasher.update(stamp.token()?.as_bytes());
+        hasher.update((self.page as u64).to_le_bytes());
+        hasher.update(self.scale.to_le_bytes());
+        hasher.update([u8::from(self.invert)]);
+        Some(format!("{}.{extension}", snapshot::hex(&hasher.finalize())))
+    }
+}
+
+/// The cache's settings as stored, or the defaults: the disk tier is off
+/// unless turned on.
+pub fn settings(repository: &Repository) -> AppResult<CacheSettings> {
+    Ok(CacheSettings {
+        memory_megabytes: repository
+            .setting(MEMORY_SETTING)?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(DEFAULT_MEGABYTES),
+        disk: repository
+            .setting(DISK_SETTING)?
+            .is_some_and(|value| value == "1"),
+    })
+}
+
+pub fn store(repository: &Repository, settings: &CacheSettings) -> AppResult<()> {
+    repository.set_setting(MEMORY_SETTING, &settings.memory_megabytes.to_string())?;
+    repository.set_setting(DISK_SETTING, if settings.disk { "1" } else { "0" })
+}
+
+struct Kept {
+    key: PageKey,
+    stamp: Stamp,
+    /// Shared with whoever it was last handed to, so a hit costs no copy.
+    page: Arc<RenderedPage>,
+}
+
+struct Inner {
+    /// Least recently used first.
+    kept: VecDeque<Kept>,
+    bytes: usize,
+    budget: usize,
+    /// Where pages are written, when the disk tier is on.
+    disk: Option<PathBuf>,
+}
+
+impl Inner {
+    fn shrink_to(&mut self, budget: usize) {
+        while self.bytes > budget {
+            let Some(oldest) = self.kept.pop_front() else {
+                break;
+            };
+            self.bytes -= oldest.page.byte_size();
+        }
+    }
+
+    fn remove(&mut self, key: &PageKey) {
+        if let Some(position) = self.kept.iter().position(|kept| &kept.key == key)
+            && let Some(removed) = self.kept.remove(position)
+        {
+            self.bytes -= removed.page.byte_size();
+        }
+    }
+}
+
+/// Drawn pages in memory, least recently used thrown out first, with an
+/// optional copy of each on disk.
+pub struct PageCache {
+    inner: Mutex<Inner>,
+    /// Where the disk tier lives when it is turned on.
+    root: Option<PathBuf>,
+    pruning: Arc<Pruning>,
+}
+
+impl PageCache {
+    /// A cache held in memory alone.
+    pub fn in_memory(megabytes: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                kept: VecDeque::new(),
+                bytes: 0,
+                budget: megabytes.saturating_mul(1 << 20),
+                disk: None,
+            }),
+            root: None,
+            pruning: Arc::new(Pruning::new(MOST_DISK_BYTES)),
+        }
+    }
+
+    /// A cache that can also keep pages under `root`, as `settings` say.
+    pub fn new(root: PathBuf, settings: CacheSettings) -> Self {
+        let cache = Self {
+            root: Some(root),
+            ..Self::in_memory(settings.memory_megabytes)
+        };
+        cache.configure(settings);
+        cache
+    }
+
+    /// Takes new settings at once: a smaller budget throws pages out now, and a
+    /// disk tier turned off is emptied rather than left to go stale.
+    pub fn configure(&self, settings: CacheSettings) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        inner.budget = settings.memory_megabytes.saturating_mul(1 << 20);
+        let budget = inner.budget;
+        inner.shrink_to(budget);
+        let disk = self.root.clone().filter(|_| settings.disk);
+        if let Some(directory) = &disk {
+            let _ = std::fs::create_dir_all(directory);
+        } else if let Some(root) = &self.root {
+            let _ = std::fs::remove_dir_all(root);
+        }
+        inner.disk = disk;
+    }
+
+    /// Whether the page is kept as drawn from the file as it is now, in memory
+    /// or on disk. On disk the stamp is in the name, as `get` reads it, so
+    /// asking is a look at the directory and not a read.
+    pub fn holds(&self, key: &PageKey, stamp: Stamp) -> bool {
+        let Ok(inner) = self.inner.lock() else {
+            return false;
+        };
+        if inner
+            .kept
+            .iter()
+            .any(|kept| &kept.key == key && kept.stamp == stamp)
+        {
+            return true;
+        }
+        inner
+            .disk
+            .as_ref()
+            .zip(key.file_name(&stamp, PACKED))
+            .is_some_and(|(directory, name)| directory.join(name).is_file())
+    }
+
+    /// The page as last drawn from the file as it is now, from memory or else
+    /// from disk.
+    pub async fn get(&self, key: &PageKey, stamp: Stamp) -> Option<Arc<RenderedPage>> {
+        let disk = {
+            let mut inner = self.inner.lock().ok()?;
+            if let Some(position) = inner.kept.iter().position(|kept| &kept.key == key) {
+                let kept = inner.kept.remove(position)?;
+                if kept.stamp == stamp {
+                    let page = Arc::clone(&kept.page);
+                    inner.kept.push_back(kept);
+                    return Some(page);
+                }
+                // Drawn from a file that has since been rewritten.
+                inner.bytes -= kept.page.byte_size();
+            }
+            inner.disk.clone()?
+        };
+
+        let file = disk.join(key.file_name(&stamp, PACKED)?);
+        let page = tauri::async_runtime::spawn_blocking(move || {
+            let page = unpack(&std::fs::read(&file).ok()?)?;
+            // Read is use: what was read last is deleted last.
+            if let Ok(opened) = std::fs::File::options().write(true).open(&file) {
+                let _ = opened.set_modified(SystemTime::now());
+            }
+            Some(Arc::new(page))
+        })
+        .await
+        .ok()
+        .flatten()?;
+        self.keep(key.clone(), stamp, Arc::clone(&page));
+        Some(page)
+    }
+
+    /// Keeps a freshly drawn page, and writes it to disk behind the caller.
+    pub fn put(&self, key: PageKey, stamp: Stamp, page: &Arc<RenderedPage>) {
+        let disk = self
+            .inner
+            .lock()
+            .ok()
+            .and_then(|inner| inner.disk.clone())
+            .zip(key.file_name(&stamp, PACKED));
+        self.keep(key, stamp, Arc::clone(page));
+        if let Some((directory, name)) = disk {
+            let page = Arc::clone(page);
+            let pruning = Arc::clone(&self.pruning);
+            tauri::async_runtime::spawn_blocking(move || {
+                let packed = pack(&page);
+                write(&directory, &name, &packed);
+                pruning.wrote(&directory, packed.len() as u64);
+            });
+        }
+    }
+
+    fn keep(&self, key: PageKey, stamp: Stamp, page: Arc<RenderedPage>) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        inner.remove(&key);
+        // A page larger than the whole budget would only throw everything
+        // else out and then itself.
+        if page.byte_size() > inner.budget {
+            return;
+        }
+        inner.bytes += page.byte_size();
+        inner.kept.push_back(Kept { key, stamp, page });
+        let budget = inner.budget;
+        inner.shrink_to(budget);
+    }
+}
+
+/// Thumbnails, kept on disk alone. They are a few kilobytes of PNG and quick
+/// to read back, and a strip of every page is worth keeping between sessions;
+/// without a directory, nothing is kept.
+pub struct ThumbnailCache {
+    root: Option<PathBuf>,
+    pruning: Arc<Pruning>,
+}
+
+impl Default for ThumbnailCache {
+    fn default() -> Self {
+        Self {
+            root: None,
+            pruning: Arc::new(Pruning::new(MOST_THUMBNAIL_BYTES)),
+        }
+    }
+}
+
+impl ThumbnailCache {
+    pub fn new(root: PathBuf) -> Self {
+        let _ = std::fs::create_dir_all(&root);
+        Self {
+            root: Some(root),
+            ..Self::default()
+        }
+    }
+
+    pub async fn get(&self, key: &PageKey, stamp: Stamp) -> Option<Vec<u8>> {
+        let file = self.root.as_ref()?.join(key.file_name(&stamp, "png")?);
+        tauri::async_runtime::spawn_blocking(move || {
+            let png = std::fs::read(&file).ok()?;
+            if let Ok(opened) = std::fs::File::options().write(true).open(&file) {
+                let _ = opened.set_modified(SystemTime::now());
+            }
+            Some(png)
+        })
+        .await
+        .ok()
+        .flatten()
+    }
+
+    /// Writes a thumbnail behind the caller.
+    pub fn put(&self, key: PageKey, stamp: Stamp, png: Vec<u8>) {
+        let (Some(directory), Some(name)) = (self.root.clone(), key.file_name(&stamp, "png"))
+        else {
+            return;
+        };
+        let pruning = Arc::clone(&self.pruning);
+        tauri::async_runtime::spawn_blocking(move || {
+            write(&directory, &name, &png);
+            pruning.wrote(&directory, png.len() as u64);
+        });
+    }
+}
+
+/// Written beside its final name and renamed into it, so a reader never finds
+/// half a file.
+fn write(directory: &Path, name: &str, bytes: &[u8]) {
+    let staging = directory.join(format!("{name}.partial"));
+    if std::fs::write(&staging, bytes).is_ok() {
+        let _ = std::fs::rename(&staging, directory.join(name));
+    } else {
+        let _ = std::fs::remove_file(&staging);
+    }
+}
+
+/// Bytes written into a directory since it was last measured. Listing and
+/// measuring a directory of a thousand files is what keeping it to a budget
+/// costs, so that is done once a sixteenth of the budget has been written
+/// since the last time, and on the first write of a session, which is when
+/// what an earlier one left is found out.
+struct Pruning {
+    budget: u64,
+    written: AtomicU64,
+}
+
+impl Pruning {
+    fn new(budget: u64) -> Self {
+        Self {
+            budget,
+            written: AtomicU64::new(budget / 16),
+        }
+    }
+
+    /// Counts a file written into `directory`, and brings the directory back
+    /// to budget when enough has been.
+    fn wrote(&self, directory: &Path, bytes: u64) {
+        let written = self.written.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if written >= self.budget / 16 {
+            self.written.store(0, Ordering::Relaxed);
+            prune(directory, self.budget);
+        }
+    }
+}
+
+/// Deletes the pages least recently used until what is left fits `budget`.
+fn prune(directory: &Path, budget: u64) {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return;
+    };
+    let mut files = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            metadata
+                .is_file()
+                .then(|| (metadata.modified().ok(), metadata.len(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    let mut total = files.iter().map(|(_, len, _)| len).sum::<u64>();
+    files.sort();
+    for (_, len, path) in files {
+        if total <= budget {
+            break;
+        }
+        if std::fs::remove_file(&path).is_ok() {
+            total -= len;
+        }
+    }
+}
+
+/// The most pixels one control byte repeats, and the most it passes through.
+const LONGEST_RUN: usize = 129;
+const LONGEST_LITERAL: usize = 128;
+
+/// A page as it is written to disk: its dimensions, as in front of a
+/// [`framed`](RenderedPage::framed) page, then its pixels in runs. A control
+/// byte below 128 is followed by that many pixels and one more, as they are;
+/// one from 128 up by a single pixel, to be repeated that many times less 126.
+fn pack(page: &RenderedPage) -> Vec<u8> {
+    let samples = page.samples();
+    let pixel = |index: usize| &samples[index * 4..index * 4 + 4];
+    let count = samples.len() / 4;
+    let mut packed = Vec::with_capacity(RenderedPage::PREFIX + samples.len() / 8);
+    packed.extend_from_slice(&page.width.to_le_bytes());
+    packed.extend_from_slice(&page.height.to_le_bytes());
+    let literal = |packed: &mut Vec<u8>, from: usize, to: usize| {
+        if to > from {
+            packed.push((to - from - 1) as u8);
+            packed.extend_from_slice(&samples[from * 4..to * 4]);
+        }
+    };
+
+    // `from` is the first pixel not yet written, `at` the one being looked at.
+    let mut from = 0;
+    let mut at = 0;
+    while at < count {
+        let mut run = 1;
+        while at + run < count && run < LONGEST_RUN && pixel(at + run) == pixel(at) {
+            run += 1;
+        }
+        if run > 1 {
+            literal(&mut packed, from, at);
+            packed.push((run + 126) as u8);
+            packed.extend_from_slice(pixel(at));
+            at += run;
+            from = at;
+        } else {
+            at += 1;
+            if at - from == LONGEST_LITERAL {
+                literal(&mut packed, from, at);
+                from = at;
+            }
+        }
+    }
+    literal(&mut packed, from, count);
+    packed
+}
+
+/// A page as [`pack`] wrote it, or `None` for a file that is not one.
+fn unpack(packed: &[u8]) -> Option<RenderedPage> {
+    let header = packed.get(..RenderedPage::PREFIX)?;
+    let width = u32::from_le_bytes(header[..4].try_into().ok()?);
+    let height = u32::from_le_bytes(header[4..].try_into().ok()?);
+    let length = (width as usize)
+        .checked_mul(height as usize)?
+        .checked_mul(4)?
+        .checked_add(RenderedPage::PREFIX)?;
+    // Never more than the runs could come to, whatever the header claims.
+    let mut buffer = Vec::with_capacity(length.min(packed.len().saturating_mul(LONGEST_RUN)));
+    buffer.extend_from_slice(header);
+    let mut rest = &packed[RenderedPage::PREFIX..];
+    while let Some((&control, tail)) = rest.split_first() {
+        if control < 128 {
+            let bytes = (usize::from(control) + 1) * 4;
+            buffer.extend_from_slice(tail.get(..bytes)?);
+            rest = &tail[bytes..];
+        } else {
+            let pixel = tail.get(..4)?;
+            for _ in 0..usize::from(control) - 126 {
+                buffer.extend_from_slice(pixel);
+            }
+            rest = &tail[4..];
+        }
+        if buffer.len() > length {
+            return None;
+        }
+    }
+    RenderedPage::from_framed(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(width: u32, height: u32, shade: u8) -> RenderedPage {
+        let mut buffer = vec![0; RenderedPage::PREFIX];
+        buffer[..4].copy_from_slice(&width.to_le_bytes());
+        buffer[4..8].copy_from_slice(&height.to_le_bytes());
+        buffer.resize(RenderedPage::PREFIX + (width * height * 4) as usize, shade);
+        RenderedPage::from_framed(buffer).unwrap()
+    }
+
+    fn stamped(directory: &Path, contents: &str) -> (PathBuf, Stamp) {
+        let pdf = directory.join("document.pdf");
+        std::fs::write(&pdf, contents).unwrap();
+        let stamp = Stamp::of(&pdf).unwrap();
+        (pdf, stamp)
+    }
+
+    #[tokio::test]
+    async fn pages_are_kept_to_the_budget_least_recently_used_first() {
+        let directory = tempfile::tempdir().unwrap();
+        let (pdf, stamp) = stamped(directory.path(), "%PDF-1.7");
+        // Room for three pages of a quarter of a megabyte each, and not four.
+        let cache = PageCache::in_memory(1);
+        let size = 256 * 256;
+        for index in 0..3 {
+            cache.put(
+                PageKey::new(&pdf, index, 2.0, false),
+                stamp,
+                &Arc::new(page(256, 256, index as u8)),
+            );
+        }
+        assert_eq!(cache.inner.lock().unwrap().bytes, 3 * (size * 4 + 8));
+        assert!(
+            cache
+                .get(&PageKey::new(&pdf, 0, 2.0, false), stamp)
+                .await
+                .is_some()
+        );
+
+        for index in 3..5 {
+            cache.put(
+                PageKey::new(&pdf, index, 2.0, false),
+                stamp,
+                &Arc::new(page(256, 256, index as u8)),
+            );
+        }
+        assert!(
+            cache
+                .get(&PageKey::new(&pdf, 0, 2.0, false), stamp)
+                .await
+                .is_some(),
+            "used recently, so kept"
+        );
+        assert!(
+            cache
+                .get(&PageKey::new(&pdf, 1, 2.0, false), stamp)
+                .await
+                .is_none()
+        );
+        assert!(
+            cache
+                .get(&PageKey::new(&pdf, 4, 2.0, true), stamp)
+                .await
+                .is_none(),
+            "drawn the other way"
+        );
+    }
+
+    #[tokio::test]
+    async fn a_rewritten_file_misses_and_a_disk_copy_outlives_memory() {
+        let directory = tempfile::tempdir().unwrap();
+        let (pdf, stamp) = stamped(directory.path(), "%PDF-1.7");
+        let root = directory.path().join("pages");
+        let settings = CacheSettings {
+            memory_megabytes: 1,
+            disk: true,
+        };
+        let cache = PageCache::new(root.clone(), settings);
+        let key = PageKey::new(&pdf, 0, 1.5, false);
+        let drawn = page(16, 8, 200);
+        write(
+            &root,
+            &key.file_name(&stamp, PACKED).unwrap(),
+            &pack(&drawn),
+        );
+
+        // Nothing in memory: from disk, and in memory after that.
+        assert!(cache.holds(&key, stamp));
+        let read = cache.get(&key, stamp).await.unwrap();
+        assert_eq!((read.width, read.height), (16, 8));
+        assert_eq!(read.samples(), drawn.samples());
+        assert_eq!(cache.inner.lock().unwrap().kept.len(), 1);
+
+        let (_, rewritten) = stamped(directory.path(), "%PDF-1.7 and more");
+        assert!(!cache.holds(&key, rewritten));
+        assert!(cache.get(&key, rewritten).await.is_none());
+        assert!(cache.inner.lock().unwrap().kept.is_empty());
+
+        cache.configure(CacheSettings {
+            disk: false,
+            ..settings
+        });
+        assert!(!root.exists(), "turned off, the disk tier is emptied");
+    }
+
+    #[test]
+    fn a_packed_page_unpacks_to_the_same_pixels() {
+        let blank = page(300, 40, 255);
+        let packed = pack(&blank);
+        assert!(packed.len() < 1000, "a blank page is a handful of runs");
+        let unpacked = unpack(&packed).unwrap();
+        assert_eq!((unpacked.width, unpacked.height), (300, 40));
+        assert_eq!(unpacked.samples(), blank.samples());
+
+        // A stretch with no runs, longer than one control byte passes
+        // through, and then short runs.
+        let mut buffer = vec![0; RenderedPage::PREFIX];
+        buffer[..4].copy_from_slice(&700_u32.to_le_bytes());
+        buffer[4..8].copy_from_slice(&1_u32.to_le_bytes());
+        for index in 0..700_u32 {
+            let shade = if index < 300 { index } else { index / 7 * 7 };
+            buffer.extend_from_slice(&[shade as u8, (shade >> 8) as u8, 0, 255]);
+        }
+        let mixed = RenderedPage::from_framed(buffer).unwrap();
+        let unpacked = unpack(&pack(&mixed)).unwrap();
+        assert_eq!(unpacked.samples(), mixed.samples());
+
+        assert!(unpack(&packed[..packed.len() - 1]).is_none());
+        assert!(unpack(b"short").is_none());
+    }
+
+    #[test]
+    fn the_disk_tier_keeps_what_was_read_last() {
+        let directory = tempfile::tempdir().unwrap();
+        for (name, age) in [("old", 300), ("middle", 200), ("new", 100)] {
+            let path = directory.path().join(name);
+            std::fs::write(&path, [0; 10]).unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() - std::time::Duration::from_secs(age))
+                .unwrap();
+        }
+        prune(directory.path(), 20);
+        assert!(!directory.path().join("old").exists());
+        assert!(directory.path().join("middle").exists());
+        assert!(directory.path().join("new").exists());
+    }
+}
@@ -0,0 +1,141 @@
+//! Which pages a rebuild changed.
+//!
+//! A save that reflows page 37 while the reader is on page 2 is otherwise
+//! invisible: the new PDF replaces the old one and every page looks as settled
+//! as the next. So each working-tree publication is fingerprinted a page at a
+//! time, and the fingerprints are kept beside the artifact. The next build is
+//! compared against them, and the pages that differ follow the build's update
+//! in another, for the viewer to mark. The page is published first; this
+//! runs behind it, beside the advice.
+//!
+//! A fingerprint is a hash of what the page draws: its content streams, and
+//! which images and forms they place. Read rather than drawn — a page of text
+//! is a few kilobytes of operators, where drawing it is megabytes of pixels —
+//! and because TeX writes the same page the same way twice, a page whose
+//! operators match is a page that looks the same. A placed object is known by
+//! its reference and its length, not its contents: a photograph is megabytes
+//! too, and it is one object for every page that shows it. The previous PDF is
+//! gone by the time the next one is published, which is why the fingerprints
+//! are stored rather than worked out again from both files.
+
+use std::path::Path;
+
+use mupdf::pdf::PdfObject;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    database::Repository,
+    error::AppResult,
+    impose::{dictionary, inherited},
+    render, snapshot,
+};
+
+/// Forms inside forms: a figure that includes a figure. Past this the
+/// nesting is a cycle, which a malformed PDF can contain.
+const MAX_DEPTH: usize = 8;
+
+/// One hash per page, in page order.
+pub fn fingerprints(pdf: &Path) -> AppResult<Vec<String>> {
+    let document = render::open_pdf(pdf)?;
+    let failed = |error| render::mupdf_error("could not read a page", error);
+    let mut hashes = Vec::new();
+    for index in 0..render::page_count(&document)? {
+        let page = document.find_page(index as i32).map_err(failed)?;
+        let mut hasher = Sha256::new();
+        if let Some(contents) = dictionary(&page, "Contents") {
+            // One stream, or an array of them to be read as one.
+            if contents.is_array().map_err(failed)? {
+                for part in 0..contents.len().map_err(failed)? {
+                    if let Some(part) = contents.get_array(part as i32).map_err(failed)? {
+                        hasher.update(part.read_stream().map_err(failed)?);
+                    }
+                }
+            } else {
+                hasher.update(contents.read_stream().map_err(failed)?);
+            }
+        }
+        // The operators name an image and a figure; what is in them is here.
+        if let Some(resources) = inherited(&page, "Resources") {
+            placed(&resources, 0, &mut hasher);
+        }
+        hashes.push(snapshot::hex(&hasher.finalize()));
+    }
+    Ok(hashes)
+}
+
+/// Feeds in which images and forms a resource dictionary holds, and those of
+/// the forms inside them. Each goes in as its object number and the length of
+/// its stream, which a different figure under the same number will not share.
+/// Fonts are left out: a reflow changes the operators that use them, and a
+/// font swapped under unchanged text is not a save's doing.
+fn placed(resources: &PdfObject, depth: usize, hasher: &mut Sha256) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    let Some(entries) = dictionary(resources, "XObject") else {
+        return;
+    };
+    for index in 0..entries.len().unwrap_or_default() {
+        let Some(object) = entries.get_dict_val(index as i32).ok().flatten() else {
+            continue;
+        };
+        let number = object.as_indirect().unwrap_or_default();
+        let length = dictionary(&object, "Length")
+            .and_then(|length| length.as_int().ok())
+            .unwrap_or_default();
+        hasher.update(number.to_le_bytes());
+        hasher.update(length.to_le_bytes());
+        if let Some(inner) = dictionary(&object, "Resources") {
+            placed(&inner, depth + 1, hasher);
+        }
+    }
+}
+
+/// The 0-based pages of `current` that differ from the same page of
+/// `previous`, including every page the document has gained. Pages it has
+/// lost are not listed: there is nothing left to mark.
+pub fn changed(previous: &[String], current: &[String]) -> Vec<u32> {
+    current
+        .iter()
+        .enumerate()
+        .filter(|(index, hash)| previous.get(*index) != Some(*hash))
+        .map(|(index, _)| index as u32)
+        .collect()
+}
+
+/// Fingerprints a fresh publication of an artifact and stores the result,
+/// handing back what changed since the last one. `None` for a first build,
+/// with nothing to compare against, or when the PDF could not be read: the
+/// build has succeeded either way, and saying nothing changed would be wrong.
+pub fn record(repository: &Repository, artifact_id: i64, pdf: &Path) -> Option<Vec<u32>> {
+    let current = match fingerprints(pdf) {
+        Ok(current) => current,
+        Err(error) => {
+            eprintln!("Press could not fingerprint {}: {error}", pdf.display());
+            return None;
+        }
+    };
+    let previous = repository.page_fingerprints(artifact_id).ok().flatten();
+    let _ = repository.set_page_fingerprints(artifact_id, &current);
+    previous.map(|previous| changed(&previous, &current))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hashes(pages: &[&str]) -> Vec<String> {
+        pages.iter().map(|page| page.to_string()).collect()
+    }
+
+    #[test]
+    fn reflowed_and_added_pages_are_changed_and_lost_ones_are_not() {
+        let previous = hashes(&["a", "b", "c", "d"]);
+        assert_eq!(changed(&previous, &previous), Vec::<u32>::new());
+        assert_eq!(
+            changed(&previous, &hashes(&["a", "x", "c", "d", "e"])),
+            vec![1, 4]
+        );
+        assert_eq!(changed(&previous, &hashes(&["a", "b"])), Vec::<u32>::new());
+    }
+}
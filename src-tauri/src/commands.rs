@@ -1,23 +1,26 @@
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
     sync::Arc,
 };
 
-use tauri::{AppHandle, Manager, State};
+use tauri::{AppHandle, Manager, State, ipc::Channel};
 
 use crate::{
-    AppState, appearance,
-    database::{NewProject, ProjectEdit},
+    AppState, appearance, build, cache,
+    database::{NewProject, ProjectEdit, Repository, StoredArtifact},
     documents, editor,
     error::{AppError, AppResult},
-    frontmatter,
+    extract, frontmatter, history, labels, library, lint,
     model::{
-        DocumentKind, EditorCommand, Engine, OpenRequest, PageSize, Preset, PresetList,
-        PresetPreview, ProjectSummary, SearchHit, SnapshotOutcome, SourceRef, TextBox,
-        VersionSummary,
+        Annotation, AnnotationPlace, CacheSettings, DocumentKind, EditorCommand, Engine,
+        HistorySearch, ImageFormat, LabelMatch, LibraryHit, NewAnnotation, OpenRequest,
+        OpenedProject, OutlineEntry, PageConstraints, PagePoint, PageRegion, PageSize,
+        PreflightReport, Preset, PresetList, PresetPreview, PreviousBuild, PrintOptions, Project,
+        ProjectSummary, ReadingPosition, RenderMetrics, SearchMatch, SearchOptions,
+        SnapshotOutcome, SourceRef, TextBox, TextSelection, VersionSummary, ViewState,
     },
-    preview,
+    preview, search,
 };
 
 /// Every database and filesystem call goes through here, off the async runtime.
@@ -70,36 +73,128 @@ pub async fn add_project(
     let repository = Arc::clone(&state.repository);
     let requested = engine(engine_override)?;
     let id = blocking(move || {
-        let document = documents::validate(&PathBuf::from(&document_path))?;
-        let canonical = document
-            .to_str()
-            .ok_or_else(|| AppError::InvalidInput("that path is not valid UTF-8".into()))?;
-        if crate::toolchain::resolve_executable("latexmk").is_none() {
-            return Err(AppError::ToolUnavailable(
-                "latexmk was not found. Install a TeX distribution or add latexmk to PATH.".into(),
-            ));
-        }
-        // Markdown reaches latexmk through pandoc, so both have to be present.
-        if DocumentKind::of(&document) == DocumentKind::Markdown
-            && crate::toolchain::resolve_executable("pandoc").is_none()
+        register_document(&repository, &PathBuf::from(&document_path), name, requested)
+    })
+    .await?;
+
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.project_summary(id)).await
+}
+
+/// Puts a document in the library, or finds it already there, once the tools
+/// it needs are known to be installed. Returns the project's id.
+fn register_document(
+    repository: &Repository,
+    document_path: &Path,
+    name: Option<String>,
+    requested: Option<Engine>,
+) -> AppResult<i64> {
+    let document = documents::validate(document_path)?;
+    let canonical = document
+        .to_str()
+        .ok_or_else(|| AppError::InvalidInput("that path is not valid UTF-8".into()))?;
+    if crate::toolchain::resolve_executable("latexmk").is_none() {
+        return Err(AppError::ToolUnavailable(
+            "latexmk was not found. Install a TeX distribution or add latexmk to PATH.".into(),
+        ));
+    }
+    // Markdown reaches latexmk through pandoc, so both have to be present.
+    if DocumentKind::of(&document) == DocumentKind::Markdown
+        && crate::toolchain::resolve_executable("pandoc").is_none()
+    {
+        return Err(AppError::ToolUnavailable(
+            "pandoc was not found. Install pandoc to compile markdown.".into(),
+        ));
+    }
+    // A markdown document says nothing about the TeX engine, and pandoc's
+    // output compiles with any of them, so pdflatex is the honest default.
+    let engine = requested
+        .or_else(|| documents::detect_engine(&document))
+        .unwrap_or(Engine::PdfLatex);
+    let suggested = name.unwrap_or_else(|| canonical.to_owned());
+    Ok(repository
+        .upsert_project(NewProject {
+            name: &suggested,
+            document_path: canonical,
+            engine,
+        })?
+        .id)
+}
+
+/// Unpacks the sources a PDF carries into `folder` and adds the document among
+/// them to the library: see `bundle`.
+///
+/// Nothing in the folder is overwritten. A file already there under a name an
+/// attachment needs stops the unpacking before anything is written, so it is
+/// never left half done. The document is the one a bundle from Press names;
+/// for anyone else's PDF, the attachments are asked the way a file opened by
+/// hand would be, top-level files first, and the first that belongs to a
+/// document Press compiles decides which.
+#[tauri::command]
+pub async fn import_embedded(
+    document_id: i64,
+    folder: String,
+    state: State<'_, AppState>,
+) -> AppResult<ProjectSummary> {
+    let pdf = state
+        .viewing
+        .path(document_id)
+        .ok_or_else(|| AppError::NotFound("that PDF is no longer open".into()))?;
+    let repository = Arc::clone(&state.repository);
+    let id = blocking(move || {
+        let document = crate::render::open_pdf(&pdf)?;
+        let mut attachments = crate::bundle::attachments(&document);
+        let recorded = crate::bundle::metadata(&document);
+        drop(document);
+        attachments.sort_by_key(|(path, _)| (path.components().count(), path.clone()));
+
+        let folder = PathBuf::from(folder);
+        if let Some((taken, _)) = attachments
+            .iter()
+            .find(|(path, _)| folder.join(path).exists())
         {
-            return Err(AppError::ToolUnavailable(
-                "pandoc was not found. Install pandoc to compile markdown.".into(),
-            ));
+            return Err(AppError::InvalidInput(format!(
+                "{} already has {} in it. Choose an empty folder.",
+                folder.display(),
+                taken.display()
+            )));
         }
-        // A markdown document says nothing about the TeX engine, and pandoc's
-        // output compiles with any of them, so pdflatex is the honest default.
-        let engine = requested
-            .or_else(|| documents::detect_engine(&document))
-            .unwrap_or(Engine::PdfLatex);
-        let suggested = name.unwrap_or_else(|| canonical.to_owned());
-        Ok(repository
-            .upsert_project(NewProject {
-                name: &suggested,
-                document_path: canonical,
-                engine,
-            })?
-            .id)
+        for (path, contents) in &attachments {
+            let target = folder.join(path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&target, contents)?;
+        }
+
+        let property = |name| {
+            recorded
+                .as_deref()
+                .and_then(|xmp| crate::bundle::property(xmp, name))
+        };
+        // The XMP is as much the sender's as the attachments are. The name it
+        // gives counts only when it stays inside the folder and is one of the
+        // files just written; otherwise the attachments are asked as for any
+        // other PDF.
+        let named = property("Document")
+            .and_then(|name| crate::bundle::inside(&name))
+            .filter(|name| attachments.iter().any(|(path, _)| path == name))
+            .map(|name| folder.join(name));
+        let root = named
+            .into_iter()
+            .chain(attachments.iter().map(|(path, _)| folder.join(path)))
+            .filter(|path| {
+                path.is_file() && (documents::is_latex(path) || documents::is_markdown(path))
+            })
+            .find_map(|path| documents::document_root(&path).ok().flatten())
+            .ok_or_else(|| {
+                AppError::InvalidInput(format!(
+                    "The files are in {}, but none of them is a document Press compiles.",
+                    folder.display()
+                ))
+            })?;
+        let engine = property("Engine").and_then(|token| token.parse().ok());
+        register_document(&repository, &root, None, engine)
     })
     .await?;
 
@@ -107,13 +202,15 @@ pub async fn add_project(
     blocking(move || repository.project_summary(id)).await
 }
 
-/// Makes a project current: watches it and builds its working tree.
+/// Makes a project current: watches it and builds its working tree. Comes
+/// back with where the reader was in each version, so the viewer can put them
+/// there again.
 #[tauri::command]
 pub async fn open_project(
     project_id: i64,
     app: AppHandle,
     state: State<'_, AppState>,
-) -> AppResult<ProjectSummary> {
+) -> AppResult<OpenedProject> {
     let repository = Arc::clone(&state.repository);
     let project = blocking(move || {
         let project = repository.get_project(project_id)?;
@@ -124,7 +221,34 @@ pub async fn open_project(
 
     Arc::clone(&state.builds).open(app, project).await?;
     let repository = Arc::clone(&state.repository);
-    blocking(move || repository.project_summary(project_id)).await
+    blocking(move || {
+        Ok(OpenedProject {
+            summary: repository.project_summary(project_id)?,
+            views: repository.view_states(project_id)?,
+        })
+    })
+    .await
+}
+
+/// Jumps kept per version. Going back further than this is reading the
+/// document again.
+const MOST_JUMPS: usize = 100;
+
+/// Saves where the reader is in a version, for `open_project` to hand back.
+/// Called as the viewer goes; the newest jumps are kept when there are too
+/// many.
+#[tauri::command]
+pub async fn save_view_state(
+    project_id: i64,
+    source_ref: Option<String>,
+    mut view: ViewState,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let target = self::source_ref(source_ref)?;
+    let excess = view.jumps.len().saturating_sub(MOST_JUMPS);
+    view.jumps.drain(..excess);
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.save_view_state(project_id, &target, &view)).await
 }
 
 /// Shows a PDF without keeping it.
@@ -351,6 +475,30 @@ pub async fn delete_snapshot(snapshot_id: i64, state: State<'_, AppState>) -> Ap
     Ok(())
 }
 
+/// The working tree's earlier builds still kept, the most recent first.
+#[tauri::command]
+pub async fn previous_builds(
+    project_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<PreviousBuild>> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.previous_builds(project_id)).await
+}
+
+/// Shows one of the working tree's earlier builds. It is opened the way a
+/// loose PDF is: it is a finished file, with no build or version of its own
+/// behind it any more.
+#[tauri::command]
+pub async fn open_previous_build(
+    previous_id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<crate::model::LooseDocument> {
+    let repository = Arc::clone(&state.repository);
+    let viewing = Arc::clone(&state.viewing);
+    blocking(move || viewing.open(&app, &repository.previous_build_path(previous_id)?)).await
+}
+
 /// Every page's size in PDF points, so the viewer can lay out a whole document
 /// before drawing any of it.
 #[tauri::command]
@@ -424,6 +572,60 @@ pub async fn page_links(
         .collect())
 }
 
+/// The headings of a PDF, for jumping between them. A loose PDF's `.aux` is
+/// looked for beside it, which is where latexmk leaves one by default.
+#[tauri::command]
+pub async fn document_outline(
+    artifact_id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<OutlineEntry>> {
+    let path = crate::protocol::resolve(&app, artifact_id).await?;
+    let contents = tokio::fs::read_to_string(path.with_extension("aux"))
+        .await
+        .ok();
+    state.renderer.outline(path, contents).await
+}
+
+/// The labels in a build that best match `query`, and the pages they are on.
+/// Nothing when the PDF came with no `.aux`.
+#[tauri::command]
+pub async fn find_label(
+    artifact_id: i64,
+    query: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<LabelMatch>> {
+    let path = crate::protocol::resolve(&app, artifact_id).await?;
+    let Ok(aux) = tokio::fs::read_to_string(path.with_extension("aux")).await else {
+        return Ok(Vec::new());
+    };
+    let labels = labels::parse(&aux);
+    let found = labels::lookup(&labels, &query);
+    let anchors = found
+        .iter()
+        .map(|(label, _)| label.anchor.clone().unwrap_or_default())
+        .collect();
+    let pages = state.renderer.destinations(path, anchors).await?;
+    Ok(found
+        .into_iter()
+        .zip(pages)
+        .map(|((label, _), page)| labels::to_match(label, page))
+        .collect())
+}
+
+/// Fonts, images, version and metadata of a built PDF, with what IEEE PDF
+/// eXpress and ACM TAPS would reject in them.
+#[tauri::command]
+pub async fn preflight(
+    artifact_id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<PreflightReport> {
+    let path = crate::protocol::resolve(&app, artifact_id).await?;
+    state.renderer.preflight(path).await
+}
+
 /// Opens a link that leads out of the document, in whatever the system uses for
 /// it.
 ///
@@ -483,46 +685,345 @@ pub async fn peek_source(
     // is given one that says exactly that and nothing more.
     let loose = state.viewing.path(artifact_id);
     blocking(move || {
-        let (project, stored) = match loose {
-            Some(path) => (
-                crate::peek::beside(&path),
-                crate::peek::loose(artifact_id, path),
-            ),
-            None => {
-                let stored = repository.artifact(artifact_id)?;
-                (repository.get_project(stored.summary.project_id)?, stored)
+        let (project, stored) = peek_target(&repository, artifact_id, loose)?;
+        crate::peek::resolve(&project, &stored, &repository, &objects, page, x, y)
+    })
+    .await
+}
+
+/// The project and artifact SyncTeX answers are resolved against: the stored
+/// ones, or stand-ins for a PDF that is only being shown.
+fn peek_target(
+    repository: &Repository,
+    artifact_id: i64,
+    loose: Option<PathBuf>,
+) -> AppResult<(Project, StoredArtifact)> {
+    Ok(match loose {
+        Some(path) => (
+            crate::peek::beside(&path),
+            crate::peek::loose(artifact_id, path),
+        ),
+        None => {
+            let stored = repository.artifact(artifact_id)?;
+            (repository.get_project(stored.summary.project_id)?, stored)
+        }
+    })
+}
+
+/// The passage at a point — the top of the viewport, usually — as a line of
+/// the source: see `position`. `page` counts from zero. Taken before the PDF
+/// changes, since a rebuild replaces the file the point was on; `None` when
+/// SyncTeX has nothing to say, and the viewer keeps its page number.
+#[tauri::command]
+pub async fn reading_position(
+    artifact_id: i64,
+    page: u32,
+    x: f64,
+    y: f64,
+    state: State<'_, AppState>,
+) -> AppResult<Option<ReadingPosition>> {
+    let repository = Arc::clone(&state.repository);
+    let objects = state.objects_root.clone();
+    let loose = state.viewing.path(artifact_id);
+    blocking(move || {
+        let (project, stored) = peek_target(&repository, artifact_id, loose)?;
+        let anchor = match crate::peek::anchor_at(
+            &project,
+            &stored,
+            &repository,
+            &objects,
+            page + 1,
+            x,
+            y,
+        ) {
+            Err(AppError::ToolUnavailable(_)) => None,
+            anchor => anchor?,
+        };
+        Ok(anchor.map(|anchor| ReadingPosition {
+            source_ref: stored.summary.source_ref.clone(),
+            anchor,
+        }))
+    })
+    .await
+}
+
+/// Lines past a position's own that are tried before giving up. A position on
+/// a blank line or a comment typesets as nothing; the passage starts at the
+/// next line that does.
+const MOST_LINES_AHEAD: usize = 8;
+
+/// Where a reading position taken on one artifact is in another: the same
+/// build after a rebuild, or another version of the project. Between versions
+/// the line is carried across a diff of its file; within the working tree,
+/// which may have been edited since, by what the line said.
+#[tauri::command]
+pub async fn carry_position(
+    artifact_id: i64,
+    position: ReadingPosition,
+    state: State<'_, AppState>,
+) -> AppResult<Option<PagePoint>> {
+    let repository = Arc::clone(&state.repository);
+    let objects = state.objects_root.clone();
+    let loose = state.viewing.path(artifact_id);
+    blocking(move || {
+        let (project, stored) = peek_target(&repository, artifact_id, loose)?;
+        let target = &stored.summary.source_ref;
+        let file = &position.anchor.file;
+        let read = |source_ref| {
+            crate::peek::read_source(&project, &repository, &objects, source_ref, file)
+        };
+        let line = match target {
+            SourceRef::Snapshot(_) if *target == position.source_ref => Some(position.anchor.line),
+            SourceRef::Worktree if *target == position.source_ref => {
+                let current = read(target)?.unwrap_or_default();
+                crate::annotations::relocate(&position.anchor, &current)
+                    .or(Some(position.anchor.line))
             }
+            _ => match (read(&position.source_ref)?, read(target)?) {
+                (Some(old), Some(new)) => {
+                    crate::position::map_line(&old, &new, position.anchor.line.saturating_sub(1))
+                        .map(|line| line + 1)
+                }
+                _ => None,
+            },
         };
-        crate::peek::resolve(&project, &stored, &repository, &objects, page, x, y)
+        let Some(line) = line else {
+            return Ok(None);
+        };
+
+        for line in line..line + MOST_LINES_AHEAD {
+            match crate::peek::place_of(&project, &stored, file, line) {
+                Ok(Some((page, x, y))) => {
+                    return Ok(Some(PagePoint {
+                        page: page - 1,
+                        x: x as f32,
+                        y: y as f32,
+                    }));
+                }
+                Ok(None) => {}
+                Err(AppError::ToolUnavailable(_)) => return Ok(None),
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(None)
     })
     .await
 }
 
-/// Full-document search, run by MuPDF itself.
+/// Most runs of mathematics one copy looks up. Each is a SyncTeX call; past
+/// this, a selection is a chapter and the rest keep their glyphs.
+const MOST_MATH_LOOKUPS: usize = 200;
+/// Most pages one copy reads. Past this the selection is the document, and the
+/// text export is the way to have it.
+const MOST_SELECTION_PAGES: usize = 100;
+
+/// A selection as text to copy, in reading order: see `extract`.
+///
+/// With `latex_math`, runs of words that look like mathematics are looked up
+/// in the source and replaced by what was written there, delimiters and all.
+/// A run the source cannot account for keeps the glyphs it was set in, and so
+/// does everything when SyncTeX is not installed: copying still works, it is
+/// only less clever.
+#[tauri::command]
+pub async fn selection_text(
+    artifact_id: i64,
+    selection: TextSelection,
+    latex_math: bool,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let path = crate::protocol::resolve(&app, artifact_id).await?;
+    let (first, last) = match selection {
+        TextSelection::Region { page, .. } => (page, page),
+        TextSelection::Words {
+            from_page, to_page, ..
+        } => (from_page, to_page),
+    };
+    if last < first {
+        return Err(AppError::InvalidInput(
+            "a selection cannot end before it starts".into(),
+        ));
+    }
+    if last - first >= MOST_SELECTION_PAGES {
+        return Err(AppError::InvalidInput(format!(
+            "a selection can cover at most {MOST_SELECTION_PAGES} pages; export the text to have \
+             all of it"
+        )));
+    }
+
+    let mut pages = Vec::new();
+    for page in first..=last {
+        let words = state.renderer.words(path.clone(), page).await?;
+        let chosen = match selection {
+            TextSelection::Region {
+                x,
+                y,
+                width,
+                height,
+                ..
+            } => words
+                .into_iter()
+                .filter(|word| {
+                    let (across, down) = (word.x + word.width / 2.0, word.y + word.height / 2.0);
+                    (x..=x + width).contains(&across) && (y..=y + height).contains(&down)
+                })
+                .collect(),
+            TextSelection::Words {
+                from_page,
+                from_word,
+                to_page,
+                to_word,
+            } => {
+                let start = if page == from_page { from_word } else { 0 };
+                let end = if page == to_page {
+                    to_word.saturating_add(1).min(words.len())
+                } else {
+                    words.len()
+                };
+                words.get(start..end).map(<[_]>::to_vec).unwrap_or_default()
+            }
+        };
+        pages.push(chosen);
+    }
+
+    let mut replaced = vec![Vec::new(); pages.len()];
+    if latex_math {
+        let runs = pages
+            .iter()
+            .enumerate()
+            .flat_map(|(index, words)| {
+                extract::math_runs(words)
+                    .into_iter()
+                    .map(move |run| (index, run))
+            })
+            .take(MOST_MATH_LOOKUPS)
+            .collect::<Vec<_>>();
+        // SyncTeX pages count from one, and a run is asked about by the middle
+        // of its first word.
+        let points = runs
+            .iter()
+            .map(|(index, run)| {
+                let word = &pages[*index][run.start];
+                (
+                    (first + index + 1) as u32,
+                    f64::from(word.x + word.width / 2.0),
+                    f64::from(word.y + word.height / 2.0),
+                )
+            })
+            .collect::<Vec<_>>();
+        let repository = Arc::clone(&state.repository);
+        let objects = state.objects_root.clone();
+        let loose = state.viewing.path(artifact_id);
+        let sources = blocking(move || {
+            let (project, stored) = peek_target(&repository, artifact_id, loose)?;
+            let mut sources = Vec::with_capacity(points.len());
+            for (page, x, y) in points {
+                match crate::peek::math_at(&project, &stored, &repository, &objects, page, x, y) {
+                    Ok(source) => sources.push(source),
+                    Err(AppError::ToolUnavailable(_)) => break,
+                    Err(error) => return Err(error),
+                }
+            }
+            Ok(sources)
+        })
+        .await?;
+        for ((index, run), text) in runs.into_iter().zip(extract::assign(&sources)) {
+            if let Some(text) = text {
+                replaced[index].push((run, text));
+            }
+        }
+    }
+
+    Ok(extract::join_pages(pages.iter().zip(&replaced).map(
+        |(words, replaced)| (words.as_slice(), extract::join(words, replaced)),
+    )))
+}
+
+/// Full-document search over the extracted text: see `search`.
+///
+/// Matches are sent down `results` a page at a time, as they are found, so the
+/// first page's show while the rest of a long document is still being read.
+/// Resolves with how many there were once every page has been searched.
 #[tauri::command]
 pub async fn search_document(
     artifact_id: i64,
     needle: String,
+    options: SearchOptions,
+    results: Channel<Vec<SearchMatch>>,
     app: AppHandle,
     state: State<'_, AppState>,
-) -> AppResult<Vec<SearchHit>> {
-    if needle.trim().is_empty() {
-        return Ok(Vec::new());
-    }
+) -> AppResult<usize> {
+    let Some(query) = search::Query::new(&needle, options)? else {
+        return Ok(0);
+    };
     let path = crate::protocol::resolve(&app, artifact_id).await?;
-    Ok(state
-        .renderer
-        .search(path, needle)
-        .await?
-        .into_iter()
-        .map(|hit| SearchHit {
-            page: hit.page,
-            x: hit.x,
-            y: hit.y,
-            width: hit.width,
-            height: hit.height,
-        })
-        .collect())
+    let pages = state.renderer.geometry(path.clone()).await?.len();
+    let mut total = 0;
+    for page in 0..pages {
+        let found = state
+            .renderer
+            .search(path.clone(), page, query.clone())
+            .await?;
+        if found.is_empty() {
+            continue;
+        }
+        total += found.len();
+        // The window that asked has gone: nobody is left to read the rest.
+        if results.send(found).is_err() {
+            break;
+        }
+    }
+    Ok(total)
+}
+
+/// Which of a project's stored versions contain `needle`, with where it first
+/// appeared and where it was last seen.
+#[tauri::command]
+pub async fn search_history(
+    project_id: i64,
+    needle: String,
+    options: SearchOptions,
+    state: State<'_, AppState>,
+) -> AppResult<HistorySearch> {
+    let Some(query) = search::Query::new(&needle, options)? else {
+        return Ok(HistorySearch::default());
+    };
+    let repository = Arc::clone(&state.repository);
+    let objects = state.objects_root.clone();
+    blocking(move || {
+        let mut manifests = HashMap::new();
+        let mut versions = Vec::new();
+        // Newest first from the database; a history reads forwards.
+        for snapshot in repository.list_snapshots(project_id)?.into_iter().rev() {
+            if !manifests.contains_key(&snapshot.revision) {
+                let manifest = repository.snapshot_manifest(project_id, &snapshot.revision)?;
+                manifests.insert(snapshot.revision.clone(), manifest);
+            }
+            let manifest = manifests[&snapshot.revision].clone();
+            versions.push((snapshot, manifest));
+        }
+        Ok(history::search(versions, &objects, &query))
+    })
+    .await
+}
+
+/// Enough results to find a paper by, few enough to read down.
+const LIBRARY_HITS: usize = 50;
+
+/// Pages anywhere in the library that contain every word of `query`, best
+/// first. Snapshots are searched too when `snapshots` is set; otherwise each
+/// document is searched as its working tree last built.
+#[tauri::command]
+pub async fn search_library(
+    query: String,
+    snapshots: bool,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<LibraryHit>> {
+    let Some(query) = library::query(&query) else {
+        return Ok(Vec::new());
+    };
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.search_library(&query, snapshots, LIBRARY_HITS)).await
 }
 
 /// Copies a built PDF into the user's Downloads folder, and says where it went.
@@ -539,41 +1040,382 @@ pub async fn export_artifact(
     let source = crate::protocol::resolve(&app, artifact_id).await?;
 
     let repository = Arc::clone(&state.repository);
-    let stem = blocking(move || {
+    let stem = blocking(move || export_stem(&repository, artifact_id)).await?;
+
+    let destination = unused_path(&downloads(&app)?, &stem, "pdf");
+    tokio::fs::copy(&source, &destination)
+        .await
+        .map_err(unwritable)?;
+    Ok(destination.to_string_lossy().into_owned())
+}
+
+/// The whole of an artifact's text, written beside its PDF exports as
+/// `<name>.txt`, one page to a form feed as `pdftotext` writes it.
+///
+/// Unlike the PDF, a PDF Press only shows can be exported this way: the text is
+/// newly made, so nothing leaves storage that was not already on the reader's
+/// disk. Mathematics keeps its glyphs; looking up every run in a document is
+/// more than an export should wait for.
+#[tauri::command]
+pub async fn export_text(
+    artifact_id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let source = crate::protocol::resolve(&app, artifact_id).await?;
+    let stem = any_export_stem(&state, artifact_id).await?;
+
+    let pages = state.renderer.geometry(source.clone()).await?.len();
+    let mut text = Vec::with_capacity(pages);
+    for page in 0..pages {
+        let words = state.renderer.words(source.clone(), page).await?;
+        text.push(extract::join(&words, &[]));
+    }
+
+    let destination = unused_path(&downloads(&app)?, &stem, "txt");
+    tokio::fs::write(&destination, text.join("\n\u{c}") + "\n")
+        .await
+        .map_err(unwritable)?;
+    Ok(destination.to_string_lossy().into_owned())
+}
+
+/// A stored version's PDF with its source inside it, written to Downloads:
+/// see `bundle`. Only a version has a source Press can vouch for — the working
+/// tree may have moved on since it was built — so anything else is refused with
+/// a way forward.
+#[tauri::command]
+pub async fn export_with_source(
+    artifact_id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let source = crate::protocol::resolve(&app, artifact_id).await?;
+    let unversioned = || {
+        AppError::InvalidInput(
+            "Only a stored version carries its source. Store a version, then export that.".into(),
+        )
+    };
+    if state.viewing.path(artifact_id).is_some() {
+        return Err(unversioned());
+    }
+
+    let repository = Arc::clone(&state.repository);
+    let objects = state.objects_root.clone();
+    let (stem, files, provenance) = blocking(move || {
         let stored = repository.artifact(artifact_id)?;
+        let SourceRef::Snapshot(revision) = &stored.summary.source_ref else {
+            return Err(unversioned());
+        };
         let project = repository.get_project(stored.summary.project_id)?;
-        // The working tree is the document itself; a snapshot carries its title
-        // so several exported versions do not collide.
-        let title = match &stored.summary.source_ref {
-            SourceRef::Worktree => None,
-            reference => repository
-                .list_versions(project.id)?
-                .into_iter()
-                .find(|version| &version.source_ref == reference)
-                .map(|version| version.title),
+        let files = repository
+            .snapshot_manifest(project.id, revision)?
+            .into_iter()
+            .map(|file| {
+                let contents = std::fs::read(crate::snapshot::object_path(&objects, &file.object))?;
+                Ok((file.path, contents))
+            })
+            .collect::<AppResult<Vec<_>>>()?;
+        let provenance = crate::bundle::Provenance {
+            revision: revision.clone(),
+            engine: stored.summary.engine,
+            built_at: stored.summary.built_at,
+            document: project.file_name(),
         };
-        Ok(match title.as_deref().map(file_safe) {
-            Some(title) => format!("{}-{title}", project.job_name()),
-            None => project.job_name(),
-        })
+        Ok((export_stem(&repository, artifact_id)?, files, provenance))
     })
     .await?;
+    let pdf = state.renderer.embed(source, files, provenance).await?;
+
+    let destination = unused_path(&downloads(&app)?, &format!("{stem}-source"), "pdf");
+    tokio::fs::write(&destination, pdf)
+        .await
+        .map_err(unwritable)?;
+    Ok(destination.to_string_lossy().into_owned())
+}
 
-    let downloads = app.path().download_dir().map_err(|error| {
+/// The document set up for printing drafts — a range of its pages, two or
+/// four to a sheet, or a booklet to fold — written to Downloads as a new PDF.
+/// The artifact itself is only read.
+#[tauri::command]
+pub async fn export_print(
+    artifact_id: i64,
+    options: PrintOptions,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let source = crate::protocol::resolve(&app, artifact_id).await?;
+    let stem = any_export_stem(&state, artifact_id).await?;
+    let layout = options.layout;
+    let pdf = state.renderer.impose(source, options).await?;
+
+    let destination = unused_path(
+        &downloads(&app)?,
+        &format!("{stem}-{}", layout.slug()),
+        "pdf",
+    );
+    tokio::fs::write(&destination, pdf)
+        .await
+        .map_err(unwritable)?;
+    Ok(destination.to_string_lossy().into_owned())
+}
+
+/// A reader's highlight or comment on the PDF being shown, stored against the
+/// version it was built from and anchored to the source line under it: see
+/// `annotations`. Without SyncTeX the annotation is kept all the same, only
+/// with nothing to follow the text by.
+#[tauri::command]
+pub async fn add_annotation(
+    artifact_id: i64,
+    annotation: NewAnnotation,
+    state: State<'_, AppState>,
+) -> AppResult<Annotation> {
+    if state.viewing.path(artifact_id).is_some() {
+        return Err(AppError::InvalidInput(
+            "Annotations are kept with a project's versions. Add this PDF as a project to annotate it.".into(),
+        ));
+    }
+    let repository = Arc::clone(&state.repository);
+    let objects = state.objects_root.clone();
+    blocking(move || {
+        let stored = repository.artifact(artifact_id)?;
+        let project = repository.get_project(stored.summary.project_id)?;
+        let anchor = match annotation.rects.first() {
+            Some(rect) => {
+                match crate::peek::anchor_at(
+                    &project,
+                    &stored,
+                    &repository,
+                    &objects,
+                    annotation.page + 1,
+                    f64::from(rect.x + rect.width / 2.0),
+                    f64::from(rect.y + rect.height / 2.0),
+                ) {
+                    Ok(anchor) => anchor,
+                    Err(AppError::ToolUnavailable(_)) => None,
+                    Err(error) => return Err(error),
+                }
+            }
+            None => None,
+        };
+        repository.add_annotation(
+            project.id,
+            &stored.summary.source_ref,
+            &annotation,
+            anchor.as_ref(),
+        )
+    })
+    .await
+}
+
+/// A project's annotations, on one version or, without a source reference, on
+/// every version it has.
+#[tauri::command]
+pub async fn list_annotations(
+    project_id: i64,
+    source_ref: Option<String>,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<Annotation>> {
+    let source_ref = source_ref
+        .map(|token| token.parse::<SourceRef>())
+        .transpose()?;
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.annotations(project_id, source_ref.as_ref())).await
+}
+
+/// Rewrites an annotation's comment. Its place is what it was made on, and
+/// stays.
+#[tauri::command]
+pub async fn update_annotation(
+    annotation_id: i64,
+    body: String,
+    state: State<'_, AppState>,
+) -> AppResult<Annotation> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.update_annotation(annotation_id, &body)).await
+}
+
+#[tauri::command]
+pub async fn delete_annotation(annotation_id: i64, state: State<'_, AppState>) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.delete_annotation(annotation_id)).await
+}
+
+/// Where the annotations made on one version fall in the working tree: the
+/// line each anchor has become, and where the working tree's latest build
+/// typeset it. An annotation whose line is gone, or that had no anchor, comes
+/// back with neither.
+#[tauri::command]
+pub async fn relocate_annotations(
+    project_id: i64,
+    source_ref: String,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<AnnotationPlace>> {
+    let source_ref = source_ref.parse::<SourceRef>()?;
+    let repository = Arc::clone(&state.repository);
+    blocking(move || {
+        let project = repository.get_project(project_id)?;
+        let annotations = repository.annotations(project_id, Some(&source_ref))?;
+        let built = repository.artifact_for(project_id, &SourceRef::Worktree, project.engine)?;
+        let mut sources = HashMap::new();
+        let mut synctex = built.is_some();
+
+        let mut places = Vec::with_capacity(annotations.len());
+        for annotation in annotations {
+            let mut place = AnnotationPlace {
+                annotation_id: annotation.id,
+                line: None,
+                page: None,
+                x: 0.0,
+                y: 0.0,
+            };
+            if let Some(anchor) = &annotation.anchor {
+                let current = sources.entry(anchor.file.clone()).or_insert_with(|| {
+                    std::fs::read_to_string(project.directory().join(&anchor.file))
+                        .unwrap_or_default()
+                });
+                place.line = crate::annotations::relocate(anchor, current);
+            }
+            if synctex
+                && let (Some(line), Some(stored), Some(anchor)) =
+                    (place.line, &built, &annotation.anchor)
+            {
+                match crate::peek::place_of(&project, stored, &anchor.file, line) {
+                    Ok(Some((page, x, y))) => {
+                        place.page = Some(page - 1);
+                        place.x = x as f32;
+                        place.y = y as f32;
+                    }
+                    Ok(None) => {}
+                    // The lines are still worth having; the pages will have
+                    // to wait for a TeX distribution.
+                    Err(AppError::ToolUnavailable(_)) => synctex = false,
+                    Err(error) => return Err(error),
+                }
+            }
+            places.push(place);
+        }
+        Ok(places)
+    })
+    .await
+}
+
+/// The PDF with a version's annotations written into it as ordinary PDF
+/// annotations, for a reader that is not Press. Written to Downloads; the
+/// artifact itself is only read.
+#[tauri::command]
+pub async fn export_annotated(
+    artifact_id: i64,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let source = crate::protocol::resolve(&app, artifact_id).await?;
+    if state.viewing.path(artifact_id).is_some() {
+        return Err(AppError::InvalidInput(
+            "A PDF Press only shows has no annotations to export.".into(),
+        ));
+    }
+    let repository = Arc::clone(&state.repository);
+    let (stem, annotations) = blocking(move || {
+        let stored = repository.artifact(artifact_id)?;
+        let annotations =
+            repository.annotations(stored.summary.project_id, Some(&stored.summary.source_ref))?;
+        Ok((export_stem(&repository, artifact_id)?, annotations))
+    })
+    .await?;
+    let pdf = state.renderer.annotate(source, annotations).await?;
+
+    let destination = unused_path(&downloads(&app)?, &format!("{stem}-annotated"), "pdf");
+    tokio::fs::write(&destination, pdf)
+        .await
+        .map_err(unwritable)?;
+    Ok(destination.to_string_lossy().into_owned())
+}
+
+/// The resolution an image is exported at when none is asked for: what a
+/// printer would use, and sharp on any projector.
+const EXPORT_DPI: f32 = 300.0;
+
+/// One page, or a region of it, written to Downloads as a PNG or an SVG for a
+/// slide. `page` counts from zero, as it does for `page_words`. Named after the
+/// document and the page, so a figure and the equation beside it do not
+/// overwrite each other.
+#[tauri::command]
+pub async fn export_image(
+    artifact_id: i64,
+    page: usize,
+    format: ImageFormat,
+    dpi: Option<f32>,
+    region: Option<PageRegion>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<String> {
+    let source = crate::protocol::resolve(&app, artifact_id).await?;
+    let stem = any_export_stem(&state, artifact_id).await?;
+    let image = state
+        .renderer
+        .image(source, page, format, dpi.unwrap_or(EXPORT_DPI), region)
+        .await?;
+
+    let destination = unused_path(
+        &downloads(&app)?,
+        &format!("{stem}-page{}", page + 1),
+        format.extension(),
+    );
+    tokio::fs::write(&destination, image)
+        .await
+        .map_err(unwritable)?;
+    Ok(destination.to_string_lossy().into_owned())
+}
+
+/// What an export of any artifact is called. A PDF Press only shows has no
+/// project to name it, so it keeps its own file name.
+async fn any_export_stem(state: &AppState, artifact_id: i64) -> AppResult<String> {
+    match state.viewing.path(artifact_id) {
+        Some(path) => Ok(path
+            .file_stem()
+            .map(|stem| file_safe(&stem.to_string_lossy()))
+            .unwrap_or_else(|| "document".into())),
+        None => {
+            let repository = Arc::clone(&state.repository);
+            blocking(move || export_stem(&repository, artifact_id)).await
+        }
+    }
+}
+
+/// What an export of a stored artifact is called: the document's name, and
+/// the version's title when it is not the working tree.
+fn export_stem(repository: &Repository, artifact_id: i64) -> AppResult<String> {
+    let stored = repository.artifact(artifact_id)?;
+    let project = repository.get_project(stored.summary.project_id)?;
+    // The working tree is the document itself; a snapshot carries its title
+    // so several exported versions do not collide.
+    let title = match &stored.summary.source_ref {
+        SourceRef::Worktree => None,
+        reference => repository
+            .list_versions(project.id)?
+            .into_iter()
+            .find(|version| &version.source_ref == reference)
+            .map(|version| version.title),
+    };
+    Ok(match title.as_deref().map(file_safe) {
+        Some(title) => format!("{}-{title}", project.job_name()),
+        None => project.job_name(),
+    })
+}
+
+fn downloads(app: &AppHandle) -> AppResult<PathBuf> {
+    app.path().download_dir().map_err(|error| {
         AppError::NotFound(format!(
             "Press could not find your Downloads folder: {error}"
         ))
-    })?;
-    let destination = unused_path(&downloads, &stem);
-    tokio::fs::copy(&source, &destination)
-        .await
-        .map_err(|error| {
-            AppError::Io(std::io::Error::new(
-                error.kind(),
-                format!("could not write to Downloads: {error}"),
-            ))
-        })?;
-    Ok(destination.to_string_lossy().into_owned())
+    })
+}
+
+fn unwritable(error: std::io::Error) -> AppError {
+    AppError::Io(std::io::Error::new(
+        error.kind(),
+        format!("could not write to Downloads: {error}"),
+    ))
 }
 
 /// A version title is free text, so it is reduced to something that survives
@@ -596,13 +1438,13 @@ fn file_safe(title: &str) -> String {
 }
 
 /// Never overwrites: exporting the same version twice leaves both files.
-fn unused_path(directory: &Path, stem: &str) -> PathBuf {
-    let first = directory.join(format!("{stem}.pdf"));
+fn unused_path(directory: &Path, stem: &str, extension: &str) -> PathBuf {
+    let first = directory.join(format!("{stem}.{extension}"));
     if !first.exists() {
         return first;
     }
     (2..)
-        .map(|suffix| directory.join(format!("{stem}-{suffix}.pdf")))
+        .map(|suffix| directory.join(format!("{stem}-{suffix}.{extension}")))
         .find(|candidate| !candidate.exists())
         .unwrap_or(first)
 }
@@ -622,6 +1464,135 @@ pub async fn get_build_log(
     }
 }
 
+/// The chktex warnings a project leaves out of its lint stage.
+#[tauri::command]
+pub async fn lint_ignores(project_id: i64, state: State<'_, AppState>) -> AppResult<Vec<u32>> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.lint_ignores(project_id)).await
+}
+
+/// Replaces the project's ignore list. Takes effect from the next build, which
+/// is started here so the panel stops showing what was just silenced.
+#[tauri::command]
+pub async fn set_lint_ignores(
+    project_id: i64,
+    warnings: Vec<u32>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    let project = blocking(move || {
+        let project = repository.get_project(project_id)?;
+        repository.set_lint_ignores(project_id, &warnings)?;
+        Ok(project)
+    })
+    .await?;
+    Arc::clone(&state.builds)
+        .request(app, project, SourceRef::Worktree)
+        .await?;
+    Ok(())
+}
+
+/// Whether LaTeX builds run chktex at all.
+#[tauri::command]
+pub async fn lint_enabled(state: State<'_, AppState>) -> AppResult<bool> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || Ok(lint::enabled(&repository))).await
+}
+
+/// Turns the lint stage on or off, from the next build on.
+#[tauri::command]
+pub async fn set_lint_enabled(enabled: bool, state: State<'_, AppState>) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.set_setting(lint::SETTING, if enabled { "1" } else { "0" })).await
+}
+
+/// The words the spelling check accepts for this project.
+#[tauri::command]
+pub async fn project_dictionary(
+    project_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<Vec<String>> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.dictionary(project_id)).await
+}
+
+/// Teaches the spelling check a word. Like the ignore list, it applies from
+/// the next build, which is started here.
+#[tauri::command]
+pub async fn add_dictionary_word(
+    project_id: i64,
+    word: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    let project = blocking(move || {
+        let project = repository.get_project(project_id)?;
+        repository.add_to_dictionary(project_id, &word)?;
+        Ok(project)
+    })
+    .await?;
+    Arc::clone(&state.builds)
+        .request(app, project, SourceRef::Worktree)
+        .await?;
+    Ok(())
+}
+
+/// Takes a word back out, and rebuilds so it is flagged again where it is
+/// still misspelled.
+#[tauri::command]
+pub async fn remove_dictionary_word(
+    project_id: i64,
+    word: String,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    let project = blocking(move || {
+        let project = repository.get_project(project_id)?;
+        repository.remove_from_dictionary(project_id, &word)?;
+        Ok(project)
+    })
+    .await?;
+    Arc::clone(&state.builds)
+        .request(app, project, SourceRef::Worktree)
+        .await?;
+    Ok(())
+}
+
+/// The template limits this project is checked against after each build.
+#[tauri::command]
+pub async fn page_constraints(
+    project_id: i64,
+    state: State<'_, AppState>,
+) -> AppResult<PageConstraints> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || repository.page_constraints(project_id)).await
+}
+
+/// Replaces the limits and rebuilds, so the strip shows what they say about
+/// the document as it stands.
+#[tauri::command]
+pub async fn set_page_constraints(
+    project_id: i64,
+    constraints: PageConstraints,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    let project = blocking(move || {
+        let project = repository.get_project(project_id)?;
+        repository.set_page_constraints(project_id, &constraints)?;
+        Ok(project)
+    })
+    .await?;
+    Arc::clone(&state.builds)
+        .request(app, project, SourceRef::Worktree)
+        .await?;
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn launch_editor(project_id: i64, state: State<'_, AppState>) -> AppResult<String> {
     let repository = Arc::clone(&state.repository);
@@ -660,6 +1631,58 @@ pub async fn set_editor_command(command: String, state: State<'_, AppState>) ->
     blocking(move || repository.set_setting(editor::SETTING, command.trim())).await
 }
 
+/// How many earlier working-tree builds are kept.
+#[tauri::command]
+pub async fn kept_builds(state: State<'_, AppState>) -> AppResult<usize> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || Ok(build::kept_builds(&repository))).await
+}
+
+/// Stores how many earlier builds to keep. Lowered, the builds past the new
+/// number go now rather than at the next start.
+#[tauri::command]
+pub async fn set_kept_builds(count: usize, state: State<'_, AppState>) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    let dropped = blocking(move || {
+        repository.set_setting(build::KEPT_SETTING, &count.to_string())?;
+        repository.prune_previous_builds(count)
+    })
+    .await?;
+    for path in dropped {
+        crate::runner::discard_publication(&path).await;
+    }
+    Ok(())
+}
+
+/// How much of what has been drawn is kept, and whether on disk too.
+#[tauri::command]
+pub async fn render_cache(state: State<'_, AppState>) -> AppResult<CacheSettings> {
+    let repository = Arc::clone(&state.repository);
+    blocking(move || cache::settings(&repository)).await
+}
+
+/// Stores the cache's settings and puts them into effect straight away.
+#[tauri::command]
+pub async fn set_render_cache(
+    settings: CacheSettings,
+    state: State<'_, AppState>,
+) -> AppResult<()> {
+    let repository = Arc::clone(&state.repository);
+    let renderer = Arc::clone(&state.renderer);
+    blocking(move || {
+        cache::store(&repository, &settings)?;
+        renderer.cache().configure(settings);
+        Ok(())
+    })
+    .await
+}
+
+/// How the renderer is doing, for the diagnostics panel.
+#[tauri::command]
+pub async fn render_metrics(state: State<'_, AppState>) -> AppResult<RenderMetrics> {
+    Ok(state.renderer.metrics())
+}
+
 /// Which tile Press is wearing in the Dock.
 #[tauri::command]
 pub async fn icon_choice(state: State<'_, AppState>) -> AppResult<String> {
@@ -0,0 +1,474 @@
+//! Whether a build fits the template it was written for.
+//!
+//! A conference rejects a paper for running a page over, or for a margin
+//! nudged inwards to make it fit, without anyone reading it. Press checks the
+//! project's declared limits after every build so that the author hears about
+//! it from the diagnostics strip, on the day the page spilled over, rather
+//! than from the submission system on the day of the deadline.
+//!
+//! Everything is read from the PDF: the page sizes from [`render::geometry`],
+//! and the lines, their sizes and where they sit from MuPDF's text layer. The
+//! one thing a PDF does not say is where the references begin, which is what
+//! a page limit is usually counted up to. For that the table of contents LaTeX
+//! wrote is asked first, and the text layer — a line that reads "References"
+//! in a heading's size — when the bibliography never went into it.
+
+use std::{path::Path, sync::LazyLock};
+
+use regex::Regex;
+
+use crate::{
+    error::AppResult,
+    model::{Diagnostic, PageConstraints, Severity},
+    render::{self, PageGeometry, PageLayout},
+};
+
+/// The tag on every diagnostic a template check produced.
+pub const SOURCE: &str = "template";
+
+/// How far a margin may fall short before it counts. Less than this is a
+/// rounding error in whatever wrote the PDF, not a tampered layout.
+const MARGIN_TOLERANCE: f32 = 0.5;
+/// The same for paper, which some drivers write a point off.
+const PAPER_TOLERANCE: f32 = 1.0;
+const SIZE_TOLERANCE: f32 = 0.05;
+/// Pages named in one finding before the rest are summarised.
+const PAGES_NAMED: usize = 5;
+
+/// What a bibliography's heading says, in the classes that are common enough
+/// to meet.
+const REFERENCE_HEADINGS: &[&str] = &[
+    "references",
+    "bibliography",
+    "works cited",
+    "literature cited",
+];
+
+static CONTENTS_LINE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\\contentsline\s*\{(?:part|chapter|section)\}\{(?:\\numberline\s*\{[^}]*\})?\s*([^{}]+?)\s*\}\{(\d+)\}",
+    )
+    .unwrap()
+});
+
+/// Checks the PDF at `path` against `constraints`. `contents` is the `.aux`
+/// the build wrote, for the contents lines in it; empty when there is none.
+pub fn check(
+    path: &Path,
+    constraints: &PageConstraints,
+    contents: &str,
+) -> AppResult<Vec<Diagnostic>> {
+    let document = render::open(path)?;
+    let geometry = render::geometry(&document)?;
+    // Only the limits that need the text layer pay for it.
+    let layouts = if constraints.max_pages.is_some()
+        || constraints.min_font_size.is_some()
+        || constraints.margins.is_some()
+    {
+        (0..geometry.len())
+            .map(|index| render::layout(&document, index))
+            .collect::<AppResult<Vec<_>>>()?
+    } else {
+        Vec::new()
+    };
+    Ok(evaluate(
+        &geometry,
+        &layouts,
+        constraints,
+        references_in_contents(contents),
+    ))
+}
+
+/// Everything that breaks `constraints`. `references` is the page the
+/// contents say the references start on, 1-based, when they say.
+fn evaluate(
+    geometry: &[PageGeometry],
+    layouts: &[PageLayout],
+    constraints: &PageConstraints,
+    references: Option<usize>,
+) -> Vec<Diagnostic> {
+    let mut findings = Vec::new();
+
+    if let Some(limit) = constraints.max_pages {
+        let limit = limit as usize;
+        let counted = body_pages(layouts, references);
+        let pages = counted.unwrap_or(geometry.len());
+        if pages > limit {
+            findings.push(finding(match counted {
+                Some(_) => format!(
+                    "The text runs to {pages} pages before the references; the limit is {limit}"
+                ),
+                None => format!(
+                    "The document is {pages} pages long and the limit is {limit}. No \
+                     references were found, so every page was counted"
+                ),
+            }));
+        }
+    }
+
+    if let Some(paper) = constraints.paper {
+        let (width, height) = paper.dimensions();
+        let wrong = pages_where(geometry.iter().map(|page| {
+            (page.width - width).abs() > PAPER_TOLERANCE
+                || (page.height - height).abs() > PAPER_TOLERANCE
+        }));
+        if !wrong.is_empty() {
+            findings.push(finding(format!(
+                "{} not {}",
+                describe_pages(&wrong, "is", "are"),
+                paper.name()
+            )));
+        }
+    }
+
+    if let Some(minimum) = constraints.min_font_size {
+        let mut smallest = f32::INFINITY;
+        let small = pages_where(layouts.iter().map(|layout| {
+            let mut below = false;
+            for line in &layout.lines {
+                if line.size + SIZE_TOLERANCE < minimum && !is_folio(&line.text) {
+                    smallest = smallest.min(line.size);
+                    below = true;
+                }
+            }
+            below
+        }));
+        if !small.is_empty() {
+            findings.push(finding(format!(
+                "Text is set as small as {smallest:.1}pt, below the {minimum}pt minimum, on {}",
+                describe_pages(&small, "", "").trim_end()
+            )));
+        }
+    }
+
+    if let Some(margins) = constraints.margins {
+        let sides = [
+            ("top", margins.top),
+            ("bottom", margins.bottom),
+            ("left", margins.left),
+            ("right", margins.right),
+        ];
+        for (side, allowed) in sides {
+            let mut narrowest = f32::INFINITY;
+            let narrow =
+                pages_where(geometry.iter().zip(layouts).map(|(page, layout)| {
+                    match margin(page, layout, side) {
+                        Some(measured) if measured + MARGIN_TOLERANCE < allowed => {
+                            narrowest = narrowest.min(measured);
+                            true
+                        }
+                        _ => false,
+                    }
+                }));
+            if !narrow.is_empty() {
+                findings.push(finding(format!(
+                    "The {side} margin is {}, narrower than the {} allowed, on {}",
+                    length(narrowest.max(0.0)),
+                    length(allowed),
+                    describe_pages(&narrow, "", "").trim_end()
+                )));
+            }
+        }
+    }
+
+    findings
+}
+
+/// How many pages the text takes before its references, counting the page
+/// they start on when anything above the heading is on it. `None` when there
+/// are no references to count up to.
+fn body_pages(layouts: &[PageLayout], contents: Option<usize>) -> Option<usize> {
+    let page = contents
+        .filter(|page| (1..=layouts.len()).contains(page))
+        .or_else(|| references_in_text(layouts))?;
+    let Some(layout) = layouts.get(page - 1) else {
+        return Some(page - 1);
+    };
+    let heading = layout
+        .lines
+        .iter()
+        .find(|line| is_reference_heading(&line.text));
+    let shares_page = match heading {
+        Some(heading) => layout
+            .lines
+            .iter()
+            .any(|line| line.y + line.height <= heading.y && !is_folio(&line.text)),
+        // The contents named the page but the heading cannot be read on it:
+        // counting the page is the answer that does not let a paper through.
+        None => true,
+    };
+    Some(if shares_page { page } else { page - 1 })
+}
+
+/// The page the contents say the references start on. The last entry wins: a
+/// thesis can have a bibliography per chapter, and the one that ends the
+/// document is the one a limit stops at.
+fn references_in_contents(contents: &str) -> Option<usize> {
+    CONTENTS_LINE
+        .captures_iter(contents)
+        .filter(|capture| is_reference_heading(&capture[1]))
+        .filter_map(|capture| capture[2].parse().ok())
+        .last()
+}
+
+/// The last page with a line that reads as a bibliography's heading, set no
+/// smaller than the text around it.
+fn references_in_text(layouts: &[PageLayout]) -> Option<usize> {
+    let mut sizes = layouts
+        .iter()
+        .flat_map(|layout| layout.lines.iter().map(|line| line.size))
+        .collect::<Vec<_>>();
+    sizes.sort_by(f32::total_cmp);
+    let body = sizes.get(sizes.len() / 2).copied().unwrap_or_default();
+    layouts
+        .iter()
+        .enumerate()
+        .filter(|(_, layout)| {
+            layout
+                .lines
+                .iter()
+                .any(|line| line.size + SIZE_TOLERANCE >= body && is_reference_heading(&line.text))
+        })
+        .map(|(index, _)| index + 1)
+        .next_back()
+}
+
+/// "References", "7 References", "VII. REFERENCES".
+fn is_reference_heading(text: &str) -> bool {
+    let text = text.trim();
+    let title = match text.split_once(char::is_whitespace) {
+        Some((number, rest)) if is_numbering(number) => rest.trim(),
+        _ => text,
+    };
+    let title = title.trim_end_matches(['.', ':']).to_lowercase();
+    REFERENCE_HEADINGS.contains(&title.as_str())
+}
+
+fn is_numbering(word: &str) -> bool {
+    let word = word.trim_end_matches('.');
+    !word.is_empty()
+        && (word.chars().all(|character| character.is_ascii_digit())
+            || word.chars().all(|character| "IVXLC".contains(character)))
+}
+
+/// A line that is only a page number. Templates put those in the margin, and
+/// they are not text anyone is setting small to save space.
+fn is_folio(text: &str) -> bool {
+    let text = text.trim();
+    !text.is_empty()
+        && text.len() <= 8
+        && (text.chars().all(|character| character.is_ascii_digit())
+            || text
+                .to_ascii_lowercase()
+                .chars()
+                .all(|character| "ivxlc".contains(character)))
+}
+
+/// The blank space between one edge of the page and whatever is printed
+/// nearest to it. `None` for a page with nothing on it.
+fn margin(page: &PageGeometry, layout: &PageLayout, side: &str) -> Option<f32> {
+    let boxes = layout
+        .lines
+        .iter()
+        .filter(|line| !is_folio(&line.text))
+        .map(|line| (line.x, line.y, line.width, line.height))
+        .chain(
+            layout
+                .images
+                .iter()
+                .map(|image| (image.x, image.y, image.width, image.height)),
+        );
+    boxes
+        .map(|(x, y, width, height)| match side {
+            "top" => y,
+            "bottom" => page.height - (y + height),
+            "left" => x,
+            _ => page.width - (x + width),
+        })
+        .min_by(f32::total_cmp)
+}
+
+fn pages_where(pages: impl Iterator<Item = bool>) -> Vec<usize> {
+    pages
+        .enumerate()
+        .filter(|(_, broken)| *broken)
+        .map(|(index, _)| index + 1)
+        .collect()
+}
+
+/// "Page 3 is", "Pages 1, 2 and 4 are", "Pages 1, 2, 3, 4, 5 and 12 more are".
+fn describe_pages(pages: &[usize], one: &str, many: &str) -> String {
+    let named = pages
+        .iter()
+        .take(PAGES_NAMED)
+        .map(usize::to_string)
+        .collect::<Vec<_>>();
+    let list = match (named.as_slice(), pages.len().saturating_sub(PAGES_NAMED)) {
+        ([only], _) => return format!("page {only} {one}"),
+        (named, 0) => {
+            let (last, rest) = named.split_last().expect("more than one page");
+            format!("{} and {last}", rest.join(", "))
+        }
+        (named, more) => format!("{} and {more} more", named.join(", ")),
+    };
+    format!("pages {list} {many}")
+}
+
+/// Points as a venue states them: in inches, and in millimetres beside.
+fn length(points: f32) -> String {
+    format!("{:.2}in ({:.0}mm)", points / 72.0, points / 72.0 * 25.4)
+}
+
+fn finding(message: String) -> Diagnostic {
+    // A sentence that opens on a list of pages opens in lower case.
+    let message = match message.get(..1) {
+        Some(first) => first.to_uppercase() + &message[1..],
+        None => message,
+    };
+    Diagnostic {
+        file: None,
+        line: None,
+        column: None,
+        severity: Severity::Error,
+        message,
+        source: Some(SOURCE.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        model::{Margins, PaperSize},
+        render::TextLine,
+    };
+
+    const LETTER: PageGeometry = PageGeometry {
+        width: 612.0,
+        height: 792.0,
+    };
+
+    fn line(text: &str, x: f32, y: f32, size: f32) -> TextLine {
+        TextLine {
+            text: text.to_owned(),
+            x,
+            y,
+            width: 468.0,
+            height: size,
+            size,
+        }
+    }
+
+    /// A page of ten-point text inside one-inch margins, with its number
+    /// printed in the bottom margin.
+    fn page(number: usize) -> PageLayout {
+        PageLayout {
+            lines: vec![
+                line("Running text on the page.", 72.0, 72.0, 10.0),
+                line("More running text.", 72.0, 700.0, 10.0),
+                TextLine {
+                    width: 6.0,
+                    ..line(&number.to_string(), 303.0, 760.0, 10.0)
+                },
+            ],
+            images: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_references_are_found_in_the_contents_whatever_the_numbering() {
+        let toc = "\\contentsline {section}{\\numberline {1}Introduction}{1}{section.1}%\n\
+                   \\contentsline {section}{\\numberline {7}References}{9}{section.7}%\n";
+        assert_eq!(references_in_contents(toc), Some(9));
+
+        let aux = "\\@writefile{toc}{\\contentsline {chapter}{Bibliography}{187}{chapter*.40}}\n";
+        assert_eq!(references_in_contents(aux), Some(187));
+        assert_eq!(
+            references_in_contents("\\contentsline {section}{Results}{4}"),
+            None
+        );
+    }
+
+    #[test]
+    fn a_page_limit_counts_up_to_the_references() {
+        let mut layouts = (1..=10).map(page).collect::<Vec<_>>();
+        // References start halfway down page nine, under the last of the text.
+        layouts[8].lines[1] = line("VII. REFERENCES", 72.0, 400.0, 10.0);
+        let constraints = PageConstraints {
+            max_pages: Some(8),
+            ..PageConstraints::default()
+        };
+        let geometry = vec![LETTER; 10];
+
+        let findings = evaluate(&geometry, &layouts, &constraints, None);
+        assert_eq!(findings.len(), 1);
+        assert!(
+            findings[0]
+                .message
+                .contains("9 pages before the references")
+        );
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert_eq!(findings[0].source.as_deref(), Some(SOURCE));
+
+        // At the top of page nine, the text stopped on page eight.
+        layouts[8].lines[0] = line("References", 72.0, 72.0, 12.0);
+        layouts[8].lines[1] = line("[1] A. Author.", 72.0, 100.0, 10.0);
+        assert!(evaluate(&geometry, &layouts, &constraints, Some(9)).is_empty());
+    }
+
+    #[test]
+    fn paper_type_size_and_margins_are_held_to_the_template() {
+        let mut layouts = vec![page(1), page(2), page(3)];
+        layouts[1]
+            .lines
+            .push(line("A squeezed caption.", 72.0, 300.0, 7.0));
+        layouts[2].lines.push(TextLine {
+            width: 500.0,
+            ..line("An overfull line.", 60.0, 400.0, 10.0)
+        });
+        let geometry = vec![
+            LETTER,
+            LETTER,
+            PageGeometry {
+                width: 595.0,
+                height: 842.0,
+            },
+        ];
+        let constraints = PageConstraints {
+            max_pages: None,
+            paper: Some(PaperSize::Letter),
+            min_font_size: Some(9.0),
+            margins: Some(Margins {
+                top: 72.0,
+                bottom: 72.0,
+                left: 72.0,
+                right: 72.0,
+            }),
+        };
+
+        let messages = evaluate(&geometry, &layouts, &constraints, None)
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect::<Vec<_>>();
+
+        assert!(messages.contains(&"Page 3 is not US Letter".to_owned()));
+        assert!(messages.iter().any(
+            |message| message.starts_with("Text is set as small as 7.0pt")
+                && message.ends_with("on page 2")
+        ));
+        assert!(messages.iter().any(|message| {
+            message.starts_with("The left margin is 0.83in") && message.ends_with("on page 3")
+        }));
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.starts_with("The right margin"))
+        );
+        // Page numbers sit in the bottom margin by design.
+        assert!(
+            !messages
+                .iter()
+                .any(|message| message.starts_with("The bottom margin"))
+        );
+    }
+}
@@ -10,8 +10,10 @@ use rusqlite::{Connection, OptionalExtension, Row, Transaction, params};
 use crate::{
     error::{AppError, AppResult},
     model::{
-        ArtifactSummary, BuildState, Diagnostic, Engine, Preset, Project, ProjectSummary,
-        SnapshotOutcome, SnapshotSummary, SourceRef, VersionSummary,
+        Annotation, ArtifactSummary, BuildState, Diagnostic, DocumentStatistics, Engine,
+        LibraryHit, NewAnnotation, PageConstraints, PagePoint, Preset, PreviousBuild, Project,
+        ProjectSummary, SavedView, SnapshotOutcome, SnapshotSummary, SourceAnchor, SourceRef,
+        VersionSummary, ViewState,
     },
 };
 
@@ -387,6 +389,308 @@ impl Repository {
         Ok(())
     }
 
+    // -- lint -------------------------------------------------------------
+
+    /// The chktex warnings a project has asked not to hear about, by number.
+    pub fn lint_ignores(&self, project_id: i64) -> AppResult<Vec<u32>> {
+        let connection = self.lock()?;
+        let mut statement = connection
+            .prepare("SELECT warning FROM lint_ignores WHERE project_id = ?1 ORDER BY warning")?;
+        let warnings = statement
+            .query_map([project_id], |row| row.get(0))?
+            .collect::<Result<Vec<u32>, _>>()?;
+        Ok(warnings)
+    }
+
+    /// Replaces the whole list. The settings sheet edits it as one field, so
+    /// there is no add or remove to offer.
+    pub fn set_lint_ignores(&self, project_id: i64, warnings: &[u32]) -> AppResult<()> {
+        let mut connection = self.lock()?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "DELETE FROM lint_ignores WHERE project_id = ?1",
+            [project_id],
+        )?;
+        for warning in warnings {
+            transaction.execute(
+                "INSERT OR IGNORE INTO lint_ignores (project_id, warning) VALUES (?1, ?2)",
+                params![project_id, warning],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    // -- personal dictionary ----------------------------------------------
+
+    /// The words a project has taught the spelling check, alphabetically.
+    pub fn dictionary(&self, project_id: i64) -> AppResult<Vec<String>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(
+            "SELECT word FROM dictionary_words WHERE project_id = ?1
+             ORDER BY word COLLATE NOCASE",
+        )?;
+        let words = statement
+            .query_map([project_id], |row| row.get(0))?
+            .collect::<Result<Vec<String>, _>>()?;
+        Ok(words)
+    }
+
+    pub fn add_to_dictionary(&self, project_id: i64, word: &str) -> AppResult<()> {
+        let word = word.trim();
+        if word.is_empty() || word.contains(char::is_whitespace) {
+            return Err(AppError::InvalidInput(format!(
+                "\"{word}\" is not a single word"
+            )));
+        }
+        self.lock()?.execute(
+            "INSERT OR IGNORE INTO dictionary_words (project_id, word) VALUES (?1, ?2)",
+            params![project_id, word],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_from_dictionary(&self, project_id: i64, word: &str) -> AppResult<()> {
+        self.lock()?.execute(
+            "DELETE FROM dictionary_words WHERE project_id = ?1 AND word = ?2",
+            params![project_id, word.trim()],
+        )?;
+        Ok(())
+    }
+
+    // -- page constraints -------------------------------------------------
+
+    /// The template limits a project has declared; none, for one that has not.
+    pub fn page_constraints(&self, project_id: i64) -> AppResult<PageConstraints> {
+        let connection = self.lock()?;
+        let body = connection
+            .query_row(
+                "SELECT body FROM page_constraints WHERE project_id = ?1",
+                [project_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(body
+            .and_then(|body| serde_json::from_str(&body).ok())
+            .unwrap_or_default())
+    }
+
+    pub fn set_page_constraints(
+        &self,
+        project_id: i64,
+        constraints: &PageConstraints,
+    ) -> AppResult<()> {
+        let lengths = [
+            constraints.min_font_size,
+            constraints.margins.map(|margins| margins.top),
+            constraints.margins.map(|margins| margins.bottom),
+            constraints.margins.map(|margins| margins.left),
+            constraints.margins.map(|margins| margins.right),
+        ];
+        if constraints.max_pages == Some(0)
+            || lengths
+                .into_iter()
+                .flatten()
+                .any(|length| !length.is_finite() || length < 0.0)
+        {
+            return Err(AppError::InvalidInput(
+                "page limits must be positive numbers".into(),
+            ));
+        }
+        let connection = self.lock()?;
+        if constraints.is_empty() {
+            connection.execute(
+                "DELETE FROM page_constraints WHERE project_id = ?1",
+                [project_id],
+            )?;
+            return Ok(());
+        }
+        connection.execute(
+            "INSERT INTO page_constraints (project_id, body) VALUES (?1, ?2)
+             ON CONFLICT(project_id) DO UPDATE SET body = excluded.body",
+            params![project_id, serde_json::to_string(constraints)?],
+        )?;
+        Ok(())
+    }
+
+    // -- view state -------------------------------------------------------
+
+    /// Every version's view state for a project, most recently saved first.
+    /// One that no longer reads — written by a different Press — is left out
+    /// rather than failing the project's opening.
+    pub fn view_states(&self, project_id: i64) -> AppResult<Vec<SavedView>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(
+            "SELECT source_ref, body, updated_at FROM view_state WHERE project_id = ?1
+             ORDER BY updated_at DESC, rowid DESC",
+        )?;
+        let rows = statement
+            .query_map([project_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(rows
+            .into_iter()
+            .filter_map(|(source_ref, body, updated_at)| {
+                Some(SavedView {
+                    source_ref: source_ref.parse().ok()?,
+                    updated_at,
+                    view: serde_json::from_str(&body).ok()?,
+                })
+            })
+            .collect())
+    }
+
+    pub fn save_view_state(
+        &self,
+        project_id: i64,
+        source_ref: &SourceRef,
+        view: &ViewState,
+    ) -> AppResult<()> {
+        let point = |point: &PagePoint| point.x.is_finite() && point.y.is_finite();
+        if !(view.zoom.is_finite() && view.zoom > 0.0)
+            || !point(&view.position)
+            || !view.marks.values().chain(&view.jumps).all(point)
+        {
+            return Err(AppError::InvalidInput(
+                "a view state needs finite positions and a positive zoom".into(),
+            ));
+        }
+        if let Some(mark) = view.marks.keys().find(|mark| !mark.is_ascii_alphabetic()) {
+            return Err(AppError::InvalidInput(format!(
+                "\"{mark}\" is not a mark: marks are letters"
+            )));
+        }
+        self.lock()?.execute(
+            "INSERT INTO view_state (project_id, source_ref, body, updated_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(project_id, source_ref) DO UPDATE
+             SET body = excluded.body, updated_at = excluded.updated_at",
+            params![
+                project_id,
+                source_ref.to_string(),
+                serde_json::to_string(view)?,
+                unix_timestamp()
+            ],
+        )?;
+        Ok(())
+    }
+
+    // -- annotations ------------------------------------------------------
+
+    pub fn add_annotation(
+        &self,
+        project_id: i64,
+        source_ref: &SourceRef,
+        annotation: &NewAnnotation,
+        anchor: Option<&SourceAnchor>,
+    ) -> AppResult<Annotation> {
+        if annotation.rects.is_empty() {
+            return Err(AppError::InvalidInput(
+                "an annotation needs a place on the page".into(),
+            ));
+        }
+        let now = unix_timestamp();
+        let mut connection = self.lock()?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO annotations (
+                project_id, source_ref, kind, page, rects, quote, body,
+                created_at, updated_at
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)",
+            params![
+                project_id,
+                source_ref.to_string(),
+                annotation.kind.as_token(),
+                annotation.page,
+                serde_json::to_string(&annotation.rects)?,
+                annotation.quote,
+                annotation.body,
+                now,
+            ],
+        )?;
+        let id = transaction.last_insert_rowid();
+        if let Some(anchor) = anchor {
+            transaction.execute(
+                "INSERT INTO annotation_anchors (annotation_id, file, line, text)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![id, anchor.file, anchor.line as i64, anchor.text],
+            )?;
+        }
+        transaction.commit()?;
+
+        Ok(Annotation {
+            id,
+            project_id,
+            source_ref: source_ref.clone(),
+            kind: annotation.kind,
+            page: annotation.page,
+            rects: annotation.rects.clone(),
+            quote: annotation.quote.clone(),
+            body: annotation.body.clone(),
+            anchor: anchor.cloned(),
+            created_at: now,
+            updated_at: now,
+        })
+    }
+
+    pub fn annotation(&self, annotation_id: i64) -> AppResult<Annotation> {
+        let connection = self.lock()?;
+        connection
+            .query_row(
+                &annotation_query("a.id = ?1"),
+                [annotation_id],
+                map_annotation,
+            )
+            .optional()?
+            .ok_or_else(|| {
+                AppError::NotFound(format!("annotation {annotation_id} does not exist"))
+            })?
+    }
+
+    /// A project's annotations in reading order, on one version or on all of
+    /// them.
+    pub fn annotations(
+        &self,
+        project_id: i64,
+        source_ref: Option<&SourceRef>,
+    ) -> AppResult<Vec<Annotation>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(&annotation_query(
+            "a.project_id = ?1 AND (?2 IS NULL OR a.source_ref = ?2)",
+        ))?;
+        let annotations = statement
+            .query_map(
+                params![project_id, source_ref.map(SourceRef::to_string)],
+                map_annotation,
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        annotations.into_iter().collect()
+    }
+
+    pub fn update_annotation(&self, annotation_id: i64, body: &str) -> AppResult<Annotation> {
+        let changed = self.lock()?.execute(
+            "UPDATE annotations SET body = ?2, updated_at = ?3 WHERE id = ?1",
+            params![annotation_id, body, unix_timestamp()],
+        )?;
+        if changed == 0 {
+            return Err(AppError::NotFound(format!(
+                "annotation {annotation_id} does not exist"
+            )));
+        }
+        self.annotation(annotation_id)
+    }
+
+    pub fn delete_annotation(&self, annotation_id: i64) -> AppResult<()> {
+        self.lock()?
+            .execute("DELETE FROM annotations WHERE id = ?1", [annotation_id])?;
+        Ok(())
+    }
+
     // -- artifacts --------------------------------------------------------
 
     pub fn artifact(&self, artifact_id: i64) -> AppResult<StoredArtifact> {
@@ -513,6 +817,302 @@ impl Repository {
         ))
     }
 
+    // -- previous builds --------------------------------------------------
+
+    /// Keeps a superseded working-tree publication instead of deleting it, then
+    /// trims every ring to `keep`. Returns the PDFs no longer kept, for
+    /// discarding; with `keep` at zero that includes the one just offered.
+    pub fn keep_previous_build(
+        &self,
+        artifact_id: i64,
+        revision: i64,
+        pdf_path: &Path,
+        byte_size: i64,
+        built_at: i64,
+        keep: usize,
+    ) -> AppResult<Vec<PathBuf>> {
+        let path = pdf_path
+            .to_str()
+            .ok_or_else(|| AppError::InvalidInput("PDF path is not valid UTF-8".into()))?;
+        let mut connection = self.lock()?;
+        let transaction = connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO previous_builds (
+                artifact_id, revision, pdf_path, byte_size, built_at, superseded_at
+             ) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                artifact_id,
+                revision,
+                path,
+                byte_size,
+                built_at,
+                unix_timestamp()
+            ],
+        )?;
+        let dropped = prune_previous_builds(&transaction, keep)?;
+        transaction.commit()?;
+        Ok(dropped)
+    }
+
+    /// Trims every ring to `keep`, for when the number kept has been lowered.
+    /// Returns the PDFs let go.
+    pub fn prune_previous_builds(&self, keep: usize) -> AppResult<Vec<PathBuf>> {
+        let mut connection = self.lock()?;
+        let transaction = connection.transaction()?;
+        let dropped = prune_previous_builds(&transaction, keep)?;
+        transaction.commit()?;
+        Ok(dropped)
+    }
+
+    /// A project's kept working-tree builds, the most recently replaced first.
+    pub fn previous_builds(&self, project_id: i64) -> AppResult<Vec<PreviousBuild>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(
+            "SELECT p.id, p.artifact_id, p.revision, p.byte_size, p.built_at, p.superseded_at
+             FROM previous_builds p
+             JOIN artifacts a ON a.id = p.artifact_id
+             WHERE a.project_id = ?1 AND a.source_ref = ?2
+             ORDER BY p.id DESC",
+        )?;
+        let builds = statement
+            .query_map(
+                params![project_id, SourceRef::Worktree.to_string()],
+                |row| {
+                    Ok(PreviousBuild {
+                        id: row.get(0)?,
+                        artifact_id: row.get(1)?,
+                        revision: row.get(2)?,
+                        byte_size: row.get(3)?,
+                        built_at: row.get(4)?,
+                        superseded_at: row.get(5)?,
+                    })
+                },
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(builds)
+    }
+
+    pub fn previous_build_path(&self, previous_id: i64) -> AppResult<PathBuf> {
+        let connection = self.lock()?;
+        connection
+            .query_row(
+                "SELECT pdf_path FROM previous_builds WHERE id = ?1",
+                [previous_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?
+            .map(PathBuf::from)
+            .ok_or_else(|| {
+                AppError::NotFound(format!("earlier build {previous_id} is no longer kept"))
+            })
+    }
+
+    // -- page fingerprints ------------------------------------------------
+
+    /// The page hashes of an artifact's last publication, if it was
+    /// fingerprinted: see `changes`.
+    pub fn page_fingerprints(&self, artifact_id: i64) -> AppResult<Option<Vec<String>>> {
+        let connection = self.lock()?;
+        let body = connection
+            .query_row(
+                "SELECT body FROM page_fingerprints WHERE artifact_id = ?1",
+                [artifact_id],
+                |row| row.get::<_, String>(0),
+            )
+            .optional()?;
+        Ok(body.and_then(|body| serde_json::from_str(&body).ok()))
+    }
+
+    pub fn set_page_fingerprints(&self, artifact_id: i64, hashes: &[String]) -> AppResult<()> {
+        self.lock()?.execute(
+            "INSERT INTO page_fingerprints (artifact_id, body) VALUES (?1, ?2)
+             ON CONFLICT(artifact_id) DO UPDATE SET body = excluded.body",
+            params![artifact_id, serde_json::to_string(hashes)?],
+        )?;
+        Ok(())
+    }
+
+    // -- statistics -------------------------------------------------------
+
+    /// Stores what a build of `artifact_id` counted, replacing what an earlier
+    /// build of the same artifact counted. The row goes when the artifact does.
+    pub fn record_statistics(
+        &self,
+        artifact_id: i64,
+        statistics: &DocumentStatistics,
+    ) -> AppResult<()> {
+        self.lock()?.execute(
+            "INSERT INTO artifact_statistics (artifact_id, text_words, abstract_words,
+                    header_words, caption_words, pages, figures, tables, equations)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+             ON CONFLICT(artifact_id) DO UPDATE SET
+                    text_words = excluded.text_words,
+                    abstract_words = excluded.abstract_words,
+                    header_words = excluded.header_words,
+                    caption_words = excluded.caption_words,
+                    pages = excluded.pages,
+                    figures = excluded.figures,
+                    tables = excluded.tables,
+                    equations = excluded.equations",
+            params![
+                artifact_id,
+                statistics.text_words,
+                statistics.abstract_words,
+                statistics.header_words,
+                statistics.caption_words,
+                statistics.pages,
+                statistics.figures,
+                statistics.tables,
+                statistics.equations,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// What the build behind one artifact counted, if it counted anything.
+    pub fn artifact_statistics(&self, artifact_id: i64) -> AppResult<Option<DocumentStatistics>> {
+        let connection = self.lock()?;
+        let statistics = connection
+            .query_row(
+                "SELECT artifact_id, text_words, abstract_words, header_words,
+                        caption_words, pages, figures, tables, equations
+                 FROM artifact_statistics WHERE artifact_id = ?1",
+                [artifact_id],
+                map_statistics,
+            )
+            .optional()?;
+        Ok(statistics)
+    }
+
+    /// The counts for every artifact a project has built with `engine`, by
+    /// artifact. An artifact built before Press counted anything has none.
+    fn statistics_for_project(
+        &self,
+        project_id: i64,
+        engine: Engine,
+    ) -> AppResult<HashMap<i64, DocumentStatistics>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(
+            "SELECT s.artifact_id, s.text_words, s.abstract_words, s.header_words,
+                    s.caption_words, s.pages, s.figures, s.tables, s.equations
+             FROM artifact_statistics s JOIN artifacts a ON a.id = s.artifact_id
+             WHERE a.project_id = ?1 AND a.engine = ?2",
+        )?;
+        let rows = statement.query_map(params![project_id, engine.as_token()], |row| {
+            Ok((row.get::<_, i64>("artifact_id")?, map_statistics(row)?))
+        })?;
+        Ok(rows.collect::<Result<HashMap<_, _>, _>>()?)
+    }
+
+    // -- library text -----------------------------------------------------
+
+    /// Replaces the stored text of `artifact_id` with `pages`, if `revision`
+    /// is still the artifact's. A slow extraction of a build that has since
+    /// been superseded must not overwrite the text of the one that replaced it.
+    pub fn index_text(&self, artifact_id: i64, revision: i64, pages: &[String]) -> AppResult<()> {
+        let mut connection = self.lock()?;
+        let transaction = connection.transaction()?;
+        let current = transaction
+            .query_row(
+                "SELECT revision FROM artifacts WHERE id = ?1",
+                [artifact_id],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()?;
+        if current != Some(revision) {
+            return Ok(());
+        }
+        transaction.execute(
+            "DELETE FROM page_text WHERE artifact_id = ?1",
+            [artifact_id],
+        )?;
+        {
+            let mut insert = transaction
+                .prepare("INSERT INTO page_text (body, artifact_id, page) VALUES (?1, ?2, ?3)")?;
+            for (index, body) in pages.iter().enumerate() {
+                insert.execute(params![body, artifact_id, index as i64 + 1])?;
+            }
+        }
+        transaction.execute(
+            "INSERT INTO indexed_artifacts (artifact_id, revision) VALUES (?1, ?2)
+             ON CONFLICT(artifact_id) DO UPDATE SET revision = excluded.revision",
+            params![artifact_id, revision],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Artifacts whose current revision has no text stored, with their PDFs.
+    pub fn unindexed_artifacts(&self) -> AppResult<Vec<(i64, i64, PathBuf)>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(
+            "SELECT a.id, a.revision, a.pdf_path FROM artifacts a
+             LEFT JOIN indexed_artifacts i ON i.artifact_id = a.id
+             WHERE i.revision IS NOT a.revision",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, i64>(1)?,
+                PathBuf::from(row.get::<_, String>(2)?),
+            ))
+        })?;
+        Ok(rows.collect::<Result<Vec<_>, _>>()?)
+    }
+
+    /// The pages that best match an FTS5 `query`, best first. Only each
+    /// document's working tree, as built with its current engine, unless
+    /// `snapshots` asks for every version.
+    pub fn search_library(
+        &self,
+        query: &str,
+        snapshots: bool,
+        limit: usize,
+    ) -> AppResult<Vec<LibraryHit>> {
+        let connection = self.lock()?;
+        let mut statement = connection.prepare(
+            "SELECT a.project_id, p.name, a.id, a.source_ref, t.page,
+                    snippet(page_text, 0, char(2), char(3), '…', 16)
+             FROM page_text t
+             JOIN artifacts a ON a.id = t.artifact_id
+             JOIN projects p ON p.id = a.project_id
+             WHERE page_text MATCH ?1 AND a.engine = p.engine
+                   AND (?2 OR a.source_ref = ?3)
+             ORDER BY bm25(page_text)
+             LIMIT ?4",
+        )?;
+        let rows = statement.query_map(
+            params![
+                query,
+                snapshots,
+                SourceRef::Worktree.to_string(),
+                limit as i64
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, i64>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, i64>(4)?,
+                    row.get::<_, String>(5)?,
+                ))
+            },
+        )?;
+        rows.map(|row| -> AppResult<LibraryHit> {
+            let (project_id, project_name, artifact_id, source_ref, page, snippet) = row?;
+            Ok(LibraryHit {
+                project_id,
+                project_name,
+                artifact_id,
+                source_ref: source_ref.parse()?,
+                page: page as usize,
+                snippet: crate::library::snippet(&snippet),
+            })
+        })
+        .collect()
+    }
+
     // -- snapshots --------------------------------------------------------
 
     /// Records a captured snapshot. The objects are already in the store; this
@@ -684,6 +1284,19 @@ impl Repository {
             params![project_id, &revision],
             |row| row.get(0),
         )?;
+        if remaining == 0 {
+            // What was read and noted on a version goes with the last copy of
+            // it; the anchors follow their annotations by cascade.
+            let token = SourceRef::Snapshot(revision.clone()).to_string();
+            transaction.execute(
+                "DELETE FROM view_state WHERE project_id = ?1 AND source_ref = ?2",
+                params![project_id, &token],
+            )?;
+            transaction.execute(
+                "DELETE FROM annotations WHERE project_id = ?1 AND source_ref = ?2",
+                params![project_id, &token],
+            )?;
+        }
         transaction.commit()?;
         Ok((project_id, revision, remaining == 0))
     }
@@ -691,7 +1304,7 @@ impl Repository {
     /// The history, newest first, with the working tree pinned at the top.
     /// Each row carries what Press knows about building that version.
     ///
-    /// Three queries whatever the length of the history. Asking per row instead
+    /// Four queries whatever the length of the history. Asking per row instead
     /// meant two queries and two turns of the connection lock for every version
     /// a document had, on every snapshot taken, renamed or discarded.
     pub fn list_versions(&self, project_id: i64) -> AppResult<Vec<VersionSummary>> {
@@ -699,6 +1312,7 @@ impl Repository {
         let snapshots = self.list_snapshots(project_id)?;
         let builds = self.build_states(project_id)?;
         let artifacts = self.artifacts_for_project(project_id, project.engine)?;
+        let statistics = self.statistics_for_project(project_id, project.engine)?;
 
         let mut versions = Vec::with_capacity(snapshots.len() + 1);
         // Read rather than taken. Two snapshots of identical content share a
@@ -706,12 +1320,16 @@ impl Repository {
         // longer stores the second, but databases written before that rule have
         // pairs in them, and both rows have to report the state they both have.
         let row = |source_ref: SourceRef, title: String, snapshot: Option<SnapshotSummary>| {
+            let artifact = artifacts.get(&source_ref).cloned();
             VersionSummary {
                 build: builds
                     .get(&source_ref)
                     .cloned()
                     .unwrap_or_else(|| BuildState::never(source_ref.clone())),
-                artifact: artifacts.get(&source_ref).cloned(),
+                statistics: artifact
+                    .as_ref()
+                    .and_then(|artifact| statistics.get(&artifact.id).cloned()),
+                artifact,
                 title,
                 snapshot,
                 source_ref,
@@ -775,7 +1393,11 @@ impl Repository {
         let transaction = connection.transaction()?;
         let paths = {
             let mut statement = transaction.prepare(
-                "SELECT pdf_path FROM artifacts WHERE project_id = ?1 AND source_ref = ?2",
+                "SELECT pdf_path FROM artifacts WHERE project_id = ?1 AND source_ref = ?2
+                 UNION ALL
+                 SELECT p.pdf_path FROM previous_builds p
+                 JOIN artifacts a ON a.id = p.artifact_id
+                 WHERE a.project_id = ?1 AND a.source_ref = ?2",
             )?;
             let rows =
                 statement.query_map(params![project_id, &token], |row| row.get::<_, String>(0))?;
@@ -807,7 +1429,9 @@ impl Repository {
 
     pub fn managed_pdf_paths(&self) -> AppResult<Vec<PathBuf>> {
         let connection = self.lock()?;
-        let mut statement = connection.prepare("SELECT pdf_path FROM artifacts")?;
+        let mut statement = connection.prepare(
+            "SELECT pdf_path FROM artifacts UNION ALL SELECT pdf_path FROM previous_builds",
+        )?;
         let rows = statement.query_map([], |row| row.get::<_, String>(0))?;
         rows.map(|row| row.map(PathBuf::from))
             .collect::<Result<Vec<_>, _>>()
@@ -887,12 +1511,44 @@ fn summary_query(filter: &str) -> String {
     format!("{SUMMARY_QUERY_BASE}{filter}{SUMMARY_ORDER}")
 }
 
+/// Deletes the oldest kept builds of every artifact past `keep`, returning
+/// their PDFs.
+fn prune_previous_builds(transaction: &Transaction<'_>, keep: usize) -> AppResult<Vec<PathBuf>> {
+    let dropped = {
+        let mut statement = transaction.prepare(
+            "SELECT id, pdf_path FROM (
+                SELECT id, pdf_path, ROW_NUMBER() OVER (
+                    PARTITION BY artifact_id ORDER BY id DESC
+                ) AS place
+                FROM previous_builds
+             ) WHERE place > ?1",
+        )?;
+        statement
+            .query_map([keep as i64], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    for (id, _) in &dropped {
+        transaction.execute("DELETE FROM previous_builds WHERE id = ?1", [id])?;
+    }
+    Ok(dropped
+        .into_iter()
+        .map(|(_, path)| PathBuf::from(path))
+        .collect())
+}
+
 fn artifact_paths_for_project(
     transaction: &Transaction<'_>,
     project_id: i64,
 ) -> AppResult<Vec<PathBuf>> {
-    let mut statement =
-        transaction.prepare("SELECT pdf_path FROM artifacts WHERE project_id = ?1")?;
+    let mut statement = transaction.prepare(
+        "SELECT pdf_path FROM artifacts WHERE project_id = ?1
+         UNION ALL
+         SELECT p.pdf_path FROM previous_builds p
+         JOIN artifacts a ON a.id = p.artifact_id
+         WHERE a.project_id = ?1",
+    )?;
     let rows = statement.query_map([project_id], |row| row.get::<_, String>(0))?;
     Ok(rows
         .filter_map(Result::ok)
@@ -953,6 +1609,68 @@ fn map_build_state(row: &Row<'_>) -> rusqlite::Result<AppResult<BuildState>> {
     })())
 }
 
+fn map_statistics(row: &Row<'_>) -> rusqlite::Result<DocumentStatistics> {
+    Ok(DocumentStatistics {
+        text_words: row.get("text_words")?,
+        abstract_words: row.get("abstract_words")?,
+        header_words: row.get("header_words")?,
+        caption_words: row.get("caption_words")?,
+        pages: row.get("pages")?,
+        figures: row.get("figures")?,
+        tables: row.get("tables")?,
+        equations: row.get("equations")?,
+    })
+}
+
+fn annotation_query(filter: &str) -> String {
+    format!(
+        "SELECT a.id, a.project_id, a.source_ref, a.kind, a.page, a.rects, a.quote,
+                a.body, a.created_at, a.updated_at,
+                anchor.file AS anchor_file, anchor.line AS anchor_line,
+                anchor.text AS anchor_text
+         FROM annotations a
+         LEFT JOIN annotation_anchors anchor ON anchor.annotation_id = a.id
+         WHERE {filter}
+         ORDER BY a.page, a.created_at, a.id"
+    )
+}
+
+fn map_annotation(row: &Row<'_>) -> rusqlite::Result<AppResult<Annotation>> {
+    let source_ref: String = row.get("source_ref")?;
+    let kind: String = row.get("kind")?;
+    let rects: String = row.get("rects")?;
+    let anchor = match row.get::<_, Option<String>>("anchor_file")? {
+        Some(file) => Some(SourceAnchor {
+            file,
+            line: row.get::<_, i64>("anchor_line")? as usize,
+            text: row.get("anchor_text")?,
+        }),
+        None => None,
+    };
+    let id = row.get("id")?;
+    let project_id = row.get("project_id")?;
+    let page = row.get("page")?;
+    let quote = row.get("quote")?;
+    let body = row.get("body")?;
+    let created_at = row.get("created_at")?;
+    let updated_at = row.get("updated_at")?;
+    Ok((|| {
+        Ok(Annotation {
+            id,
+            project_id,
+            source_ref: source_ref.parse()?,
+            kind: kind.parse()?,
+            page,
+            rects: serde_json::from_str(&rects)?,
+            quote,
+            body,
+            anchor,
+            created_at,
+            updated_at,
+        })
+    })())
+}
+
 fn map_artifact(row: &Row<'_>) -> rusqlite::Result<AppResult<StoredArtifact>> {
     let source_ref: String = row.get("source_ref")?;
     let engine: String = row.get("engine")?;
@@ -1157,6 +1875,130 @@ const SCHEMA: &str = "
         diagnostics TEXT NOT NULL DEFAULT '[]',
         PRIMARY KEY (project_id, source_ref)
     );
+
+    -- chktex warning numbers a project leaves out, passed to it as `-n`. Per
+    -- project because what counts as noise depends on the document: a thesis
+    -- in one house style and a talk in another disagree about warning 1.
+    CREATE TABLE IF NOT EXISTS lint_ignores (
+        project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+        warning INTEGER NOT NULL,
+        PRIMARY KEY (project_id, warning)
+    );
+
+    -- A project's personal dictionary: names, jargon and coinages the spelling
+    -- check should stop reporting. Compared without regard to case.
+    CREATE TABLE IF NOT EXISTS dictionary_words (
+        project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+        word TEXT NOT NULL COLLATE NOCASE,
+        PRIMARY KEY (project_id, word)
+    );
+
+    -- The limits a venue sets on a project's PDF, as JSON. One document
+    -- rather than a column per limit: the limits are read and written whole,
+    -- and a venue that adds a new kind of limit should not need a migration.
+    CREATE TABLE IF NOT EXISTS page_constraints (
+        project_id INTEGER PRIMARY KEY REFERENCES projects(id) ON DELETE CASCADE,
+        body TEXT NOT NULL
+    );
+
+    -- A reader's highlights and comments, on the version of the project they
+    -- were made on. Keyed by source reference rather than artifact: an
+    -- artifact is rebuilt and replaced, and the annotations outlive it. The
+    -- rectangles are JSON, read and written whole like page constraints.
+    CREATE TABLE IF NOT EXISTS annotations (
+        id INTEGER PRIMARY KEY,
+        project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+        source_ref TEXT NOT NULL,
+        kind TEXT NOT NULL,
+        page INTEGER NOT NULL,
+        rects TEXT NOT NULL,
+        quote TEXT NOT NULL DEFAULT '',
+        body TEXT NOT NULL DEFAULT '',
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS annotations_by_version
+        ON annotations(project_id, source_ref);
+
+    -- The source line SyncTeX put under an annotation when it was made, and
+    -- what that line said. Beside the annotation because not every one has
+    -- one: a PDF built without SyncTeX still takes highlights.
+    CREATE TABLE IF NOT EXISTS annotation_anchors (
+        annotation_id INTEGER PRIMARY KEY REFERENCES annotations(id) ON DELETE CASCADE,
+        file TEXT NOT NULL,
+        line INTEGER NOT NULL,
+        text TEXT NOT NULL
+    );
+
+    -- Where the reader was in each version, and how they were reading it, as
+    -- JSON. Whole like page constraints: the viewer saves it as it goes and
+    -- reads it back in one piece when the project opens.
+    CREATE TABLE IF NOT EXISTS view_state (
+        project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+        source_ref TEXT NOT NULL,
+        body TEXT NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (project_id, source_ref)
+    );
+
+    -- What the build behind an artifact counted. Beside the artifact rather
+    -- than in it: the counts are read for the history and nowhere else, and
+    -- an artifact from before they were counted simply has no row.
+    CREATE TABLE IF NOT EXISTS artifact_statistics (
+        artifact_id INTEGER PRIMARY KEY REFERENCES artifacts(id) ON DELETE CASCADE,
+        text_words INTEGER NOT NULL,
+        abstract_words INTEGER NOT NULL,
+        header_words INTEGER NOT NULL,
+        caption_words INTEGER NOT NULL,
+        pages INTEGER,
+        figures INTEGER NOT NULL,
+        tables INTEGER NOT NULL,
+        equations INTEGER NOT NULL
+    );
+
+    -- Working-tree publications kept after a rebuild replaced them, newest
+    -- last, so the reader can go back a few saves without having stored a
+    -- version. A ring: past the number kept, the oldest is deleted.
+    CREATE TABLE IF NOT EXISTS previous_builds (
+        id INTEGER PRIMARY KEY,
+        artifact_id INTEGER NOT NULL REFERENCES artifacts(id) ON DELETE CASCADE,
+        revision INTEGER NOT NULL,
+        pdf_path TEXT NOT NULL,
+        byte_size INTEGER NOT NULL,
+        built_at INTEGER NOT NULL,
+        superseded_at INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS previous_builds_by_artifact
+        ON previous_builds(artifact_id);
+
+    -- A hash of each page of an artifact as last published, as a JSON array,
+    -- so the next build of the working tree can say which pages it changed.
+    -- The PDF the hashes came from is deleted when it is superseded.
+    CREATE TABLE IF NOT EXISTS page_fingerprints (
+        artifact_id INTEGER PRIMARY KEY REFERENCES artifacts(id) ON DELETE CASCADE,
+        body TEXT NOT NULL
+    );
+
+    -- Each artifact's text, a row per page, for searching the whole library.
+    -- Derived from the PDFs and rebuilt from them whenever it is missing.
+    CREATE VIRTUAL TABLE IF NOT EXISTS page_text USING fts5(
+        body,
+        artifact_id UNINDEXED,
+        page UNINDEXED,
+        tokenize = 'unicode61 remove_diacritics 2'
+    );
+    -- Which revision of an artifact `page_text` holds, so that a PDF with no
+    -- text in it is not extracted again at every start.
+    CREATE TABLE IF NOT EXISTS indexed_artifacts (
+        artifact_id INTEGER PRIMARY KEY REFERENCES artifacts(id) ON DELETE CASCADE,
+        revision INTEGER NOT NULL
+    );
+    -- A virtual table has no foreign keys to cascade through.
+    CREATE TRIGGER IF NOT EXISTS artifacts_page_text_delete
+        AFTER DELETE ON artifacts
+    BEGIN
+        DELETE FROM page_text WHERE artifact_id = old.id;
+    END;
 ";
 
 /// Bump only when an existing table changes shape. Adding a table or an index
@@ -1166,7 +2008,7 @@ const SCHEMA: &str = "
 /// There is no migration path by design: Press has one user, and a stale
 /// database is cheaper to delete than to migrate. The version exists so that a
 /// mismatch says so plainly instead of failing later with a confusing SQL error.
-const SCHEMA_VERSION: i32 = 5;
+const SCHEMA_VERSION: i32 = 6;
 
 fn initialize(connection: &Connection) -> AppResult<()> {
     connection.execute_batch(SCHEMA)?;
@@ -1225,7 +2067,7 @@ mod tests {
     use std::collections::HashSet;
 
     use super::*;
-    use crate::model::BuildStatus;
+    use crate::model::{AnnotationKind, BuildStatus, PageRegion, PaperSize, ZoomMode};
 
     #[test]
     fn reopening_keeps_what_was_stored() {
@@ -1683,6 +2525,57 @@ mod tests {
         assert_eq!(documents.get(&root.join("other.tex")), None);
     }
 
+    #[test]
+    fn replaced_worktree_builds_are_kept_in_a_ring() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "thesis");
+        let project = add(&database, &root.join("main.tex"));
+
+        let mut replaced = Vec::new();
+        for number in 1..=4 {
+            let pdf = directory.path().join(format!("build-{number}.pdf"));
+            let (artifact, superseded) = database
+                .record_artifact(NewArtifact {
+                    project_id: project.id,
+                    source_ref: &SourceRef::Worktree,
+                    engine: Engine::PdfLatex,
+                    pdf_path: &pdf,
+                    page_count: Some(number),
+                    byte_size: 8,
+                })
+                .unwrap();
+            if let Some(previous) = superseded {
+                let dropped = database
+                    .keep_previous_build(artifact.id, artifact.revision - 1, &previous, 8, 0, 2)
+                    .unwrap();
+                replaced.push((previous, dropped));
+            }
+        }
+        let first = directory.path().join("build-1.pdf");
+        assert_eq!(replaced[0].1, Vec::<PathBuf>::new());
+        assert_eq!(replaced[2].1, vec![first.clone()], "the oldest falls out");
+
+        let kept = database.previous_builds(project.id).unwrap();
+        assert_eq!(
+            kept.iter().map(|build| build.revision).collect::<Vec<_>>(),
+            vec![3, 2]
+        );
+        assert_eq!(
+            database.previous_build_path(kept[1].id).unwrap(),
+            directory.path().join("build-2.pdf")
+        );
+        let managed = database.managed_pdf_paths().unwrap();
+        assert!(managed.contains(&directory.path().join("build-3.pdf")));
+        assert!(!managed.contains(&first));
+
+        assert_eq!(
+            database.prune_previous_builds(1).unwrap(),
+            vec![directory.path().join("build-2.pdf")]
+        );
+        assert!(database.previous_build_path(kept[1].id).is_err());
+    }
+
     #[test]
     fn artifacts_are_cached_per_version_and_engine() {
         let directory = tempfile::tempdir().unwrap();
@@ -1884,8 +2777,10 @@ mod tests {
                     diagnostics: vec![Diagnostic {
                         file: Some("main.tex".into()),
                         line: Some(4),
+                        column: None,
                         severity: crate::model::Severity::Error,
                         message: "Undefined control sequence.".into(),
+                        source: None,
                     }],
                 },
             )
@@ -1936,4 +2831,379 @@ mod tests {
         assert_eq!(state.status, BuildStatus::Interrupted);
         assert!(state.error_summary.unwrap().contains("Press closed"));
     }
+
+    #[test]
+    fn lint_ignores_are_replaced_whole_and_go_with_the_project() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "thesis");
+        let project = add(&database, &root.join("main.tex"));
+
+        assert!(database.lint_ignores(project.id).unwrap().is_empty());
+        database.set_lint_ignores(project.id, &[24, 1, 1]).unwrap();
+        assert_eq!(database.lint_ignores(project.id).unwrap(), vec![1, 24]);
+        database.set_lint_ignores(project.id, &[8]).unwrap();
+        assert_eq!(database.lint_ignores(project.id).unwrap(), vec![8]);
+
+        database.delete_project(project.id).unwrap();
+        assert!(database.lint_ignores(project.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn a_dictionary_holds_each_word_once_whatever_its_case() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "thesis");
+        let project = add(&database, &root.join("main.tex"));
+
+        database.add_to_dictionary(project.id, "Zorblax").unwrap();
+        database.add_to_dictionary(project.id, " zorblax ").unwrap();
+        database.add_to_dictionary(project.id, "anneal").unwrap();
+        assert_eq!(
+            database.dictionary(project.id).unwrap(),
+            vec!["anneal", "Zorblax"]
+        );
+        assert!(database.add_to_dictionary(project.id, "two words").is_err());
+
+        database
+            .remove_from_dictionary(project.id, "ZORBLAX")
+            .unwrap();
+        assert_eq!(database.dictionary(project.id).unwrap(), vec!["anneal"]);
+    }
+
+    #[test]
+    fn each_version_reports_what_its_own_build_counted() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "thesis");
+        let project = add(&database, &root.join("main.tex"));
+        let pdf = directory.path().join("build.pdf");
+        std::fs::write(&pdf, b"%PDF-1.7").unwrap();
+
+        let (artifact, _) = database
+            .record_artifact(NewArtifact {
+                project_id: project.id,
+                source_ref: &SourceRef::Worktree,
+                engine: Engine::PdfLatex,
+                pdf_path: &pdf,
+                page_count: Some(12),
+                byte_size: 8,
+            })
+            .unwrap();
+        let first = DocumentStatistics {
+            text_words: 4000,
+            abstract_words: 150,
+            header_words: 30,
+            caption_words: 120,
+            pages: Some(12),
+            figures: 3,
+            tables: 1,
+            equations: 7,
+        };
+        database.record_statistics(artifact.id, &first).unwrap();
+        let rebuilt = DocumentStatistics {
+            text_words: 4100,
+            ..first.clone()
+        };
+        database.record_statistics(artifact.id, &rebuilt).unwrap();
+
+        let versions = database.list_versions(project.id).unwrap();
+        assert_eq!(versions[0].statistics.as_ref(), Some(&rebuilt));
+        assert_eq!(
+            database.artifact_statistics(artifact.id).unwrap(),
+            Some(rebuilt)
+        );
+
+        database
+            .forget_version(project.id, &SourceRef::Worktree)
+            .unwrap();
+        let versions = database.list_versions(project.id).unwrap();
+        assert!(versions[0].statistics.is_none());
+    }
+
+    #[test]
+    fn the_library_finds_text_in_each_documents_current_build() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "thesis");
+        let project = add(&database, &root.join("main.tex"));
+        let pdf = directory.path().join("build.pdf");
+        std::fs::write(&pdf, b"%PDF-1.7").unwrap();
+        let record = |source_ref: &SourceRef| {
+            database
+                .record_artifact(NewArtifact {
+                    project_id: project.id,
+                    source_ref,
+                    engine: Engine::PdfLatex,
+                    pdf_path: &pdf,
+                    page_count: Some(2),
+                    byte_size: 8,
+                })
+                .unwrap()
+                .0
+        };
+        let worktree = record(&SourceRef::Worktree);
+        let snapshot = record(&SourceRef::Snapshot("abc123".into()));
+        assert_eq!(database.unindexed_artifacts().unwrap().len(), 2);
+
+        let pages = |notation: &str| {
+            vec![
+                "Introduction".to_owned(),
+                format!("We write {notation} for the Lipschitz constant."),
+            ]
+        };
+        database
+            .index_text(worktree.id, worktree.revision, &pages("L"))
+            .unwrap();
+        database
+            .index_text(snapshot.id, snapshot.revision, &pages("K"))
+            .unwrap();
+        assert!(database.unindexed_artifacts().unwrap().is_empty());
+
+        let hits = database.search_library("\"lipschitz\"", false, 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].artifact_id, worktree.id);
+        assert_eq!(hits[0].project_name, "Thesis");
+        assert_eq!(hits[0].page, 2);
+        assert!(
+            hits[0]
+                .snippet
+                .iter()
+                .any(|part| part.matched && part.text == "Lipschitz")
+        );
+        assert_eq!(
+            database
+                .search_library("\"lipschitz\"", true, 10)
+                .unwrap()
+                .len(),
+            2
+        );
+
+        // A rebuild makes the stored text stale until it is extracted again, and
+        // an extraction of the build it replaced is not taken in its place.
+        let rebuilt = record(&SourceRef::Worktree);
+        assert_eq!(database.unindexed_artifacts().unwrap().len(), 1);
+        database
+            .index_text(worktree.id, worktree.revision, &pages("M"))
+            .unwrap();
+        assert_eq!(database.unindexed_artifacts().unwrap().len(), 1);
+        database
+            .index_text(rebuilt.id, rebuilt.revision, &pages("M"))
+            .unwrap();
+        assert_eq!(
+            database.search_library("\"m\"", false, 10).unwrap().len(),
+            1
+        );
+
+        database
+            .forget_version(project.id, &SourceRef::Worktree)
+            .unwrap();
+        assert!(
+            database
+                .search_library("\"m\"", true, 10)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn page_constraints_round_trip_and_clear_when_emptied() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "paper");
+        let project = add(&database, &root.join("main.tex"));
+        assert!(database.page_constraints(project.id).unwrap().is_empty());
+
+        let constraints = PageConstraints {
+            max_pages: Some(8),
+            paper: Some(PaperSize::Letter),
+            min_font_size: Some(9.0),
+            margins: None,
+        };
+        database
+            .set_page_constraints(project.id, &constraints)
+            .unwrap();
+        assert_eq!(database.page_constraints(project.id).unwrap(), constraints);
+
+        database
+            .set_page_constraints(project.id, &PageConstraints::default())
+            .unwrap();
+        assert!(database.page_constraints(project.id).unwrap().is_empty());
+    }
+
+    #[test]
+    fn annotations_are_kept_per_version_with_their_anchors() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "paper");
+        let project = add(&database, &root.join("main.tex"));
+        let snapshot = SourceRef::Snapshot("abc".into());
+        let region = PageRegion {
+            x: 72.0,
+            y: 100.0,
+            width: 200.0,
+            height: 12.0,
+        };
+        let anchor = SourceAnchor {
+            file: "main.tex".into(),
+            line: 12,
+            text: "Hello, world.".into(),
+        };
+
+        let highlight = database
+            .add_annotation(
+                project.id,
+                &snapshot,
+                &NewAnnotation {
+                    kind: AnnotationKind::Highlight,
+                    page: 1,
+                    rects: vec![region],
+                    quote: "Hello".into(),
+                    body: String::new(),
+                },
+                Some(&anchor),
+            )
+            .unwrap();
+        database
+            .add_annotation(
+                project.id,
+                &SourceRef::Worktree,
+                &NewAnnotation {
+                    kind: AnnotationKind::Comment,
+                    page: 0,
+                    rects: vec![region],
+                    quote: String::new(),
+                    body: "Cite this".into(),
+                },
+                None,
+            )
+            .unwrap();
+
+        let on_snapshot = database.annotations(project.id, Some(&snapshot)).unwrap();
+        assert_eq!(on_snapshot, vec![highlight.clone()]);
+        assert_eq!(on_snapshot[0].anchor.as_ref(), Some(&anchor));
+        let all = database.annotations(project.id, None).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].kind, AnnotationKind::Comment);
+        assert_eq!(all[0].anchor, None);
+
+        let edited = database
+            .update_annotation(highlight.id, "Too strong?")
+            .unwrap();
+        assert_eq!(edited.body, "Too strong?");
+        database.delete_annotation(highlight.id).unwrap();
+        assert!(database.annotation(highlight.id).is_err());
+        assert!(
+            database
+                .add_annotation(
+                    project.id,
+                    &snapshot,
+                    &NewAnnotation {
+                        kind: AnnotationKind::Highlight,
+                        page: 0,
+                        rects: Vec::new(),
+                        quote: String::new(),
+                        body: String::new(),
+                    },
+                    None,
+                )
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn discarding_the_last_copy_of_a_version_takes_its_annotations() {
+        let directory = tempfile::tempdir().unwrap();
+        let objects = directory.path().join("objects");
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "paper");
+        let project = add(&database, &root.join("main.tex"));
+        let capture = crate::snapshot::capture(&root, &objects, &HashSet::new()).unwrap();
+        let stored = database
+            .create_snapshot(project.id, &capture, "Submitted", None)
+            .unwrap()
+            .stored()
+            .expect("the first snapshot of this content");
+        let note = |source_ref: &SourceRef| {
+            database
+                .add_annotation(
+                    project.id,
+                    source_ref,
+                    &NewAnnotation {
+                        kind: AnnotationKind::Comment,
+                        page: 0,
+                        rects: vec![PageRegion {
+                            x: 72.0,
+                            y: 100.0,
+                            width: 200.0,
+                            height: 12.0,
+                        }],
+                        quote: String::new(),
+                        body: "Cite this".into(),
+                    },
+                    Some(&SourceAnchor {
+                        file: "main.tex".into(),
+                        line: 1,
+                        text: String::new(),
+                    }),
+                )
+                .unwrap()
+        };
+        let on_version = note(&SourceRef::Snapshot(stored.revision.clone()));
+        let on_worktree = note(&SourceRef::Worktree);
+
+        database.delete_snapshot(stored.id).unwrap();
+        assert!(database.annotation(on_version.id).is_err());
+        assert_eq!(
+            database.annotations(project.id, None).unwrap(),
+            vec![on_worktree],
+            "the working tree's notes stay"
+        );
+    }
+
+    #[test]
+    fn view_states_come_back_most_recent_first() {
+        let directory = tempfile::tempdir().unwrap();
+        let database = Repository::open(&directory.path().join("press.db")).unwrap();
+        let root = project_fixture(directory.path(), "paper");
+        let project = add(&database, &root.join("main.tex"));
+        assert!(database.view_states(project.id).unwrap().is_empty());
+
+        let mark = PagePoint {
+            page: 3,
+            x: 0.0,
+            y: 120.0,
+        };
+        let reading = ViewState {
+            zoom_mode: ZoomMode::Custom,
+            zoom: 1.5,
+            dark: Some(true),
+            marks: [('a', mark)].into_iter().collect(),
+            jumps: vec![mark],
+            ..ViewState::default()
+        };
+        database
+            .save_view_state(project.id, &SourceRef::Worktree, &ViewState::default())
+            .unwrap();
+        let snapshot = SourceRef::Snapshot("abc".into());
+        database
+            .save_view_state(project.id, &snapshot, &reading)
+            .unwrap();
+
+        let views = database.view_states(project.id).unwrap();
+        assert_eq!(views.len(), 2);
+        assert_eq!(views[0].source_ref, snapshot);
+        assert_eq!(views[0].view, reading);
+        assert_eq!(views[1].view, ViewState::default());
+
+        let unmarked = ViewState {
+            marks: [('1', mark)].into_iter().collect(),
+            ..ViewState::default()
+        };
+        assert!(
+            database
+                .save_view_state(project.id, &snapshot, &unmarked)
+                .is_err()
+        );
+    }
 }
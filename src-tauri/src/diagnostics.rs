@@ -111,8 +111,10 @@ fn parse_error_line(
             return Some(Diagnostic {
                 file: relativize(raw_file, directory),
                 line: capture.get(2)?.as_str().parse().ok(),
+                column: None,
                 severity: Severity::Error,
                 message: clean(message),
+                source: None,
             });
         }
     }
@@ -125,8 +127,10 @@ fn parse_error_line(
     Some(Diagnostic {
         file: stack.current().and_then(|file| relativize(file, directory)),
         line: lookahead_line(lines, index),
+        column: None,
         severity: Severity::Error,
         message: clean(message),
+        source: None,
     })
 }
 
@@ -167,8 +171,10 @@ fn parse_warning_line(
     Some(Diagnostic {
         file: stack.current().and_then(|file| relativize(file, directory)),
         line: line_number,
+        column: None,
         severity: Severity::Warning,
         message: clean(&message),
+        source: None,
     })
 }
 
@@ -206,8 +212,10 @@ pub fn latexmk_failures(output: &str) -> Vec<Diagnostic> {
         let diagnostic = Diagnostic {
             file: None,
             line: None,
+            column: None,
             severity: Severity::Error,
             message: clean(message),
+            source: None,
         };
         if seen.insert(diagnostic.clone()) {
             diagnostics.push(diagnostic);
@@ -229,7 +237,7 @@ pub fn summarize(diagnostics: &[Diagnostic]) -> Option<String> {
     })
 }
 
-fn clean(message: &str) -> String {
+pub fn clean(message: &str) -> String {
     let collapsed = message.split_whitespace().collect::<Vec<_>>().join(" ");
     collapsed.chars().take(400).collect()
 }
@@ -251,7 +259,7 @@ fn looks_like_source_path(value: &str) -> bool {
 /// Resolves a path from the log to one relative to the project root. Paths that
 /// point outside the project (a system class file, say) keep their absolute form
 /// so they are still openable.
-fn relativize(raw: &str, directory: &Path) -> Option<String> {
+pub fn relativize(raw: &str, directory: &Path) -> Option<String> {
     let trimmed = raw.trim().trim_matches('"');
     if trimmed.is_empty() {
         return None;
@@ -484,7 +484,7 @@ fn resolve_relative(base: &Path, value: &str) -> Option<PathBuf> {
     joined.canonicalize().ok()
 }
 
-fn strip_comment(line: &str) -> &str {
+pub fn strip_comment(line: &str) -> &str {
     let bytes = line.as_bytes();
     for (index, byte) in bytes.iter().enumerate() {
         if *byte != b'%' {
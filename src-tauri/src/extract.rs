@@ -0,0 +1,308 @@
+//! A PDF's text as a reader would copy it.
+//!
+//! MuPDF hands back words in the order it found them on the page, which for
+//! what TeX writes is reading order: a column is finished before the next one
+//! starts, and a float is where it was placed. What it does not do is undo
+//! typesetting. A word broken at the end of a line arrives as two words with a
+//! hyphen between them; a ligature from a font without a Unicode map arrives
+//! as one character, `ﬁ`, that no search and no spell checker will take for
+//! "fi"; and mathematics arrives as whatever its glyphs happen to be. The first
+//! two are put right here. The third can only be put right by asking the
+//! source, which is the command's business: this module says which words look
+//! like mathematics and how the answers are shared out among them.
+
+use std::ops::Range;
+
+use crate::{peek::MathSource, render::Word};
+
+/// A gap between lines taller than this share of a line is a paragraph break.
+const PARAGRAPH_GAP: f32 = 0.6;
+
+/// The words of one page, in the order given, as running text. `replaced`
+/// puts other text in place of runs of words, each at most once; an empty
+/// replacement drops the run.
+pub fn join(words: &[Word], replaced: &[(Range<usize>, String)]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&Word> = None;
+    let mut index = 0;
+    while index < words.len() {
+        let word = &words[index];
+        let (content, next) = match replaced.iter().find(|(range, _)| range.start == index) {
+            Some((range, replacement)) => (replacement.clone(), range.end.max(index + 1)),
+            None => (expand_ligatures(word.text.trim()), index + 1),
+        };
+        let last = &words[next - 1];
+        index = next;
+        if content.is_empty() {
+            continue;
+        }
+        if let Some(previous) = previous {
+            if previous.line == word.line {
+                text.push(' ');
+            } else if hyphenated(previous.text.trim(), word) && text.ends_with('-') {
+                text.pop();
+            } else if word.y - (previous.y + previous.height) > previous.height * PARAGRAPH_GAP {
+                text.push_str("\n\n");
+            } else {
+                text.push(' ');
+            }
+        }
+        text.push_str(&content);
+        previous = Some(last);
+    }
+    text
+}
+
+/// Pages run together, each already joined by [`join`] from its `words`. A
+/// word broken over the page turn is put back together as one broken over a
+/// line is; otherwise a page turn is a paragraph break.
+pub fn join_pages<'a>(pages: impl IntoIterator<Item = (&'a [Word], String)>) -> String {
+    let mut text = String::new();
+    let mut previous: Option<&Word> = None;
+    for (words, page) in pages {
+        if page.is_empty() {
+            continue;
+        }
+        if let Some(previous) = previous {
+            let rejoined = words
+                .first()
+                .is_some_and(|first| hyphenated(previous.text.trim(), first));
+            if rejoined && text.ends_with('-') {
+                text.pop();
+            } else {
+                text.push_str("\n\n");
+            }
+        }
+        text.push_str(&page);
+        previous = words.last();
+    }
+    text
+}
+
+/// A word that ends a line with a hyphen, followed by one in lower case: the
+/// same word, broken to fit. "Well-" before "Known" is a name that keeps it.
+pub fn hyphenated(word: &str, next: &Word) -> bool {
+    let Some(stem) = word.strip_suffix('-') else {
+        return false;
+    };
+    stem.chars().last().is_some_and(char::is_alphabetic)
+        && next
+            .text
+            .trim()
+            .chars()
+            .next()
+            .is_some_and(char::is_lowercase)
+}
+
+/// The presentation forms fonts without a Unicode map leave behind, as the
+/// letters they stand for. A soft hyphen is only a place a word may break.
+pub fn expand_ligatures(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '\u{FB00}' => expanded.push_str("ff"),
+            '\u{FB01}' => expanded.push_str("fi"),
+            '\u{FB02}' => expanded.push_str("fl"),
+            '\u{FB03}' => expanded.push_str("ffi"),
+            '\u{FB04}' => expanded.push_str("ffl"),
+            '\u{FB05}' | '\u{FB06}' => expanded.push_str("st"),
+            '\u{00AD}' => {}
+            other => expanded.push(other),
+        }
+    }
+    expanded
+}
+
+/// Runs of words that look like mathematics: each holds a symbol no prose
+/// uses, or is a single letter or a scrap with no letters at all on the same
+/// line as one.
+///
+/// This misses mathematics set only in letters and digits — an italic `x` is
+/// an `x` once extracted — which is why what it finds is only a question put
+/// to the source, never a replacement on its own.
+pub fn math_runs(words: &[Word]) -> Vec<Range<usize>> {
+    let mut runs = Vec::<Range<usize>>::new();
+    for (index, word) in words.iter().enumerate() {
+        if !word.text.chars().any(is_math_symbol) {
+            continue;
+        }
+        let joins = |other: &Word| {
+            other.line == word.line
+                && (other.text.chars().any(is_math_symbol)
+                    || other.text.trim().chars().count() == 1
+                    || !other.text.chars().any(char::is_alphabetic))
+        };
+        let mut start = index;
+        while start > 0 && joins(&words[start - 1]) {
+            start -= 1;
+        }
+        let mut end = index + 1;
+        while end < words.len() && joins(&words[end]) {
+            end += 1;
+        }
+        match runs.last_mut() {
+            Some(last) if last.end >= start => last.end = last.end.max(end),
+            _ => runs.push(start..end),
+        }
+    }
+    runs
+}
+
+fn is_math_symbol(character: char) -> bool {
+    matches!(
+        character,
+        '\u{0370}'..='\u{03FF}'
+            | '\u{2190}'..='\u{21FF}'
+            | '\u{2200}'..='\u{22FF}'
+            | '\u{27C0}'..='\u{27EF}'
+            | '\u{2980}'..='\u{2AFF}'
+            | '\u{1D400}'..='\u{1D7FF}'
+            | '\u{2070}'..='\u{209F}'
+            | '²' | '³' | '¹'
+            | '=' | '<' | '>' | '±' | '×' | '÷' | '·' | '√' | '∞'
+    )
+}
+
+/// Shares out what the source said about each run. Runs that came from the
+/// same line take its pieces of inline mathematics in turn; a display goes to
+/// the first of its runs and the rest are dropped, since it is all of them.
+/// A run the source has nothing for keeps the text it had.
+pub fn assign(sources: &[Option<MathSource>]) -> Vec<Option<String>> {
+    let mut taken = Vec::<(&str, usize, usize)>::new();
+    sources
+        .iter()
+        .map(|source| {
+            let source = source.as_ref()?;
+            let turn = match taken
+                .iter_mut()
+                .find(|(file, line, _)| *file == source.file && *line == source.line)
+            {
+                Some((_, _, count)) => {
+                    *count += 1;
+                    *count - 1
+                }
+                None => {
+                    taken.push((&source.file, source.line, 1));
+                    0
+                }
+            };
+            if source.display {
+                return Some(if turn == 0 {
+                    source.pieces[0].clone()
+                } else {
+                    String::new()
+                });
+            }
+            source.pieces.get(turn).cloned()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words laid out left to right, ten points a glyph, a line every 14
+    /// points, and a blank line wherever a line is empty.
+    fn page(lines: &[&str]) -> Vec<Word> {
+        let mut words = Vec::new();
+        for (line, text) in lines.iter().enumerate() {
+            let mut x = 0.0;
+            for word in text.split_whitespace() {
+                let width = word.chars().count() as f32 * 10.0;
+                words.push(Word {
+                    text: word.to_owned(),
+                    x,
+                    y: line as f32 * 14.0,
+                    width,
+                    height: 12.0,
+                    line,
+                });
+                x += width + 10.0;
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn lines_run_together_and_paragraphs_stay_apart() {
+        let words = page(&[
+            "The e\u{FB03}cient pipe-",
+            "line runs. It is Well-",
+            "Known.",
+            "",
+            "A new paragraph.",
+        ]);
+        assert_eq!(
+            join(&words, &[]),
+            "The efficient pipeline runs. It is Well- Known.\n\nA new paragraph."
+        );
+    }
+
+    #[test]
+    fn a_word_broken_over_a_page_turn_is_whole_again() {
+        let first = page(&["the last line of a pipe-"]);
+        let second = page(&["line, and then Well-"]);
+        let third = page(&["Known names."]);
+        let pages = [&first, &second, &third]
+            .into_iter()
+            .map(|words| (words.as_slice(), join(words, &[])));
+        assert_eq!(
+            join_pages(pages),
+            "the last line of a pipeline, and then Well-\n\nKnown names."
+        );
+    }
+
+    #[test]
+    fn a_replacement_stands_in_for_its_run() {
+        let words = page(&["Let α ∈ R be fixed, and", "E = mc² hold."]);
+        let runs = math_runs(&words);
+        assert_eq!(runs, vec![1..4, 7..10]);
+
+        let replaced = vec![
+            (runs[0].clone(), "$\\alpha \\in \\mathbb{R}$".to_owned()),
+            (runs[1].clone(), String::new()),
+        ];
+        assert_eq!(
+            join(&words, &replaced),
+            "Let $\\alpha \\in \\mathbb{R}$ be fixed, and hold."
+        );
+    }
+
+    #[test]
+    fn inline_pieces_are_shared_in_turn_and_a_display_goes_once() {
+        let inline = |line: usize| {
+            Some(MathSource {
+                file: "main.tex".into(),
+                line,
+                display: false,
+                pieces: vec!["$a$".into(), "$b$".into()],
+            })
+        };
+        let display = Some(MathSource {
+            file: "main.tex".into(),
+            line: 9,
+            display: true,
+            pieces: vec!["\\begin{equation}\nE\n\\end{equation}".into()],
+        });
+        let assigned = assign(&[
+            inline(3),
+            None,
+            inline(3),
+            inline(3),
+            display.clone(),
+            display,
+        ]);
+        assert_eq!(
+            assigned,
+            vec![
+                Some("$a$".into()),
+                None,
+                Some("$b$".into()),
+                None,
+                Some("\\begin{equation}\nE\n\\end{equation}".into()),
+                Some(String::new()),
+            ]
+        );
+    }
+}
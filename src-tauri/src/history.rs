@@ -0,0 +1,266 @@
+//! When did this sentence get into the paper, and when did it leave.
+//!
+//! Every stored version of a project is searched, oldest first. Versions share
+//! most of their files, and the object store already knows which: a file is
+//! named by the hash of its contents, so each distinct content is read and
+//! searched once however many versions hold it, and a hundred snapshots of a
+//! thesis cost little more than the chapters that actually changed.
+//!
+//! Figures and other binary files are skipped by not being UTF-8, which is
+//! cheaper and more reliable than a list of extensions.
+
+use std::{collections::HashMap, path::Path};
+
+use crate::{
+    model::{HistoryMatch, HistorySearch, HistorySighting, HistoryVersion, SnapshotSummary},
+    search::Query,
+    snapshot::{self, StoredFile},
+};
+
+/// Matches kept per version. The count goes on past it.
+const MATCHES_PER_VERSION: usize = 20;
+/// Larger than any chapter anyone writes by hand. Something bigger is data.
+const MOST_FILE_BYTES: i64 = 4 * 1024 * 1024;
+/// How much of a line is shown beside a match.
+const LINE_CHARACTERS: usize = 200;
+
+/// Searches `versions`, which must be oldest first, each with its manifest.
+pub fn search(
+    versions: Vec<(SnapshotSummary, Vec<StoredFile>)>,
+    objects: &Path,
+    query: &Query,
+) -> HistorySearch {
+    let mut found = HashMap::<String, Found>::new();
+    let versions = versions
+        .into_iter()
+        .map(|(snapshot, manifest)| {
+            let mut count = 0;
+            let mut matches = Vec::new();
+            for file in manifest {
+                let here = found
+                    .entry(file.object.clone())
+                    .or_insert_with(|| positions(objects, &file, query));
+                count += here.count;
+                let room = MATCHES_PER_VERSION - matches.len();
+                matches.extend(
+                    here.positions
+                        .iter()
+                        .take(room)
+                        .map(|position| HistoryMatch {
+                            path: file.path.clone(),
+                            line: position.line,
+                            column: position.column,
+                            text: position.text.clone(),
+                        }),
+                );
+            }
+            HistoryVersion {
+                snapshot,
+                count,
+                matches,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let sighting = |version: &HistoryVersion| {
+        Some(HistorySighting {
+            snapshot: version.snapshot.clone(),
+            at: version.matches.first()?.clone(),
+        })
+    };
+    let first = versions.iter().position(|version| version.count > 0);
+    let last = versions.iter().rposition(|version| version.count > 0);
+    HistorySearch {
+        first_seen: first.and_then(|index| sighting(&versions[index])),
+        last_seen: last.and_then(|index| sighting(&versions[index])),
+        removed_in: last
+            .and_then(|index| versions.get(index + 1))
+            .map(|version| version.snapshot.clone()),
+        versions,
+    }
+}
+
+/// Everything `query` matched in one file's contents, whichever versions hold
+/// it: how many times, and where the first few were. No version shows more than
+/// the first few, so no more are kept for it to choose from.
+#[derive(Debug, Default)]
+struct Found {
+    count: usize,
+    positions: Vec<Position>,
+}
+
+/// One match in one file's contents.
+#[derive(Debug, Clone)]
+struct Position {
+    line: usize,
+    column: usize,
+    text: String,
+}
+
+/// Where `query` matches in a stored file. Nothing for a file that is too big,
+/// not text, or missing from the store.
+fn positions(objects: &Path, file: &StoredFile, query: &Query) -> Found {
+    if file.byte_size > MOST_FILE_BYTES {
+        return Found::default();
+    }
+    let Ok(contents) = std::fs::read_to_string(snapshot::object_path(objects, &file.object)) else {
+        return Found::default();
+    };
+    locate(&contents, query)
+}
+
+fn locate(contents: &str, query: &Query) -> Found {
+    let starts = std::iter::once(0)
+        .chain(contents.match_indices('\n').map(|(index, _)| index + 1))
+        .collect::<Vec<_>>();
+    let mut found = Found::default();
+    for matched in query.pattern().find_iter(contents) {
+        if matched.is_empty() {
+            continue;
+        }
+        found.count += 1;
+        // Past the ones kept, a match is only counted: no line to find, and
+        // no copy of it made.
+        if found.positions.len() == MATCHES_PER_VERSION {
+            continue;
+        }
+        // The last line starting at or before the match.
+        let line = starts.partition_point(|start| *start <= matched.start()) - 1;
+        let start = starts[line];
+        let end = starts.get(line + 1).map_or(contents.len(), |next| next - 1);
+        found.positions.push(Position {
+            line: line + 1,
+            column: contents[start..matched.start()].chars().count() + 1,
+            text: contents[start..end]
+                .trim()
+                .chars()
+                .take(LINE_CHARACTERS)
+                .collect(),
+        });
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::SearchOptions;
+
+    fn version(id: i64, revision: &str) -> SnapshotSummary {
+        SnapshotSummary {
+            id,
+            project_id: 1,
+            revision: revision.into(),
+            title: format!("Version {id}"),
+            body: None,
+            created_at: id,
+            file_count: 1,
+            byte_size: 0,
+        }
+    }
+
+    /// Puts `contents` in the store as `object`, and names it in a manifest.
+    fn stored(objects: &Path, object: &str, path: &str, contents: &str) -> StoredFile {
+        let target = snapshot::object_path(objects, object);
+        std::fs::create_dir_all(target.parent().unwrap()).unwrap();
+        std::fs::write(&target, contents).unwrap();
+        StoredFile {
+            path: path.into(),
+            object: object.into(),
+            byte_size: contents.len() as i64,
+        }
+    }
+
+    #[test]
+    fn a_phrase_is_traced_from_where_it_appeared_to_where_it_went() {
+        let directory = tempfile::tempdir().unwrap();
+        let objects = directory.path();
+        let before = stored(objects, "aa01", "main.tex", "\\section{Intro}\nWe begin.\n");
+        let claimed = stored(
+            objects,
+            "aa02",
+            "intro.tex",
+            "\\section{Intro}\n  Our method is optimal.\n",
+        );
+        let chapter = stored(objects, "aa03", "chapter.tex", "It is optimal, as shown.\n");
+        let hedged = stored(
+            objects,
+            "aa04",
+            "intro2.tex",
+            "Our method is often better.\n",
+        );
+
+        let versions = vec![
+            (version(1, "a"), vec![before.clone()]),
+            (version(2, "b"), vec![before.clone(), claimed.clone()]),
+            (version(3, "c"), vec![chapter.clone(), claimed]),
+            (version(4, "d"), vec![chapter, hedged]),
+        ];
+        let query = Query::new("optimal", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        let history = search(versions, objects, &query);
+
+        let counts = history
+            .versions
+            .iter()
+            .map(|version| version.count)
+            .collect::<Vec<_>>();
+        assert_eq!(counts, vec![0, 1, 2, 1]);
+
+        let first = history.first_seen.unwrap();
+        assert_eq!(first.snapshot.id, 2);
+        assert_eq!(
+            (first.at.path.as_str(), first.at.line, first.at.column),
+            ("intro.tex", 2, 17)
+        );
+        assert_eq!(first.at.text, "Our method is optimal.");
+
+        let last = history.last_seen.unwrap();
+        assert_eq!(
+            (last.snapshot.id, last.at.path.as_str()),
+            (4, "chapter.tex")
+        );
+        assert_eq!(history.removed_in, None);
+    }
+
+    #[test]
+    fn a_phrase_that_left_says_which_version_removed_it() {
+        let directory = tempfile::tempdir().unwrap();
+        let objects = directory.path();
+        let claimed = stored(objects, "bb01", "main.tex", "Our method is optimal.\n");
+        let hedged = stored(objects, "bb02", "main.tex", "Our method is usually good.\n");
+        let query = Query::new(
+            r"optimal|best",
+            SearchOptions {
+                regex: true,
+                ..SearchOptions::default()
+            },
+        )
+        .unwrap()
+        .unwrap();
+
+        let history = search(
+            vec![
+                (version(1, "a"), vec![claimed]),
+                (version(2, "b"), vec![hedged]),
+            ],
+            objects,
+            &query,
+        );
+        assert_eq!(history.last_seen.unwrap().snapshot.id, 1);
+        assert_eq!(history.removed_in.unwrap().id, 2);
+    }
+
+    #[test]
+    fn every_match_is_counted_but_only_the_first_few_are_kept() {
+        let line = "optimal ".repeat(MATCHES_PER_VERSION + 5);
+        let query = Query::new("optimal", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        let found = locate(&line, &query);
+        assert_eq!(found.count, MATCHES_PER_VERSION + 5);
+        assert_eq!(found.positions.len(), MATCHES_PER_VERSION);
+        assert_eq!(found.positions[1].column, 9);
+    }
+}
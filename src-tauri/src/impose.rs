@@ -0,0 +1,490 @@
+//! Drafts set up for paper: a range of pages, several pages to a sheet, a
+//! folded booklet, crop marks.
+//!
+//! The artifact is only ever read. A new PDF is written beside it in memory,
+//! and each page it takes is carried over as a form XObject: the page's own
+//! drawing and resources, grafted across once and then placed on a sheet with
+//! a matrix, the way `pdfpages` and `mutool` do it. Nothing is rasterised, so
+//! text stays sharp and searchable on the sheet. A plain range with no marks
+//! needs no placing at all, and its pages are grafted whole, links included.
+//!
+//! [`plan`] says where every page goes, and is where the layouts are written
+//! down; [`write`] draws what it says, on a render worker, where MuPDF lives.
+
+use std::{fmt::Write as _, io::Write as _};
+
+use mupdf::{
+    Buffer,
+    pdf::{PdfDocument, PdfGraftMap, PdfObject},
+};
+
+use crate::{
+    error::{AppError, AppResult},
+    model::{PrintLayout, PrintOptions},
+    render::{self, mupdf_error},
+};
+
+/// How far a crop mark stands off the corner it marks, in points.
+const MARK_OFFSET: f32 = 3.0;
+/// How long each mark is.
+const MARK_LENGTH: f32 = 12.0;
+/// The room kept clear around a page so its marks land on the sheet rather
+/// than on its neighbour.
+const MARK_SPACE: f32 = MARK_OFFSET + MARK_LENGTH + 3.0;
+/// What a page without a readable box is taken to be: US letter, which is
+/// also TeX's own default.
+const FALLBACK_BOX: [f32; 4] = [0.0, 0.0, 612.0, 792.0];
+
+/// One side of one sheet of paper, in points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sheet {
+    pub width: f32,
+    pub height: f32,
+    pub placed: Vec<Placed>,
+}
+
+/// A page on a sheet: which one, where its lower-left corner goes, and how
+/// much it is shrunk to fit. PDF's own orientation, so `y` counts up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Placed {
+    /// 0-based, in the source document.
+    pub page: usize,
+    pub x: f32,
+    pub y: f32,
+    pub scale: f32,
+}
+
+/// Reads a page range as a print dialogue takes it: `1-4, 7, 10-`, 1-based
+/// and inclusive, with an open end running to the last page. Empty means every
+/// page. Pages come back 0-based, in the order asked for, repeats kept.
+pub fn pages(range: &str, count: usize) -> AppResult<Vec<usize>> {
+    if range.trim().is_empty() {
+        return Ok((0..count).collect());
+    }
+    let invalid = |part: &str| AppError::InvalidInput(format!("\"{part}\" is not a page range"));
+    let number = |text: &str, part: &str| -> AppResult<usize> {
+        let page = text.trim().parse::<usize>().map_err(|_| invalid(part))?;
+        if page == 0 || page > count {
+            return Err(AppError::InvalidInput(format!(
+                "page {page} is not in this document, which has {count}"
+            )));
+        }
+        Ok(page - 1)
+    };
+
+    let mut pages = Vec::new();
+    for part in range
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        match part.split_once('-') {
+            None => pages.push(number(part, part)?),
+            Some((first, last)) => {
+                let first = if first.trim().is_empty() {
+                    0
+                } else {
+                    number(first, part)?
+                };
+                let last = if last.trim().is_empty() {
+                    count.checked_sub(1).ok_or_else(|| invalid(part))?
+                } else {
+                    number(last, part)?
+                };
+                if last < first {
+                    return Err(invalid(part));
+                }
+                pages.extend(first..=last);
+            }
+        }
+    }
+    Ok(pages)
+}
+
+/// Where each of `pages` goes. `size` gives any page's width and height; the
+/// sheet is cut to fit the first, since a draft is one size throughout.
+///
+/// Two up and the booklet turn a page's sheet on its side and put two pages
+/// across it; four up keeps the sheet and halves each side. A booklet's pages
+/// are ordered for folding the printed stack down the middle once: printed
+/// both sides and flipped on the short edge, sheet by sheet, it reads in order.
+/// Its length is made up to a multiple of four with blank pages at the end.
+pub fn plan(
+    pages: &[usize],
+    size: impl Fn(usize) -> (f32, f32),
+    layout: PrintLayout,
+    crop_marks: bool,
+) -> Vec<Sheet> {
+    let Some(&first) = pages.first() else {
+        return Vec::new();
+    };
+    let (width, height) = size(first);
+    let space = if crop_marks { MARK_SPACE } else { 0.0 };
+
+    // Each cell is (x, y, width, height), listed in reading order.
+    let (sheet_width, sheet_height, cells) = match layout {
+        PrintLayout::Single => {
+            let (width, height) = (width + 2.0 * space, height + 2.0 * space);
+            (width, height, vec![(0.0, 0.0, width, height)])
+        }
+        PrintLayout::TwoUp | PrintLayout::Booklet => (
+            height,
+            width,
+            vec![
+                (0.0, 0.0, height / 2.0, width),
+                (height / 2.0, 0.0, height / 2.0, width),
+            ],
+        ),
+        PrintLayout::FourUp => {
+            let (half_width, half_height) = (width / 2.0, height / 2.0);
+            (
+                width,
+                height,
+                vec![
+                    (0.0, half_height, half_width, half_height),
+                    (half_width, half_height, half_width, half_height),
+                    (0.0, 0.0, half_width, half_height),
+                    (half_width, 0.0, half_width, half_height),
+                ],
+            )
+        }
+    };
+
+    let slots: Vec<Option<usize>> = match layout {
+        PrintLayout::Booklet => {
+            let length = pages.len().div_ceil(4) * 4;
+            let at = |index: usize| pages.get(index).copied();
+            (0..length / 4)
+                .flat_map(|sheet| {
+                    let (low, high) = (2 * sheet, length - 1 - 2 * sheet);
+                    // Front, outside first: the last page beside the first.
+                    // Back: the second beside the second to last.
+                    [at(high), at(low), at(low + 1), at(high - 1)]
+                })
+                .collect()
+        }
+        _ => pages.iter().copied().map(Some).collect(),
+    };
+
+    slots
+        .chunks(cells.len())
+        .map(|slots| Sheet {
+            width: sheet_width,
+            height: sheet_height,
+            placed: slots
+                .iter()
+                .zip(&cells)
+                .filter_map(|(slot, &(x, y, cell_width, cell_height))| {
+                    let page = (*slot)?;
+                    let (width, height) = size(page);
+                    let scale = ((cell_width - 2.0 * space) / width)
+                        .min((cell_height - 2.0 * space) / height)
+                        .min(1.0);
+                    Some(Placed {
+                        page,
+                        x: x + (cell_width - width * scale) / 2.0,
+                        y: y + (cell_height - height * scale) / 2.0,
+                        scale,
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// The drawing for crop marks around a page placed at `x`, `y` and drawn
+/// `width` by `height`: two short hairlines off each corner, in line with its
+/// edges, so a guillotine can be lined up on them.
+fn crop_marks(x: f32, y: f32, width: f32, height: f32) -> String {
+    let mut drawing = String::from("q 0 G 0.25 w\n");
+    for (corner_x, corner_y, outward_x, outward_y) in [
+        (x, y, -1.0, -1.0),
+        (x + width, y, 1.0, -1.0),
+        (x, y + height, -1.0, 1.0),
+        (x + width, y + height, 1.0, 1.0),
+    ] {
+        let (near_x, far_x) = (
+            corner_x + outward_x * MARK_OFFSET,
+            corner_x + outward_x * (MARK_OFFSET + MARK_LENGTH),
+        );
+        let (near_y, far_y) = (
+            corner_y + outward_y * MARK_OFFSET,
+            corner_y + outward_y * (MARK_OFFSET + MARK_LENGTH),
+        );
+        let _ = writeln!(drawing, "{near_x} {corner_y} m {far_x} {corner_y} l S");
+        let _ = writeln!(drawing, "{corner_x} {near_y} m {corner_x} {far_y} l S");
+    }
+    drawing.push_str("Q\n");
+    drawing
+}
+
+/// The new PDF, as bytes.
+pub fn write(source: &PdfDocument, options: &PrintOptions) -> AppResult<Vec<u8>> {
+    let count = render::page_count(source)?;
+    let pages = pages(&options.pages, count)?;
+    if pages.is_empty() {
+        return Err(AppError::InvalidInput(
+            "there are no pages to export".into(),
+        ));
+    }
+
+    let mut target =
+        PdfDocument::new().map_err(|error| mupdf_error("could not start a PDF", error))?;
+    let mut graft = target
+        .new_graft_map()
+        .map_err(|error| mupdf_error("could not start a PDF", error))?;
+
+    if options.layout == PrintLayout::Single && !options.crop_marks {
+        for (at, &page) in pages.iter().enumerate() {
+            graft
+                .graft_page(at as i32, source, page as i32)
+                .map_err(|error| mupdf_error("could not copy a page", error))?;
+        }
+    } else {
+        let boxes = (0..count)
+            .map(|index| page_box(source, index))
+            .collect::<AppResult<Vec<_>>>()?;
+        let size = |page: usize| {
+            let [x0, y0, x1, y1] = boxes[page];
+            (x1 - x0, y1 - y0)
+        };
+        let sheets = plan(&pages, size, options.layout, options.crop_marks);
+        for (at, sheet) in sheets.iter().enumerate() {
+            let page = draw_sheet(&mut target, &mut graft, source, sheet, &boxes, options)?;
+            target
+                .insert_page(at as i32, &page)
+                .map_err(|error| mupdf_error("could not add a sheet", error))?;
+        }
+    }
+
+    let mut bytes = Vec::new();
+    target
+        .write_to(&mut bytes)
+        .map_err(|error| mupdf_error("could not write the PDF", error))?;
+    Ok(bytes)
+}
+
+/// One sheet's page object, every page on it drawn from a form of its own.
+fn draw_sheet(
+    target: &mut PdfDocument,
+    graft: &mut PdfGraftMap,
+    source: &PdfDocument,
+    sheet: &Sheet,
+    boxes: &[[f32; 4]],
+    options: &PrintOptions,
+) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not lay out a sheet", error);
+    let mut xobjects = target.new_dict().map_err(failed)?;
+    let mut drawing = String::new();
+    for (slot, placed) in sheet.placed.iter().enumerate() {
+        let [x0, y0, x1, y1] = boxes[placed.page];
+        let name = format!("P{slot}");
+        xobjects
+            .dict_put(
+                name.as_str(),
+                form(target, graft, source, placed.page, boxes[placed.page])?,
+            )
+            .map_err(failed)?;
+        // The form is drawn in the source's own coordinates, so its box's
+        // corner is moved to where the page goes.
+        let scale = placed.scale;
+        let _ = writeln!(
+            drawing,
+            "q {scale} 0 0 {scale} {} {} cm /{name} Do Q",
+            placed.x - scale * x0,
+            placed.y - scale * y0,
+        );
+        if options.crop_marks {
+            drawing.push_str(&crop_marks(
+                placed.x,
+                placed.y,
+                (x1 - x0) * scale,
+                (y1 - y0) * scale,
+            ));
+        }
+    }
+
+    let mut resources = target.new_dict().map_err(failed)?;
+    resources.dict_put("XObject", xobjects).map_err(failed)?;
+    let dictionary = target.new_dict().map_err(failed)?;
+    let contents = stream(target, dictionary, drawing.as_bytes())?;
+
+    let mut page = target.new_dict().map_err(failed)?;
+    page.dict_put("Type", target.new_name("Page").map_err(failed)?)
+        .map_err(failed)?;
+    page.dict_put(
+        "MediaBox",
+        rectangle(target, [0.0, 0.0, sheet.width, sheet.height])?,
+    )
+    .map_err(failed)?;
+    page.dict_put("Resources", resources).map_err(failed)?;
+    page.dict_put("Contents", contents).map_err(failed)?;
+    target.add_object(&page).map_err(failed)
+}
+
+/// A source page as a form XObject in `target`: its content streams run
+/// together, its resources grafted across, and its visible box as the form's.
+fn form(
+    target: &mut PdfDocument,
+    graft: &mut PdfGraftMap,
+    source: &PdfDocument,
+    index: usize,
+    bounds: [f32; 4],
+) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not copy a page", error);
+    let page = source.find_page(index as i32).map_err(failed)?;
+
+    let mut drawing = Vec::new();
+    if let Some(contents) = dictionary(&page, "Contents") {
+        // One stream, or an array of them to be read as one.
+        if contents.is_array().map_err(failed)? {
+            for part in 0..contents.len().map_err(failed)? {
+                if let Some(part) = contents.get_array(part as i32).map_err(failed)? {
+                    drawing.extend(part.read_stream().map_err(failed)?);
+                    drawing.push(b'\n');
+                }
+            }
+        } else {
+            drawing.extend(contents.read_stream().map_err(failed)?);
+        }
+    }
+
+    let mut form = target.new_dict().map_err(failed)?;
+    form.dict_put("Type", target.new_name("XObject").map_err(failed)?)
+        .map_err(failed)?;
+    form.dict_put("Subtype", target.new_name("Form").map_err(failed)?)
+        .map_err(failed)?;
+    form.dict_put("BBox", rectangle(target, bounds)?)
+        .map_err(failed)?;
+    if let Some(resources) = inherited(&page, "Resources") {
+        form.dict_put("Resources", graft.graft_object(&resources).map_err(failed)?)
+            .map_err(failed)?;
+    }
+    stream(target, form, &drawing)
+}
+
+/// `dictionary` made an indirect object holding `bytes` as its stream.
+pub fn stream(
+    target: &mut PdfDocument,
+    dictionary: PdfObject,
+    bytes: &[u8],
+) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not write a stream", error);
+    let mut object = target.add_object(&dictionary).map_err(failed)?;
+    let mut buffer = Buffer::with_capacity(bytes.len());
+    buffer.write_all(bytes)?;
+    object.write_stream_buffer(&buffer).map_err(failed)?;
+    Ok(object)
+}
+
+pub fn rectangle(target: &mut PdfDocument, corners: [f32; 4]) -> AppResult<PdfObject> {
+    let failed = |error| mupdf_error("could not write a rectangle", error);
+    let mut array = target.new_array().map_err(failed)?;
+    for value in corners {
+        array
+            .array_push(target.new_real(value).map_err(failed)?)
+            .map_err(failed)?;
+    }
+    Ok(array)
+}
+
+/// The part of a page that shows: its crop box, or its media box without one.
+pub fn page_box(source: &PdfDocument, index: usize) -> AppResult<[f32; 4]> {
+    let page = source
+        .find_page(index as i32)
+        .map_err(|error| mupdf_error("could not read a page", error))?;
+    let corners = |key| {
+        let array = inherited(&page, key)?;
+        let mut corners = [0.0; 4];
+        for (at, corner) in corners.iter_mut().enumerate() {
+            *corner = array.get_array(at as i32).ok()??.as_float().ok()?;
+        }
+        let [x0, y0, x1, y1] = corners;
+        Some([x0.min(x1), y0.min(y1), x0.max(x1), y0.max(y1)])
+    };
+    Ok(corners("CropBox")
+        .or_else(|| corners("MediaBox"))
+        .unwrap_or(FALLBACK_BOX))
+}
+
+/// A page's own value for `key`, or the nearest one above it in the page tree:
+/// the boxes and the resources can be set once for every page under a node.
+pub fn inherited(page: &PdfObject, key: &str) -> Option<PdfObject> {
+    if let Some(value) = dictionary(page, key) {
+        return Some(value);
+    }
+    let mut node = dictionary(page, "Parent")?;
+    // A tree deeper than this is a cycle, which a malformed PDF can contain.
+    for _ in 0..32 {
+        if let Some(value) = dictionary(&node, key) {
+            return Some(value);
+        }
+        node = dictionary(&node, "Parent")?;
+    }
+    None
+}
+
+pub fn dictionary(object: &PdfObject, key: &str) -> Option<PdfObject> {
+    object.get_dict(key).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_read_the_way_a_print_dialogue_reads_them() {
+        assert_eq!(pages("", 3).unwrap(), vec![0, 1, 2]);
+        assert_eq!(pages("1-3, 5, 8-", 9).unwrap(), vec![0, 1, 2, 4, 7, 8]);
+        assert_eq!(pages("-2,2", 4).unwrap(), vec![0, 1, 1]);
+        assert!(matches!(pages("4-2", 5), Err(AppError::InvalidInput(_))));
+        assert!(matches!(pages("0", 5), Err(AppError::InvalidInput(_))));
+        assert!(matches!(pages("6", 5), Err(AppError::InvalidInput(_))));
+        assert!(matches!(pages("one", 5), Err(AppError::InvalidInput(_))));
+    }
+
+    /// Six pages make two sheets, both sides printed: eight slots, the last
+    /// two blank. Folded, the stack reads 1 to 6.
+    #[test]
+    fn a_booklet_puts_pages_where_the_fold_will_read_them_in_order() {
+        let a4 = |_| (595.0, 842.0);
+        let sides = plan(&[0, 1, 2, 3, 4, 5], a4, PrintLayout::Booklet, false);
+        let order = sides
+            .iter()
+            .map(|side| side.placed.iter().map(|placed| placed.page + 1).collect())
+            .collect::<Vec<Vec<_>>>();
+        assert_eq!(order, vec![vec![1], vec![2], vec![6, 3], vec![4, 5]]);
+
+        // Landscape, with each page shrunk to fit its half.
+        assert_eq!((sides[0].width, sides[0].height), (842.0, 595.0));
+        // Page 1 is on the right half of the front, where the cover is.
+        let cover = sides[0].placed[0];
+        assert!(cover.x >= 421.0, "{cover:?}");
+        assert!((cover.scale - 595.0 / 842.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn four_up_fills_a_sheet_in_reading_order_and_marks_keep_their_room() {
+        let letter = |_| (612.0, 792.0);
+        let sheets = plan(&[0, 1, 2, 3, 4], letter, PrintLayout::FourUp, false);
+        assert_eq!(sheets.len(), 2);
+        let corners = sheets[0]
+            .placed
+            .iter()
+            .map(|placed| (placed.x, placed.y))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            corners,
+            vec![(0.0, 396.0), (306.0, 396.0), (0.0, 0.0), (306.0, 0.0)]
+        );
+        assert_eq!(sheets[1].placed.len(), 1);
+
+        // A single page with marks grows the sheet rather than shrinking it.
+        let marked = plan(&[0], letter, PrintLayout::Single, true);
+        assert_eq!(marked[0].placed[0].scale, 1.0);
+        assert_eq!(
+            (marked[0].width, marked[0].height),
+            (612.0 + 2.0 * MARK_SPACE, 792.0 + 2.0 * MARK_SPACE)
+        );
+    }
+}
@@ -0,0 +1,283 @@
+//! Finding a `\label` by name, or by what it prints as.
+//!
+//! A reviewer asked about "Table 4" wants Table 4, not every page that mentions
+//! it. LaTeX already knows where it is: every label goes into the `.aux` with
+//! the number it printed and the page it fell on, and hyperref adds its caption
+//! and the anchor it put in the PDF. `publish` keeps that `.aux` beside the
+//! PDF, so the index is always the one for the version on screen, snapshots
+//! included.
+//!
+//! The page in the `.aux` is the printed one, which is not the page of the file
+//! once there is front matter. With hyperref the anchor says exactly where the
+//! label is and MuPDF resolves it; without, the printed number is the best
+//! there is.
+
+use crate::{model::LabelMatch, outline, prose};
+
+/// Matches returned for one query. Past this the query is too loose to be
+/// looking for something in particular.
+const MOST_MATCHES: usize = 20;
+
+/// What a kind of counter prints as. Keys are hyperref's anchor prefixes and
+/// the usual label prefixes, which between them cover most documents.
+const KINDS: &[(&str, &str)] = &[
+    ("figure", "Figure"),
+    ("fig", "Figure"),
+    ("subfigure", "Figure"),
+    ("table", "Table"),
+    ("tab", "Table"),
+    ("tbl", "Table"),
+    ("chapter", "Chapter"),
+    ("chap", "Chapter"),
+    ("ch", "Chapter"),
+    ("section", "Section"),
+    ("sec", "Section"),
+    ("subsection", "Section"),
+    ("subsubsection", "Section"),
+    ("appendix", "Appendix"),
+    ("app", "Appendix"),
+    ("equation", "Equation"),
+    ("eq", "Equation"),
+    ("eqn", "Equation"),
+    ("lstlisting", "Listing"),
+    ("lst", "Listing"),
+    ("listing", "Listing"),
+    ("algorithm", "Algorithm"),
+    ("alg", "Algorithm"),
+    ("theorem", "Theorem"),
+    ("thm", "Theorem"),
+    ("lemma", "Lemma"),
+    ("lem", "Lemma"),
+    ("definition", "Definition"),
+    ("def", "Definition"),
+    ("Item", "Item"),
+];
+
+/// One `\newlabel` from an `.aux`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    /// "3", "2.1", "A".
+    pub number: String,
+    /// The page as printed: "14", or "iv".
+    pub printed_page: String,
+    /// hyperref's: the caption or heading, when the label has one.
+    pub title: Option<String>,
+    /// hyperref's: the named destination in the PDF.
+    pub anchor: Option<String>,
+}
+
+impl Label {
+    /// "Figure 3", or the bare number when the kind cannot be told.
+    pub fn caption(&self) -> String {
+        match self.kind() {
+            Some(kind) if !self.number.is_empty() => format!("{kind} {}", self.number),
+            _ => self.number.clone(),
+        }
+    }
+
+    fn kind(&self) -> Option<&'static str> {
+        let from_anchor = self
+            .anchor
+            .as_deref()
+            .and_then(|anchor| anchor.split('.').next());
+        let from_name = self.name.split_once(':').map(|(prefix, _)| prefix);
+        [from_anchor, from_name]
+            .into_iter()
+            .flatten()
+            .find_map(|prefix| {
+                KINDS
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(prefix))
+                    .map(|(_, kind)| *kind)
+            })
+    }
+}
+
+/// Every label in an `.aux`, in the order LaTeX wrote them.
+pub fn parse(aux: &str) -> Vec<Label> {
+    let characters = aux.chars().collect::<Vec<_>>();
+    let mut labels = Vec::new();
+    let mut index = 0;
+    while let Some(at) = prose::find(&characters, index, "\\newlabel") {
+        index = at + "\\newlabel".len();
+        let Some((outer, end)) = outline::groups(&characters, index, 2) else {
+            continue;
+        };
+        index = end;
+        let name = outer[0].trim().to_owned();
+        // cleveref writes a second label for each of its own, in a form meant
+        // for it alone.
+        if name.is_empty() || name.ends_with("@cref") {
+            continue;
+        }
+        let inner = outer[1].chars().collect::<Vec<_>>();
+        let mut fields = Vec::new();
+        let mut cursor = 0;
+        while let Some((field, next)) = outline::groups(&inner, cursor, 1) {
+            fields.extend(field);
+            cursor = next;
+        }
+        let [number, page, rest @ ..] = fields.as_slice() else {
+            continue;
+        };
+        let optional = |text: Option<&String>| {
+            text.map(|text| outline::plain(text))
+                .filter(|text| !text.is_empty())
+        };
+        labels.push(Label {
+            name,
+            number: outline::plain(number),
+            printed_page: outline::plain(page),
+            title: optional(rest.first()),
+            anchor: rest.get(1).map(|anchor| anchor.trim().to_owned()),
+        });
+    }
+    labels
+}
+
+/// The labels that best match `query`, best first, with how well each did.
+pub fn lookup(labels: &[Label], query: &str) -> Vec<(&Label, u32)> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut scored = labels
+        .iter()
+        .filter_map(|label| {
+            let best = [
+                Some(label.name.clone()),
+                Some(label.caption()),
+                label.title.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .filter_map(|candidate| score(&query, &candidate.to_lowercase()))
+            .max()?;
+            Some((label, best))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so labels that score the same stay in document order.
+    scored.sort_by(|left, right| right.1.cmp(&left.1));
+    scored.truncate(MOST_MATCHES);
+    scored
+}
+
+/// How well `candidate` answers `query`, both already lower case. Whole, then
+/// the start, then anywhere, then its letters in order; `None` when not even
+/// that.
+fn score(query: &str, candidate: &str) -> Option<u32> {
+    if candidate == query {
+        return Some(1000);
+    }
+    let shorter = |base: u32| base.saturating_sub(candidate.len().min(200) as u32);
+    if candidate.starts_with(query) {
+        return Some(shorter(800));
+    }
+    if let Some(at) = candidate.find(query) {
+        return Some(shorter(600).saturating_sub(at.min(100) as u32));
+    }
+    // Letters in order, each gap costing a little: `fpipe` finds `fig:pipeline`.
+    let mut gaps = 0;
+    let mut candidates = candidate.chars();
+    for wanted in query.chars().filter(|character| !character.is_whitespace()) {
+        let mut skipped = 0;
+        loop {
+            let next = candidates.next()?;
+            if next == wanted {
+                break;
+            }
+            skipped += 1;
+        }
+        gaps += skipped.min(10);
+    }
+    Some(shorter(400).saturating_sub(gaps * 5).max(1))
+}
+
+/// A match ready to show. `resolved` is the page MuPDF found the anchor on,
+/// when it could; otherwise an arabic printed number is taken at its word.
+pub fn to_match(label: &Label, resolved: Option<usize>) -> LabelMatch {
+    LabelMatch {
+        name: label.name.clone(),
+        caption: label.caption(),
+        title: label.title.clone(),
+        printed_page: label.printed_page.clone(),
+        page: resolved.or_else(|| label.printed_page.parse().ok()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AUX: &str = "\\relax\n\
+        \\newlabel{sec:intro}{{1}{1}{Introduction}{section.1}{}}\n\
+        \\newlabel{fig:pipeline}{{3}{14}{The \\emph {whole} pipeline}{figure.caption.5}{}}\n\
+        \\newlabel{fig:pipeline@cref}{{[figure][3][]3}{[1][14][]14}}\n\
+        \\newlabel{tab:results}{{4}{17}{Results}{table.caption.9}{}}\n\
+        \\newlabel{eq:energy}{{2.1}{9}}\n\
+        \\newlabel{foo}{{A}{iv}}\n";
+
+    #[test]
+    fn labels_are_read_with_their_numbers_pages_and_captions() {
+        let labels = parse(AUX);
+        let names = labels
+            .iter()
+            .map(|label| label.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "sec:intro",
+                "fig:pipeline",
+                "tab:results",
+                "eq:energy",
+                "foo"
+            ]
+        );
+
+        let pipeline = &labels[1];
+        assert_eq!(pipeline.caption(), "Figure 3");
+        assert_eq!(pipeline.printed_page, "14");
+        assert_eq!(pipeline.title.as_deref(), Some("The whole pipeline"));
+        assert_eq!(pipeline.anchor.as_deref(), Some("figure.caption.5"));
+
+        // Without hyperref, the label's own prefix says what it numbers.
+        assert_eq!(labels[3].caption(), "Equation 2.1");
+        assert_eq!(labels[3].anchor, None);
+        assert_eq!(labels[4].caption(), "A");
+    }
+
+    #[test]
+    fn a_lookup_finds_a_label_by_name_by_what_it_prints_or_by_letters() {
+        let labels = parse(AUX);
+        let best = |query: &str| {
+            lookup(&labels, query)
+                .first()
+                .map(|(label, _)| label.name.clone())
+        };
+
+        assert_eq!(best("fig:pipeline").as_deref(), Some("fig:pipeline"));
+        assert_eq!(best("Table 4").as_deref(), Some("tab:results"));
+        assert_eq!(best("fpipe").as_deref(), Some("fig:pipeline"));
+        assert_eq!(best("results").as_deref(), Some("tab:results"));
+        assert_eq!(best("zzz"), None);
+        assert!(lookup(&labels, "  ").is_empty());
+    }
+
+    #[test]
+    fn a_match_prefers_the_resolved_page_over_the_printed_one() {
+        let labels = parse(AUX);
+        let printed = to_match(&labels[1], None);
+        assert_eq!(printed.page, Some(14));
+        assert_eq!(printed.caption, "Figure 3");
+
+        let resolved = to_match(&labels[1], Some(18));
+        assert_eq!(resolved.page, Some(18));
+        assert_eq!(
+            to_match(&labels[4], None).page,
+            None,
+            "iv is not a page of the file"
+        );
+    }
+}
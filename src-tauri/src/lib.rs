@@ -1,22 +1,40 @@
 mod anchors;
+mod annotations;
 mod appearance;
 mod build;
+mod bundle;
+mod cache;
+mod changes;
 mod commands;
+mod compliance;
 mod database;
 mod diagnostics;
 mod documents;
 mod editor;
 mod error;
+mod extract;
 mod files;
 mod frontmatter;
+mod history;
+mod impose;
+mod labels;
+mod library;
+mod lint;
 mod model;
+mod outline;
 mod peek;
+mod position;
+mod preflight;
 mod preview;
+mod prose;
 mod protocol;
 mod render;
 mod runner;
+mod search;
 mod snapshot;
 mod sources;
+mod spelling;
+mod statistics;
 mod toolchain;
 mod viewing;
 
@@ -130,6 +148,13 @@ pub fn run() {
                 eprintln!("Press could not put its icon back: {error}");
             }
             sweep_storage(&artifact_root, &work_root, &objects_root, &repository);
+            // After the sweep, so that nothing it removes is extracted first.
+            let indexing = Arc::clone(&repository);
+            tauri::async_runtime::spawn_blocking(move || library::backfill(&indexing));
+            // Cache: a drawn page is one render away from existing again.
+            let page_cache =
+                cache::PageCache::new(cache_root.join("pages"), cache::settings(&repository)?);
+            let thumbnails = cache::ThumbnailCache::new(cache_root.join("thumbnails"));
             let builds = Arc::new(BuildManager::new(
                 Arc::clone(&repository),
                 artifact_root.clone(),
@@ -139,7 +164,11 @@ pub fn run() {
             app.manage(AppState {
                 repository,
                 builds,
-                renderer: Arc::new(RenderPool::with_default_size()),
+                renderer: Arc::new(
+                    RenderPool::with_default_size()
+                        .with_cache(page_cache)
+                        .with_thumbnails(thumbnails),
+                ),
                 artifact_root,
                 objects_root,
                 preview_root,
@@ -162,8 +191,10 @@ pub fn run() {
             commands::resolve_path,
             commands::add_project,
             commands::open_project,
+            commands::save_view_state,
             commands::open_pdf,
             commands::close_pdf,
+            commands::import_embedded,
             commands::close_project,
             commands::build_project,
             commands::rename_project,
@@ -173,18 +204,52 @@ pub fn run() {
             commands::page_layout,
             commands::page_words,
             commands::page_links,
+            commands::preflight,
+            commands::document_outline,
+            commands::find_label,
             commands::open_external,
             commands::peek_source,
+            commands::reading_position,
+            commands::carry_position,
+            commands::selection_text,
             commands::search_document,
+            commands::search_library,
+            commands::search_history,
             commands::create_snapshot,
             commands::list_versions,
             commands::rename_snapshot,
             commands::delete_snapshot,
+            commands::previous_builds,
+            commands::open_previous_build,
             commands::export_artifact,
+            commands::export_text,
+            commands::export_image,
+            commands::export_print,
+            commands::export_with_source,
+            commands::add_annotation,
+            commands::list_annotations,
+            commands::update_annotation,
+            commands::delete_annotation,
+            commands::relocate_annotations,
+            commands::export_annotated,
             commands::get_build_log,
+            commands::lint_ignores,
+            commands::set_lint_ignores,
+            commands::lint_enabled,
+            commands::set_lint_enabled,
+            commands::project_dictionary,
+            commands::add_dictionary_word,
+            commands::remove_dictionary_word,
+            commands::page_constraints,
+            commands::set_page_constraints,
             commands::launch_editor,
             commands::editor_command,
             commands::set_editor_command,
+            commands::kept_builds,
+            commands::set_kept_builds,
+            commands::render_cache,
+            commands::set_render_cache,
+            commands::render_metrics,
             commands::icon_choice,
             commands::set_icon_choice,
             commands::list_presets,
@@ -289,6 +354,9 @@ fn sweep_storage(
     objects_root: &Path,
     repository: &Repository,
 ) {
+    // A ring over the number kept, because the number was lowered since it
+    // filled, is trimmed here; the PDFs that fall out go with the rest below.
+    let _ = repository.prune_previous_builds(build::kept_builds(repository));
     let Ok(retained) = repository.managed_pdf_paths() else {
         return;
     };
@@ -0,0 +1,136 @@
+//! Searching every document in the library at once.
+//!
+//! "Which of our papers defined this notation" is a question about the PDFs,
+//! not the sources: a macro expands, a bibliography is generated, and what a
+//! reader would search for is what ended up on the page. So each artifact's
+//! text is extracted once, when it is published, and kept page by page in an
+//! SQLite full-text table beside the rest of the library. The table is derived
+//! and nothing else reads it; an artifact that was published before it existed
+//! is indexed at startup instead.
+
+use std::path::Path;
+
+use crate::{
+    database::Repository,
+    error::AppResult,
+    model::SnippetPart,
+    render::{self, open, page_count},
+};
+
+/// What marks a match in SQLite's snippet, chosen because no PDF's extracted
+/// text contains them.
+pub const MATCH_START: char = '\u{2}';
+pub const MATCH_END: char = '\u{3}';
+
+/// The text of every page of a PDF, in order.
+pub fn extract(pdf: &Path) -> AppResult<Vec<String>> {
+    let document = open(pdf)?;
+    (0..page_count(&document)?)
+        .map(|index| render::page_text(&document, index))
+        .collect()
+}
+
+/// Extracts and stores the text of one revision of an artifact, replacing
+/// what an earlier build of it left.
+pub fn index(
+    repository: &Repository,
+    artifact_id: i64,
+    revision: i64,
+    pdf: &Path,
+) -> AppResult<()> {
+    let pages = extract(pdf)?;
+    repository.index_text(artifact_id, revision, &pages)
+}
+
+/// Indexes whatever was published before the index existed, or while a failed
+/// extraction left a gap. Runs once, in the background, at startup.
+pub fn backfill(repository: &Repository) {
+    let Ok(missing) = repository.unindexed_artifacts() else {
+        return;
+    };
+    for (artifact_id, revision, pdf) in missing {
+        if let Err(error) = index(repository, artifact_id, revision, &pdf) {
+            eprintln!("Press could not index {}: {error}", pdf.display());
+        }
+    }
+}
+
+/// What was typed, as an FTS5 query: every word must appear, in any order, and
+/// the last one may still be being typed. Each is quoted, so punctuation and
+/// FTS5's own operators are read as text rather than as syntax.
+pub fn query(typed: &str) -> Option<String> {
+    let words = typed
+        .split_whitespace()
+        .map(|word| format!("\"{}\"", word.replace('"', "\"\"")))
+        .collect::<Vec<_>>();
+    let last = words.len().checked_sub(1)?;
+    Some(
+        words
+            .into_iter()
+            .enumerate()
+            .map(|(index, word)| if index == last { word + "*" } else { word })
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// A snippet from SQLite, split at its markers into plain and matched runs.
+pub fn snippet(marked: &str) -> Vec<SnippetPart> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let mut matched = false;
+    for character in marked.chars() {
+        if character == MATCH_START || character == MATCH_END {
+            if !text.is_empty() {
+                parts.push(SnippetPart {
+                    text: std::mem::take(&mut text),
+                    matched,
+                });
+            }
+            matched = character == MATCH_START;
+        } else {
+            text.push(character);
+        }
+    }
+    if !text.is_empty() {
+        parts.push(SnippetPart { text, matched });
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn typed_words_become_a_quoted_query_with_a_prefix_last() {
+        assert_eq!(
+            query("Lipschitz const").as_deref(),
+            Some("\"Lipschitz\" \"const\"*")
+        );
+        // Operators and quotes are only text.
+        assert_eq!(
+            query("a OR \"b").as_deref(),
+            Some("\"a\" \"OR\" \"\"\"b\"*")
+        );
+        assert_eq!(query("  "), None);
+    }
+
+    #[test]
+    fn a_snippet_is_split_into_plain_and_matched_runs() {
+        let parts = snippet("…where \u{2}Lipschitz\u{3} continuity \u{2}holds\u{3}");
+        let runs = parts
+            .iter()
+            .map(|part| (part.text.as_str(), part.matched))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            runs,
+            vec![
+                ("…where ", false),
+                ("Lipschitz", true),
+                (" continuity ", false),
+                ("holds", true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,263 @@
+//! chktex, over the files a LaTeX build actually read.
+//!
+//! A lint stage runs only after a build has succeeded, and only when chktex is
+//! installed: it is advice about a document that compiles, and a document that
+//! does not has better things to report. Its findings join the build's own
+//! diagnostics, tagged [`SOURCE`], so style problems show up in the same panel
+//! as TeX's warnings without being mistaken for them.
+//!
+//! The files come from latexmk's recorder output, the `.fls` file `-recorder`
+//! writes, rather than from a walk of the folder. A folder holds old drafts and
+//! chapters that were commented out, and linting those reports problems in
+//! text nobody will read.
+//!
+//! Suppression is chktex's own: `% chktex 8` on a line and `% chktex-file 8`
+//! anywhere in a file are read by chktex itself. Press adds only the project's
+//! list of warning numbers to leave out, which is passed through as `-n`.
+//!
+//! The stage as a whole can be turned off under [`SETTING`], for the writer
+//! whose venue's template chktex has opinions about on every line.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::LazyLock,
+    time::Duration,
+};
+
+use regex::Regex;
+use tokio::process::Command;
+
+use crate::{
+    database::Repository,
+    diagnostics::{clean, relativize},
+    model::{Diagnostic, Severity},
+    toolchain::{augmented_path, resolve_executable},
+};
+
+/// The tag on every diagnostic chktex produced.
+pub const SOURCE: &str = "chktex";
+/// Whether LaTeX builds are linted at all: `"1"` or `"0"`.
+pub const SETTING: &str = "lint.enabled";
+/// chktex reads a file in a few milliseconds; this is for the one it loops on.
+const TIMEOUT: Duration = Duration::from_secs(20);
+/// Field per finding, colon-separated, message last so that its own colons
+/// survive. `!n` is chktex's spelling of a newline.
+const FORMAT: &str = "%f:%l:%c:%n:%k:%m!n";
+/// The same number of findings a build's own log is cut off at, for the same
+/// reason: past this a list is a wall.
+const MAX_FINDINGS: usize = 200;
+/// Files chktex can say something useful about. Class and style files are
+/// TeX programming, and every warning about them is noise.
+const LINTED_EXTENSIONS: &[&str] = &["tex", "ltx"];
+
+static FINDING: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.+?):(\d+):(\d+):(\d+):(\w+):(.*)$").unwrap());
+
+/// Whether the lint stage runs, as set. On unless turned off: installing
+/// chktex is already asking for it.
+pub fn enabled(repository: &Repository) -> bool {
+    repository
+        .setting(SETTING)
+        .ok()
+        .flatten()
+        .is_none_or(|value| value == "1")
+}
+
+/// Runs chktex over `files`, relative to `directory`: the project files a
+/// build read, from [`used_sources`]. Nothing, when chktex is not installed or
+/// could not be run: a missing linter is not something the build panel needs
+/// to hear about.
+pub async fn run(directory: &Path, files: &[String], ignored: &[u32]) -> Vec<Diagnostic> {
+    let Some(chktex) = resolve_executable("chktex") else {
+        return Vec::new();
+    };
+    if files.is_empty() {
+        return Vec::new();
+    }
+
+    let mut command = Command::new(&chktex);
+    command.current_dir(directory);
+    command.env("PATH", augmented_path(&chktex));
+    // Quiet drops the banner. `-I0` stops chktex following `\input` itself:
+    // every file the build read is already on the command line, and following
+    // them as well reports each finding twice.
+    command.args(["-q", "-I0", "-f", FORMAT]);
+    for warning in ignored {
+        command.arg(format!("-n{warning}"));
+    }
+    command.args(files);
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped()).stderr(Stdio::null());
+    command.kill_on_drop(true);
+
+    // chktex exits non-zero whenever it found something, so the status says
+    // nothing about whether it ran. Only output is read.
+    match tokio::time::timeout(TIMEOUT, command.output()).await {
+        Ok(Ok(output)) => parse(&String::from_utf8_lossy(&output.stdout), directory),
+        _ => Vec::new(),
+    }
+}
+
+/// The project's own LaTeX files among everything a build opened, in the order
+/// it first opened them and relative to `directory`, which is where the build
+/// ran and so what the recorder's relative paths are relative to.
+pub fn used_sources(recorded: &str, directory: &Path) -> Vec<String> {
+    let mut seen = HashSet::new();
+    let mut files = Vec::new();
+    for line in recorded.lines() {
+        let Some(path) = line.strip_prefix("INPUT ") else {
+            continue;
+        };
+        let linted = Path::new(path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                LINTED_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+            });
+        if !linted {
+            continue;
+        }
+        // `relativize` keeps a path outside the project absolute, which is
+        // exactly the distribution's own files this must leave out.
+        let Some(relative) = relativize(path, directory) else {
+            continue;
+        };
+        if PathBuf::from(&relative).is_absolute() || relative.starts_with("..") {
+            continue;
+        }
+        if seen.insert(relative.clone()) {
+            files.push(relative);
+        }
+    }
+    files
+}
+
+/// Reads chktex's output in [`FORMAT`].
+///
+/// Everything is a warning, including what chktex itself calls an error. The
+/// document compiled; nothing a linter says about it is an error in the sense
+/// the panel uses, which is that there is no PDF.
+fn parse(output: &str, directory: &Path) -> Vec<Diagnostic> {
+    let mut seen = HashSet::new();
+    let mut findings = Vec::new();
+    for line in output.lines() {
+        let Some(capture) = FINDING.captures(line.trim_end()) else {
+            continue;
+        };
+        let message = clean(&capture[6]);
+        if message.is_empty() {
+            continue;
+        }
+        let diagnostic = Diagnostic {
+            file: relativize(&capture[1], directory),
+            line: capture[2].parse().ok(),
+            column: capture[3].parse().ok(),
+            severity: Severity::Warning,
+            // The number is what goes on the ignore list, so it is shown.
+            message: format!("{message} (warning {})", &capture[4]),
+            source: Some(SOURCE.to_owned()),
+        };
+        if seen.insert(diagnostic.clone()) {
+            findings.push(diagnostic);
+        }
+        if findings.len() == MAX_FINDINGS {
+            break;
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_projects_own_latex_is_linted() {
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path();
+        std::fs::create_dir(root.join("chapters")).unwrap();
+        for file in ["main.tex", "chapters/intro.tex", "chapters/results.tex"] {
+            std::fs::write(root.join(file), "").unwrap();
+        }
+        let recorded = format!(
+            "PWD {root}\n\
+             INPUT /usr/share/texlive/texmf-dist/tex/latex/base/article.cls\n\
+             INPUT ./main.tex\n\
+             INPUT main.tex\n\
+             INPUT ./chapters/intro.tex\n\
+             INPUT ./preamble.sty\n\
+             INPUT {root}/chapters/results.tex\n\
+             OUTPUT main.pdf\n\
+             INPUT /usr/share/texlive/texmf-dist/tex/latex/tools/verbatim.tex\n",
+            root = root.display()
+        );
+
+        assert_eq!(
+            used_sources(&recorded, root),
+            vec!["main.tex", "chapters/intro.tex", "chapters/results.tex"]
+        );
+    }
+
+    #[test]
+    fn findings_carry_their_place_and_the_number_to_ignore_them_by() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = "main.tex:12:7:1:Warning:Command terminated with space.\n\
+                      chapters/intro.tex:3:1:36:Warning:You should put a space in front of parenthesis.\n\
+                      main.tex:40:2:17:Error:Number of `(' doesn't match the number of `)'!\n\
+                      main.tex:12:7:1:Warning:Command terminated with space.\n\
+                      not a finding at all\n";
+
+        let findings = parse(output, directory.path());
+
+        assert_eq!(findings.len(), 3, "repeats are reported once");
+        assert_eq!(findings[0].file.as_deref(), Some("main.tex"));
+        assert_eq!(findings[0].line, Some(12));
+        assert_eq!(findings[0].column, Some(7));
+        assert_eq!(
+            findings[0].message,
+            "Command terminated with space. (warning 1)"
+        );
+        assert_eq!(findings[0].source.as_deref(), Some(SOURCE));
+        assert_eq!(findings[1].file.as_deref(), Some("chapters/intro.tex"));
+        // What chktex calls an error is still only advice about a PDF that exists.
+        assert_eq!(findings[2].severity, Severity::Warning);
+        assert!(findings[2].message.starts_with("Number of `('"));
+    }
+
+    #[tokio::test]
+    async fn honours_suppression_comments_and_the_ignore_list() {
+        if resolve_executable("chktex").is_none() {
+            eprintln!("skipping: chktex is not installed");
+            return;
+        }
+        let directory = tempfile::tempdir().unwrap();
+        let root = directory.path();
+        std::fs::write(
+            root.join("main.tex"),
+            "\\documentclass{article}\n\
+             \\begin{document}\n\
+             \\LaTeX is here.\n\
+             \\TeX is here. % chktex 1\n\
+             Some text ... more.\n\
+             \\end{document}\n",
+        )
+        .unwrap();
+        let files = ["main.tex".to_owned()];
+
+        let everything = run(root, &files, &[]).await;
+        assert!(everything.iter().any(|finding| finding.line == Some(3)));
+        assert!(
+            !everything.iter().any(|finding| finding.line == Some(4)),
+            "a `% chktex 1` comment silences that warning on its line"
+        );
+
+        let quieter = run(root, &files, &[1]).await;
+        assert!(
+            !quieter
+                .iter()
+                .any(|finding| finding.message.ends_with("(warning 1)"))
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use std::{
+    collections::BTreeMap,
     fmt,
     path::{Path, PathBuf},
     str::FromStr,
@@ -254,8 +255,16 @@ pub struct Diagnostic {
     /// Project-relative when the path could be resolved inside the project.
     pub file: Option<String>,
     pub line: Option<u32>,
+    /// One-based. TeX never says; the tools that read the source do.
+    #[serde(default)]
+    pub column: Option<u32>,
     pub severity: Severity,
     pub message: String,
+    /// What found the problem, when it was not the build itself. A linter's
+    /// findings share the panel with TeX's, and `None` is what every stored
+    /// build state from before there were any says.
+    #[serde(default)]
+    pub source: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -301,6 +310,49 @@ pub struct ArtifactSummary {
     pub revision: i64,
 }
 
+/// How much of what the renderer draws is kept: a budget in memory, and
+/// whether a copy goes to disk as well.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheSettings {
+    pub memory_megabytes: usize,
+    pub disk: bool,
+}
+
+/// How long one kind of render-pool job has been taking, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobLatency {
+    pub kind: String,
+    pub count: u64,
+    pub mean_ms: f64,
+    pub longest_ms: f64,
+    pub last_ms: f64,
+}
+
+/// The render pool as it stands: how many threads it is running, what their
+/// open documents weigh, and how long each kind of job takes.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RenderMetrics {
+    pub workers: usize,
+    pub open_document_bytes: u64,
+    pub jobs: Vec<JobLatency>,
+}
+
+/// A working-tree publication a later build replaced, kept so the reader can
+/// go back to it. `revision` is the artifact's revision it was published as.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviousBuild {
+    pub id: i64,
+    pub artifact_id: i64,
+    pub revision: i64,
+    pub byte_size: i64,
+    pub built_at: i64,
+    pub superseded_at: i64,
+}
+
 /// A project as stored.
 ///
 /// A project *is* a document. `document_path` is its identity and its only
@@ -505,6 +557,9 @@ pub struct LooseDocument {
     pub path: String,
     /// Bumped when the file changes on disk, which redraws the viewer.
     pub revision: i64,
+    /// The PDF's attachments, when they include a document Press compiles:
+    /// what can be unpacked into a project. Empty for everything else.
+    pub embedded_sources: Vec<String>,
 }
 
 /// A stored version of a project's source.
@@ -523,6 +578,50 @@ pub struct SnapshotSummary {
     pub byte_size: i64,
 }
 
+/// One place a history search found its text, in one file of one version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryMatch {
+    /// Project-relative, forward-slashed.
+    pub path: String,
+    /// 1-based.
+    pub line: usize,
+    /// 1-based, in characters.
+    pub column: usize,
+    /// The line the match starts on, trimmed.
+    pub text: String,
+}
+
+/// What a history search found in one stored version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryVersion {
+    pub snapshot: SnapshotSummary,
+    /// Every match in the version; `matches` holds only the first few.
+    pub count: usize,
+    pub matches: Vec<HistoryMatch>,
+}
+
+/// A version a history search found its text in, and where.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistorySighting {
+    pub snapshot: SnapshotSummary,
+    pub at: HistoryMatch,
+}
+
+/// When some text was in a project's source, across its stored versions.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistorySearch {
+    /// Every version, oldest first, including those without it.
+    pub versions: Vec<HistoryVersion>,
+    pub first_seen: Option<HistorySighting>,
+    pub last_seen: Option<HistorySighting>,
+    /// The version after it was last seen, when there is one: where it went.
+    pub removed_in: Option<SnapshotSummary>,
+}
+
 /// What asking for a snapshot led to.
 ///
 /// A version that holds nothing new is not a version, so identical content is
@@ -557,6 +656,92 @@ impl SnapshotOutcome {
     }
 }
 
+/// How long one build of a document is. Words are split as texcount splits
+/// them, so that a limit on running text can be checked against running text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentStatistics {
+    pub text_words: i64,
+    /// The abstract's words alone, which is what an abstract limit is set on.
+    /// In LaTeX they are running text as well; in markdown the abstract is
+    /// front matter, which the other counts leave out.
+    pub abstract_words: i64,
+    pub header_words: i64,
+    pub caption_words: i64,
+    pub pages: Option<i64>,
+    pub figures: i64,
+    pub tables: i64,
+    pub equations: i64,
+}
+
+impl DocumentStatistics {
+    /// Adds another file's counts. Pages belong to the whole build, so they
+    /// are left as they are.
+    pub fn add(&mut self, other: &Self) {
+        self.text_words += other.text_words;
+        self.abstract_words += other.abstract_words;
+        self.header_words += other.header_words;
+        self.caption_words += other.caption_words;
+        self.figures += other.figures;
+        self.tables += other.tables;
+        self.equations += other.equations;
+    }
+}
+
+/// What a venue allows a document to be, checked after every build. Each part
+/// is optional: a project declares the limits its call for papers states and
+/// nothing else.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PageConstraints {
+    /// Pages allowed before the references begin. The references themselves
+    /// are usually free.
+    pub max_pages: Option<u32>,
+    pub paper: Option<PaperSize>,
+    /// The smallest text allowed, in points.
+    pub min_font_size: Option<f32>,
+    /// The narrowest margins allowed, in points.
+    pub margins: Option<Margins>,
+}
+
+impl PageConstraints {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    Letter,
+    A4,
+}
+
+impl PaperSize {
+    /// Width and height in PDF points.
+    pub fn dimensions(self) -> (f32, f32) {
+        match self {
+            Self::Letter => (612.0, 792.0),
+            Self::A4 => (595.276, 841.89),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Letter => "US Letter",
+            Self::A4 => "A4",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Margins {
+    pub top: f32,
+    pub bottom: f32,
+    pub left: f32,
+    pub right: f32,
+}
+
 /// One row of the history: the working tree, or a snapshot, together with what
 /// Press knows about building it.
 #[derive(Debug, Clone, PartialEq, Serialize)]
@@ -567,6 +752,8 @@ pub struct VersionSummary {
     pub snapshot: Option<SnapshotSummary>,
     pub build: BuildState,
     pub artifact: Option<ArtifactSummary>,
+    /// Counted when `artifact` was built.
+    pub statistics: Option<DocumentStatistics>,
 }
 
 /// A link on a page, as a rectangle in PDF points and a destination.
@@ -624,6 +811,8 @@ pub struct TextBox {
     pub line: usize,
 }
 
+/// One rectangle of a search match, in PDF points. A match that runs onto the
+/// next line has one per line.
 #[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SearchHit {
@@ -634,6 +823,155 @@ pub struct SearchHit {
     pub height: f32,
 }
 
+/// How a search reads what it is given. All off is the search a reader
+/// expects: any case, any part of a word, the text taken literally.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SearchOptions {
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: bool,
+}
+
+/// Something a search found, with the text around it for the results list.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchMatch {
+    pub page: usize,
+    pub rects: Vec<SearchHit>,
+    /// What came before it on the page, cut to a few words.
+    pub before: String,
+    /// The match as it reads, with a line-end hyphen taken out.
+    pub text: String,
+    pub after: String,
+}
+
+/// A run of a library search's snippet, and whether it is what matched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetPart {
+    pub text: String,
+    pub matched: bool,
+}
+
+/// A page somewhere in the library that a search found.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryHit {
+    pub project_id: i64,
+    pub project_name: String,
+    pub artifact_id: i64,
+    /// Which version of the document: the working tree, or a snapshot.
+    pub source_ref: SourceRef,
+    /// 1-based.
+    pub page: usize,
+    pub snippet: Vec<SnippetPart>,
+}
+
+/// A heading to jump to: from the PDF's bookmarks, or from the headings the
+/// build wrote down when the PDF has none.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutlineEntry {
+    pub title: String,
+    /// 0 for the outermost headings.
+    pub level: usize,
+    /// 1-based.
+    pub page: usize,
+    /// How far down the page, in PDF points from its top, when known.
+    pub top: Option<f32>,
+}
+
+/// A `\label` that answered a lookup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelMatch {
+    /// As written in the source: `fig:pipeline`.
+    pub name: String,
+    /// As the document prints it: "Figure 3".
+    pub caption: String,
+    /// The caption or heading it labels, when hyperref recorded one.
+    pub title: Option<String>,
+    /// The page number as printed, which may be roman.
+    pub printed_page: String,
+    /// The page of the PDF, 1-based. `None` when neither the anchor nor the
+    /// printed number says.
+    pub page: Option<usize>,
+}
+
+/// What a built PDF is made of, as a submission system would inspect it.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightReport {
+    /// "1.5", as the header states it.
+    pub pdf_version: Option<String>,
+    pub encrypted: bool,
+    pub page_count: usize,
+    pub metadata: PdfMetadata,
+    pub fonts: Vec<FontReport>,
+    pub images: Vec<ImageReport>,
+    pub findings: Vec<PreflightFinding>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfMetadata {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub subject: Option<String>,
+    pub keywords: Option<String>,
+    pub creator: Option<String>,
+    pub producer: Option<String>,
+}
+
+/// One font, wherever it is used. Fonts inside an included figure count: that
+/// is usually where the one that is not embedded came from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FontReport {
+    /// Without the subset tag: `Helvetica`, not `ABCDEF+Helvetica`.
+    pub name: String,
+    /// The PDF subtype: `Type1`, `TrueType`, `Type0`, `Type3`.
+    pub kind: String,
+    pub embedded: bool,
+    pub subset: bool,
+    /// 1-based.
+    pub pages: Vec<usize>,
+}
+
+/// One placement of an image. `dpi` is what it is printed at, which depends on
+/// the size it is drawn at as much as on its pixels; it is missing when the
+/// placement could not be told apart from the page's other images.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageReport {
+    /// 1-based.
+    pub page: usize,
+    pub width: i64,
+    pub height: i64,
+    pub dpi: Option<f32>,
+    pub color_space: Option<String>,
+    /// One bit per pixel, which is held to a higher resolution.
+    pub monochrome: bool,
+}
+
+/// Submission checkers whose rules the preflight applies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Venue {
+    IeeePdfExpress,
+    AcmTaps,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreflightFinding {
+    pub severity: Severity,
+    pub message: String,
+    /// Whose checker rejects or complains about this.
+    pub venues: Vec<Venue>,
+}
+
 /// Live build progress. Emitted from parsed latexmk output rather than a timer,
 /// so the banner can say something true about a long build.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize)]
@@ -658,6 +996,283 @@ pub struct BuildUpdate {
     pub source_ref: SourceRef,
     pub build: BuildState,
     pub artifact: Option<ArtifactSummary>,
+    pub statistics: Option<DocumentStatistics>,
+    /// 0-based pages a working-tree rebuild changed: see `changes`. Carried by
+    /// the update that follows the publication, not the publication's own.
+    /// `None` when nothing was compared, which is not the same as nothing
+    /// changing.
+    pub changed_pages: Option<Vec<u32>>,
+}
+
+/// What the reader has selected to copy: a rectangle drawn on one page, or a
+/// run of words dragged out across as many pages as it takes. Word indices are
+/// those of the page's extracted words, both ends included; pages count from
+/// zero, as they do for words.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum TextSelection {
+    Region {
+        page: usize,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    Words {
+        from_page: usize,
+        from_word: usize,
+        to_page: usize,
+        to_word: usize,
+    },
+}
+
+/// What a page is exported as for slides: a picture at a resolution, or a
+/// drawing that scales.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Png,
+    Svg,
+}
+
+impl ImageFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Svg => "svg",
+        }
+    }
+}
+
+/// How pages are put on paper for printing: see `impose`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PrintLayout {
+    #[default]
+    Single,
+    TwoUp,
+    FourUp,
+    Booklet,
+}
+
+impl PrintLayout {
+    /// What an export in this layout is named after, beside the document.
+    pub fn slug(self) -> &'static str {
+        match self {
+            Self::Single => "pages",
+            Self::TwoUp => "2up",
+            Self::FourUp => "4up",
+            Self::Booklet => "booklet",
+        }
+    }
+}
+
+/// A PDF export set up for printing. All off is every page, as it was.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PrintOptions {
+    /// As a print dialogue takes it: `1-4, 7, 10-`. Empty for every page.
+    pub pages: String,
+    pub layout: PrintLayout,
+    pub crop_marks: bool,
+}
+
+/// Part of a page, in PDF points from its top left.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PageRegion {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// What a reader's mark on a page is: text marked as it is, or a note pinned
+/// to a place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnotationKind {
+    Highlight,
+    Comment,
+}
+
+impl AnnotationKind {
+    pub fn as_token(self) -> &'static str {
+        match self {
+            Self::Highlight => "highlight",
+            Self::Comment => "comment",
+        }
+    }
+}
+
+impl FromStr for AnnotationKind {
+    type Err = AppError;
+
+    fn from_str(value: &str) -> AppResult<Self> {
+        match value {
+            "highlight" => Ok(Self::Highlight),
+            "comment" => Ok(Self::Comment),
+            other => Err(AppError::InvalidInput(format!(
+                "{other} is not a kind of annotation"
+            ))),
+        }
+    }
+}
+
+/// The source line an annotation was made against, as SyncTeX gave it when
+/// the annotation was made. `text` is what the line said then: line numbers
+/// drift as a document is edited, and the words are what find it again.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceAnchor {
+    /// Project-relative.
+    pub file: String,
+    /// 1-based.
+    pub line: usize,
+    pub text: String,
+}
+
+/// A reader's highlight or comment on one version of a project's PDF.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Annotation {
+    pub id: i64,
+    pub project_id: i64,
+    pub source_ref: SourceRef,
+    pub kind: AnnotationKind,
+    /// 0-based.
+    pub page: u32,
+    /// The marked lines of a highlight; for a comment, the one place its note
+    /// is pinned to.
+    pub rects: Vec<PageRegion>,
+    /// The text under a highlight, kept so that a list of annotations reads
+    /// without the PDF open beside it.
+    pub quote: String,
+    pub body: String,
+    /// `None` when SyncTeX had nothing to say about the place: a PDF built
+    /// without it, or a mark on something the document did not write.
+    pub anchor: Option<SourceAnchor>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// An annotation as the viewer makes it. Where it belongs — the project, the
+/// version, the source line — follows from the artifact it is made on.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewAnnotation {
+    pub kind: AnnotationKind,
+    pub page: u32,
+    pub rects: Vec<PageRegion>,
+    #[serde(default)]
+    pub quote: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+/// The passage a reader is at, as a line of the source of the version they are
+/// reading: see `position`. What a viewer holds on to across a rebuild or a
+/// switch of version, where a page number would not survive.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadingPosition {
+    pub source_ref: SourceRef,
+    pub anchor: SourceAnchor,
+}
+
+/// A point on a page: 0-based, in PDF points from the page's top left.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PagePoint {
+    pub page: u32,
+    pub x: f32,
+    pub y: f32,
+}
+
+/// How the viewer sizes pages. `Custom` is whatever the reader zoomed to,
+/// kept in `ViewState::zoom`; the others are worked out from the window, so a
+/// page fitted to one window is fitted again to the next.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ZoomMode {
+    #[default]
+    Width,
+    Page,
+    Actual,
+    Custom,
+}
+
+/// Where a reader was in one version of a project, and how they were reading
+/// it. Saved as the viewer goes and handed back when the project opens.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ViewState {
+    /// The top of the viewport.
+    pub position: PagePoint,
+    pub zoom_mode: ZoomMode,
+    pub zoom: f32,
+    /// `None` follows Press's own theme; a choice made for this version
+    /// overrides it.
+    pub dark: Option<bool>,
+    /// Marks set the way zathura sets them: a letter, and the place it names.
+    pub marks: BTreeMap<char, PagePoint>,
+    /// Places jumped away from, oldest first, for going back.
+    pub jumps: Vec<PagePoint>,
+}
+
+impl Default for ViewState {
+    fn default() -> Self {
+        Self {
+            position: PagePoint {
+                page: 0,
+                x: 0.0,
+                y: 0.0,
+            },
+            zoom_mode: ZoomMode::default(),
+            zoom: 1.0,
+            dark: None,
+            marks: BTreeMap::new(),
+            jumps: Vec::new(),
+        }
+    }
+}
+
+/// A version's view state, as the project hands it back on opening.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedView {
+    pub source_ref: SourceRef,
+    /// Unix seconds. The most recent is the version the reader had open last.
+    pub updated_at: i64,
+    #[serde(flatten)]
+    pub view: ViewState,
+}
+
+/// A project made current, with every version's view state, most recently
+/// used first.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenedProject {
+    #[serde(flatten)]
+    pub summary: ProjectSummary,
+    pub views: Vec<SavedView>,
+}
+
+/// Where an annotation made on one version falls in the working tree.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnotationPlace {
+    pub annotation_id: i64,
+    /// 1-based, in the anchor's file. `None` when the line has been rewritten
+    /// past recognising, or the annotation had no anchor to begin with.
+    pub line: Option<usize>,
+    /// Where that line is typeset in the working tree's latest build, if it
+    /// has one: a 0-based page and a point from its top left.
+    pub page: Option<u32>,
+    pub x: f32,
+    pub y: f32,
 }
 
 #[cfg(test)]
@@ -745,8 +1360,10 @@ mod tests {
                 diagnostics: vec![Diagnostic {
                     file: Some("main.tex".into()),
                     line: Some(4),
+                    column: None,
                     severity: Severity::Error,
                     message: "Missing $ inserted.".into(),
+                    source: None,
                 }],
             },
             artifact: Some(ArtifactSummary {
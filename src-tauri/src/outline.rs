@@ -0,0 +1,367 @@
+//! A document's outline, for moving around it by section.
+//!
+//! The PDF's own bookmarks come first. hyperref writes them for any LaTeX
+//! document that loads it, and pandoc's LaTeX always does, so for most builds
+//! that is the whole story, and the same goes for a PDF Press is only showing.
+//!
+//! A PDF without bookmarks still came from somewhere that numbered its
+//! sections. LaTeX writes every heading into the `.aux` as a contents line —
+//! whether or not a table of contents is printed — and `publish` keeps that
+//! file beside the PDF. Markdown's headings arrive there too, by way of the
+//! LaTeX pandoc wrote, so one reader serves both. What a contents line does not
+//! say is which page of the PDF a heading is on: it carries the page number as
+//! printed, and front matter numbered in roman or not at all puts chapter one
+//! several pages into the file. The offset is found by looking for the headings
+//! in the pages' text, and the pages that are the printed contents themselves
+//! are ignored while looking, since every heading is on those.
+
+use crate::{model::OutlineEntry, prose, render::Bookmark};
+
+/// How far past its printed number a heading is looked for. Front matter
+/// longer than this is rare, and every extra offset is another pass.
+const MOST_OFFSET: usize = 60;
+/// Headings on one page before it is taken for a table of contents.
+const CONTENTS_PAGE: usize = 3;
+
+/// The bookmarks, flattened in reading order. Those that lead out of the
+/// document have no page and no place here.
+pub fn from_bookmarks(bookmarks: Vec<Bookmark>) -> Vec<OutlineEntry> {
+    bookmarks
+        .into_iter()
+        .filter_map(|bookmark| {
+            Some(OutlineEntry {
+                title: bookmark.title.trim().to_owned(),
+                level: bookmark.level,
+                page: bookmark.page?,
+                top: bookmark.top,
+            })
+        })
+        .collect()
+}
+
+/// One contents line from the `.aux`: a heading, how deep it is, and the page
+/// number LaTeX printed for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Heading {
+    level: usize,
+    title: String,
+    printed: String,
+}
+
+/// The headings in an `.aux`, placed on pages of a PDF whose text is `pages`.
+/// Nothing, when there are no contents lines to read.
+pub fn from_contents(aux: &str, pages: &[String]) -> Vec<OutlineEntry> {
+    let headings = headings(aux);
+    if headings.is_empty() {
+        return Vec::new();
+    }
+    let placed = place(&headings, pages);
+    let shallowest = headings
+        .iter()
+        .map(|heading| heading.level)
+        .min()
+        .unwrap_or_default();
+    headings
+        .into_iter()
+        .zip(placed)
+        .filter_map(|(heading, page)| {
+            Some(OutlineEntry {
+                title: heading.title,
+                level: heading.level - shallowest,
+                page: page?,
+                top: None,
+            })
+        })
+        .collect()
+}
+
+/// Every `\contentsline` in the `.aux`, in the order they were written.
+fn headings(aux: &str) -> Vec<Heading> {
+    let characters = aux.chars().collect::<Vec<_>>();
+    let mut headings = Vec::new();
+    let mut index = 0;
+    while let Some(at) = prose::find(&characters, index, "\\contentsline") {
+        index = at + "\\contentsline".len();
+        let Some((arguments, end)) = groups(&characters, index, 3) else {
+            continue;
+        };
+        index = end;
+        let Some(level) = depth(arguments[0].trim()) else {
+            continue;
+        };
+        let title = plain(&arguments[1]);
+        if title.is_empty() {
+            continue;
+        }
+        headings.push(Heading {
+            level,
+            title,
+            printed: arguments[2].trim().to_owned(),
+        });
+    }
+    headings
+}
+
+fn depth(kind: &str) -> Option<usize> {
+    Some(match kind {
+        "part" => 0,
+        "chapter" => 1,
+        "section" => 2,
+        "subsection" => 3,
+        "subsubsection" => 4,
+        "paragraph" => 5,
+        "subparagraph" => 6,
+        _ => return None,
+    })
+}
+
+/// `count` brace groups from `from`, skipping the space between them. Their
+/// contents, and where the last one ends.
+pub fn groups(characters: &[char], from: usize, count: usize) -> Option<(Vec<String>, usize)> {
+    let mut index = from;
+    let mut found = Vec::with_capacity(count);
+    for _ in 0..count {
+        while characters
+            .get(index)
+            .is_some_and(|character| character.is_whitespace())
+        {
+            index += 1;
+        }
+        if characters.get(index) != Some(&'{') {
+            return None;
+        }
+        let close = prose::closing(characters, index)?;
+        found.push(characters[index + 1..close].iter().collect());
+        index = close + 1;
+    }
+    Some((found, index))
+}
+
+/// A contents entry as a reader would write it: `\numberline {2.1}Related
+/// work` becomes "2.1 Related work", and markup is dropped for its text.
+pub fn plain(entry: &str) -> String {
+    let characters = entry.chars().collect::<Vec<_>>();
+    let mut text = String::new();
+    let mut index = 0;
+    while index < characters.len() {
+        match characters[index] {
+            '\\' => {
+                let name_end = index
+                    + 1
+                    + characters[index + 1..]
+                        .iter()
+                        .take_while(|character| character.is_ascii_alphabetic())
+                        .count();
+                let name = characters[index + 1..name_end].iter().collect::<String>();
+                index = name_end.max(index + 2);
+                match name.as_str() {
+                    "numberline" => {
+                        if let Some((number, end)) = groups(&characters, index, 1) {
+                            text.push_str(number[0].trim());
+                            text.push(' ');
+                            index = end;
+                        }
+                    }
+                    // The PDF string is the plain one, which is what is wanted.
+                    "texorpdfstring" => {
+                        if let Some((arguments, end)) = groups(&characters, index, 2) {
+                            text.push_str(&plain(&arguments[1]));
+                            index = end;
+                        }
+                    }
+                    // A control symbol, `\&` or `\%`: the symbol is the text.
+                    "" => text.extend(characters.get(index - 1)),
+                    _ => {}
+                }
+            }
+            '{' | '}' | '$' => index += 1,
+            '~' => {
+                text.push(' ');
+                index += 1;
+            }
+            character => {
+                text.push(character);
+                index += 1;
+            }
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Which page of the PDF each heading is on, 1-based.
+///
+/// Arabic page numbers share one offset, chosen as the one that puts the most
+/// headings on a page that has them on it. Roman ones are front matter, which
+/// starts the file, and are taken as they are.
+fn place(headings: &[Heading], pages: &[String]) -> Vec<Option<usize>> {
+    let texts = pages
+        .iter()
+        .map(|page| normalized(page.as_str()))
+        .collect::<Vec<_>>();
+    let titles = headings
+        .iter()
+        .map(|heading| normalized(bare(&heading.title)))
+        .collect::<Vec<_>>();
+    let contents_pages = texts
+        .iter()
+        .map(|text| {
+            titles
+                .iter()
+                .filter(|title| !title.is_empty() && text.contains(title.as_str()))
+                .count()
+                >= CONTENTS_PAGE
+        })
+        .collect::<Vec<_>>();
+    let shows = |page: usize, heading: usize| {
+        page >= 1
+            && page <= texts.len()
+            && !contents_pages[page - 1]
+            && !titles[heading].is_empty()
+            && texts[page - 1].contains(titles[heading].as_str())
+    };
+
+    let arabic = headings
+        .iter()
+        .map(|heading| heading.printed.parse::<usize>().ok())
+        .collect::<Vec<_>>();
+    let offset = (0..=MOST_OFFSET.min(texts.len()))
+        .max_by_key(|offset| {
+            let hits = arabic
+                .iter()
+                .enumerate()
+                .filter(|(heading, printed)| {
+                    printed.is_some_and(|printed| shows(printed + offset, *heading))
+                })
+                .count();
+            // The smallest offset among equals: `max_by_key` keeps the last.
+            (hits, std::cmp::Reverse(*offset))
+        })
+        .unwrap_or_default();
+
+    headings
+        .iter()
+        .zip(arabic)
+        .map(|(heading, printed)| {
+            let page = match printed {
+                Some(printed) => printed + offset,
+                None => roman(&heading.printed)?,
+            };
+            (1..=texts.len()).contains(&page).then_some(page)
+        })
+        .collect()
+}
+
+/// The title without the number `title` put in front of it, which the page
+/// may set apart from the heading or not print at all.
+fn bare(title: &str) -> &str {
+    match title.split_once(' ') {
+        Some((number, rest)) if number.chars().any(|character| character.is_ascii_digit()) => rest,
+        _ => title,
+    }
+}
+
+/// Lower case, letters and digits only, single spaces: a heading and the same
+/// heading read back out of a PDF differ in everything else.
+fn normalized(text: &str) -> String {
+    text.to_lowercase()
+        .split(|character: char| !character.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn roman(text: &str) -> Option<usize> {
+    let mut total = 0;
+    let mut previous = 0;
+    for character in text.to_ascii_lowercase().chars().rev() {
+        let value = match character {
+            'i' => 1,
+            'v' => 5,
+            'x' => 10,
+            'l' => 50,
+            'c' => 100,
+            _ => return None,
+        };
+        if value < previous {
+            total -= value;
+        } else {
+            total += value;
+            previous = value;
+        }
+    }
+    (total > 0).then_some(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(text: &str) -> String {
+        text.to_owned()
+    }
+
+    const AUX: &str = "\\relax\n\
+        \\@writefile{toc}{\\contentsline {chapter}{Abstract}{iii}{}}\n\
+        \\@writefile{toc}{\\contentsline {chapter}{\\numberline {1}Introduction}{1}{}}\n\
+        \\@writefile{toc}{\\contentsline {section}{\\numberline {1.1}Why \\texorpdfstring {$\\alpha $}{alpha} matters}{2}{}}\n\
+        \\@writefile{toc}{\\contentsline {chapter}{\\numberline {2}Related~work}{3}{}}\n\
+        \\@writefile{lof}{\\contentsline {figure}{\\numberline {1}{\\ignorespaces A plot}}{2}{}}\n\
+        \\newlabel{chap:intro}{{1}{1}}\n";
+
+    #[test]
+    fn contents_lines_are_read_with_their_numbers_and_without_markup() {
+        let headings = headings(AUX);
+        let titles = headings
+            .iter()
+            .map(|heading| heading.title.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            titles,
+            vec![
+                "Abstract",
+                "1 Introduction",
+                "1.1 Why alpha matters",
+                "2 Related work"
+            ]
+        );
+        assert_eq!(headings[0].printed, "iii");
+        assert_eq!(headings[2].level, 2);
+    }
+
+    #[test]
+    fn headings_are_placed_past_the_front_matter_and_the_printed_contents() {
+        // Title page, a blank, the abstract on iii, the contents on iv, and
+        // chapter one on the fifth page of the file.
+        let pages = vec![
+            page("A Thesis"),
+            page(""),
+            page("Abstract\nThis thesis argues"),
+            page(
+                "Contents\nAbstract iii\n1 Introduction 1\n1.1 Why alpha matters 2\n2 Related work 3",
+            ),
+            page("Chapter 1\nIntroduction\nIt begins."),
+            page("1.1 Why alpha matters\nBecause."),
+            page("Chapter 2\nRelated Work\nOthers."),
+        ];
+
+        let outline = from_contents(AUX, &pages);
+        let placed = outline
+            .iter()
+            .map(|entry| (entry.title.as_str(), entry.level, entry.page))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            placed,
+            vec![
+                ("Abstract", 0, 3),
+                ("1 Introduction", 0, 5),
+                ("1.1 Why alpha matters", 1, 6),
+                ("2 Related work", 0, 7),
+            ]
+        );
+    }
+
+    #[test]
+    fn a_pdf_with_no_contents_lines_has_no_derived_outline() {
+        assert!(from_contents("\\relax\n", &[page("Text")]).is_empty());
+    }
+}
@@ -19,7 +19,7 @@ use crate::{
     anchors,
     database::{Repository, StoredArtifact},
     error::{AppError, AppResult},
-    model::{DocumentKind, Project, SourcePeek, SourceRef},
+    model::{DocumentKind, Project, SourceAnchor, SourcePeek, SourceRef},
     snapshot,
     toolchain::{augmented_path, resolve_executable},
 };
@@ -38,6 +38,174 @@ pub fn resolve(
     x: f64,
     y: f64,
 ) -> AppResult<Option<SourcePeek>> {
+    let Some(found) = source_at(project, stored, repository, objects, page, x, y)? else {
+        return Ok(None);
+    };
+    let lines: Vec<&str> = found.text.lines().collect();
+    let (first, last) = if found.markdown {
+        block_around(&lines, found.anchor)
+    } else {
+        construct_around(&lines, found.anchor)
+    };
+
+    Ok(Some(SourcePeek {
+        file: found.relative,
+        start_line: first as i64 + 1,
+        end_line: last as i64 + 1,
+        text: lines[first..=last].join("\n"),
+    }))
+}
+
+/// The LaTeX of the mathematics at a point: the whole construct when it is a
+/// display, or every piece of inline mathematics on the line when it is not.
+/// Delimiters are kept, so each piece reads as it was written.
+pub fn math_at(
+    project: &Project,
+    stored: &StoredArtifact,
+    repository: &Repository,
+    objects: &Path,
+    page: u32,
+    x: f64,
+    y: f64,
+) -> AppResult<Option<MathSource>> {
+    let Some(found) = source_at(project, stored, repository, objects, page, x, y)? else {
+        return Ok(None);
+    };
+    let lines: Vec<&str> = found.text.lines().collect();
+    let (first, last) = construct_around(&lines, found.anchor);
+    let display = display_around(&lines, first, last);
+    let (line, pieces) = match display {
+        Some((first, last)) => (first, vec![lines[first..=last].join("\n")]),
+        None => (found.anchor, inline_math(lines[found.anchor])),
+    };
+    if pieces.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(MathSource {
+        file: found.relative,
+        line: line + 1,
+        display: display.is_some(),
+        pieces,
+    }))
+}
+
+/// What a point's mathematics was written as. `file` and `line` say which
+/// source it came from, so that several points on one line can share it out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MathSource {
+    pub file: String,
+    /// 1-based: the line of the point, or the first line of a display.
+    pub line: usize,
+    pub display: bool,
+    pub pieces: Vec<String>,
+}
+
+/// The source line under a point, and what it says, for anchoring something
+/// the reader has marked there.
+pub fn anchor_at(
+    project: &Project,
+    stored: &StoredArtifact,
+    repository: &Repository,
+    objects: &Path,
+    page: u32,
+    x: f64,
+    y: f64,
+) -> AppResult<Option<SourceAnchor>> {
+    let Some(found) = source_at(project, stored, repository, objects, page, x, y)? else {
+        return Ok(None);
+    };
+    let text = found.text.lines().nth(found.anchor).unwrap_or_default();
+    Ok(Some(SourceAnchor {
+        file: found.relative,
+        line: found.anchor + 1,
+        text: text.trim().to_owned(),
+    }))
+}
+
+/// Where a line of the source was typeset in an artifact's PDF: a 1-based page
+/// and a point from the page's top left. The other direction from a click, and
+/// the same `synctex`.
+pub fn place_of(
+    project: &Project,
+    stored: &StoredArtifact,
+    relative: &str,
+    line: usize,
+) -> AppResult<Option<(u32, f64, f64)>> {
+    let pdf = stored.pdf_path.as_path();
+    let (input, line) = if project.kind() == DocumentKind::Markdown {
+        // SyncTeX only knows pandoc's LaTeX, so the line goes through the
+        // anchors to get there. Its name is enough: `synctex` matches an input
+        // by the end of its path, and the scratch directory it was written in
+        // is not one Press keeps track of.
+        let anchors = anchors::decode(&read_sidecar(pdf, "lines").unwrap_or_default());
+        let Some(generated) = anchors::generated_line(&anchors, line as u32) else {
+            return Ok(None);
+        };
+        (
+            PathBuf::from(format!("{}.tex", project.job_name())),
+            generated,
+        )
+    } else if stored.summary.source_ref == SourceRef::Worktree {
+        (project.directory().join(relative), line as u32)
+    } else {
+        // A snapshot's checkout is gone, and with it the prefix SyncTeX
+        // recorded. The project-relative path is the end of that one.
+        (PathBuf::from(relative), line as u32)
+    };
+
+    let synctex = synctex()?;
+    let output = std::process::Command::new(&synctex)
+        .env("PATH", augmented_path(&synctex))
+        .arg("view")
+        .arg("-i")
+        .arg(format!("{line}:0:{}", input.display()))
+        .arg("-o")
+        .arg(pdf)
+        .output()
+        .map_err(|error| AppError::Build(format!("could not run synctex: {error}")))?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    // One record per box the line produced; the first is where it starts.
+    let text = String::from_utf8_lossy(&output.stdout);
+    let mut page = None;
+    let mut x = None;
+    let mut y = None;
+    for entry in text.lines() {
+        if let Some(rest) = entry.strip_prefix("Page:") {
+            page.get_or_insert(rest.trim().parse::<u32>().unwrap_or(0));
+        } else if let Some(rest) = entry.strip_prefix("x:") {
+            x.get_or_insert(rest.trim().parse::<f64>().unwrap_or(0.0));
+        } else if let Some(rest) = entry.strip_prefix("y:") {
+            y.get_or_insert(rest.trim().parse::<f64>().unwrap_or(0.0));
+        }
+    }
+    match (page, x, y) {
+        (Some(page), Some(x), Some(y)) if page > 0 => Ok(Some((page, x, y))),
+        _ => Ok(None),
+    }
+}
+
+/// A source file, and the line in it a point came from.
+struct Located {
+    relative: String,
+    text: String,
+    /// 0-based, and within `text`.
+    anchor: usize,
+    /// Read as markdown: blocks rather than LaTeX constructs.
+    markdown: bool,
+}
+
+fn source_at(
+    project: &Project,
+    stored: &StoredArtifact,
+    repository: &Repository,
+    objects: &Path,
+    page: u32,
+    x: f64,
+    y: f64,
+) -> AppResult<Option<Located>> {
     let Some(hit) = ask_synctex(&stored.pdf_path, page, x, y)? else {
         return Ok(None);
     };
@@ -82,22 +250,15 @@ pub fn resolve(
         return Ok(None);
     };
 
-    let lines: Vec<&str> = text.lines().collect();
-    if lines.is_empty() {
+    let count = text.lines().count();
+    if count == 0 {
         return Ok(None);
     }
-    let anchor = (line as usize).clamp(1, lines.len()) - 1;
-    let (first, last) = if from_pandoc || kind == DocumentKind::Markdown {
-        block_around(&lines, anchor)
-    } else {
-        construct_around(&lines, anchor)
-    };
-
-    Ok(Some(SourcePeek {
-        file: relative,
-        start_line: first as i64 + 1,
-        end_line: last as i64 + 1,
-        text: lines[first..=last].join("\n"),
+    Ok(Some(Located {
+        relative,
+        text,
+        anchor: (line as usize).clamp(1, count) - 1,
+        markdown: from_pandoc || kind == DocumentKind::Markdown,
     }))
 }
 
@@ -146,11 +307,7 @@ struct Hit {
 /// Parsing the compressed format here would be a second implementation of
 /// something already installed and already correct.
 fn ask_synctex(pdf: &Path, page: u32, x: f64, y: f64) -> AppResult<Option<Hit>> {
-    let synctex = resolve_executable("synctex").ok_or_else(|| {
-        AppError::ToolUnavailable(
-            "synctex was not found. It ships with TeX distributions, beside latexmk.".into(),
-        )
-    })?;
+    let synctex = synctex()?;
     let output = std::process::Command::new(&synctex)
         .env("PATH", augmented_path(&synctex))
         .arg("edit")
@@ -181,6 +338,14 @@ fn ask_synctex(pdf: &Path, page: u32, x: f64, y: f64) -> AppResult<Option<Hit>>
     }
 }
 
+fn synctex() -> AppResult<PathBuf> {
+    resolve_executable("synctex").ok_or_else(|| {
+        AppError::ToolUnavailable(
+            "synctex was not found. It ships with TeX distributions, beside latexmk.".into(),
+        )
+    })
+}
+
 /// TeX writes the directory it was run in and the path it was given, so the
 /// result usually has a `/./` in the middle of it.
 fn tidy(path: &Path) -> PathBuf {
@@ -251,7 +416,7 @@ fn ends_at_boundary(path: &str, candidate: &str) -> bool {
 }
 
 /// The source of one file, as this version has it.
-fn read_source(
+pub fn read_source(
     project: &Project,
     repository: &Repository,
     objects: &Path,
@@ -318,6 +483,90 @@ fn paragraph(lines: &[&str], anchor: usize, boundary: impl Fn(&str) -> bool) ->
     (first, last)
 }
 
+/// The display a run of lines is, or is the body of.
+///
+/// SyncTeX puts a display's boxes on whichever of its lines it likes: the
+/// closing one, the opening one, or one in the middle, which widens to the
+/// body alone because the environment's own lines are where widening stops.
+fn display_around(lines: &[&str], first: usize, last: usize) -> Option<(usize, usize)> {
+    let encloses = |first: usize, last: usize| {
+        let opened = environment_at(lines[first], "\\begin{").filter(|name| is_display_math(name));
+        let bracketed = lines[first].trim() == "\\[" && lines[last].trim() == "\\]";
+        bracketed
+            || opened.is_some_and(|name| {
+                environment_at(lines[last], "\\end{").is_some_and(|closed| closed == name)
+            })
+    };
+    if encloses(first, last) {
+        return Some((first, last));
+    }
+    (first > 0 && last + 1 < lines.len() && encloses(first - 1, last + 1))
+        .then(|| (first - 1, last + 1))
+}
+
+fn is_display_math(name: &str) -> bool {
+    let name = name.trim_end_matches('*');
+    matches!(
+        name,
+        "equation"
+            | "align"
+            | "alignat"
+            | "gather"
+            | "multline"
+            | "flalign"
+            | "eqnarray"
+            | "displaymath"
+            | "math"
+    )
+}
+
+/// The inline mathematics on a line, delimiters and all, in order: `$…$`,
+/// `$$…$$`, `\(…\)` and a one-line `\[…\]`. An escaped `\$` is a dollar.
+fn inline_math(line: &str) -> Vec<String> {
+    let characters = line.chars().collect::<Vec<_>>();
+    let mut pieces = Vec::new();
+    let mut index = 0;
+    while index < characters.len() {
+        let rest = &characters[index..];
+        let (open, close) = match rest {
+            ['\\', '(', ..] => ("\\(", "\\)"),
+            ['\\', '[', ..] => ("\\[", "\\]"),
+            ['\\', _, ..] => {
+                index += 2;
+                continue;
+            }
+            ['$', '$', ..] => ("$$", "$$"),
+            ['$', ..] => ("$", "$"),
+            _ => {
+                index += 1;
+                continue;
+            }
+        };
+        let body = index + open.chars().count();
+        let close = close.chars().collect::<Vec<_>>();
+        let mut end = body;
+        let found = loop {
+            if end + close.len() > characters.len() {
+                break None;
+            }
+            if characters[end] == '\\' && close[0] != '\\' {
+                end += 2;
+                continue;
+            }
+            if characters[end..end + close.len()] == close[..] {
+                break Some(end + close.len());
+            }
+            end += 1;
+        };
+        let Some(after) = found else {
+            break;
+        };
+        pieces.push(characters[index..after].iter().collect());
+        index = after;
+    }
+    pieces
+}
+
 /// `document` wraps everything, so widening to it would answer every click with
 /// the whole file. TeX attributes the first box on a page to it often enough
 /// for that to matter.
@@ -423,6 +672,27 @@ mod tests {
         assert_eq!(block_around(&lines, 5), (5, 5));
     }
 
+    #[test]
+    fn a_display_is_found_from_any_of_its_lines() {
+        assert_eq!(display_around(DOCUMENT, 5, 7), Some((5, 7)));
+        // The body alone, as widening from a line inside it gives.
+        assert_eq!(display_around(DOCUMENT, 6, 6), Some((5, 7)));
+        assert_eq!(display_around(DOCUMENT, 2, 3), None);
+
+        let bracketed = ["Prose.", "\\[", "  a^2 + b^2 = c^2", "\\]"];
+        assert_eq!(display_around(&bracketed, 2, 2), Some((1, 3)));
+    }
+
+    #[test]
+    fn inline_mathematics_is_picked_out_of_a_line_with_its_delimiters() {
+        assert_eq!(
+            inline_math("Let $x \\in \\mathbb{R}$ cost \\$5, and \\(f(x)\\) be $$\\int f$$."),
+            vec!["$x \\in \\mathbb{R}$", "\\(f(x)\\)", "$$\\int f$$"]
+        );
+        assert!(inline_math("No mathematics, only a \\$ sign.").is_empty());
+        assert!(inline_math("An $unclosed one").is_empty());
+    }
+
     #[test]
     fn nested_environments_match_their_own_end() {
         let lines = [
@@ -0,0 +1,161 @@
+//! Keeping the reader on the same passage when the PDF under them changes.
+//!
+//! A page number is a poor bookmark. A rebuild that adds a figure pushes
+//! everything after it along, and an older version may have had a whole section
+//! fewer. What survives both is the source: the viewer takes the line SyncTeX
+//! puts at the top of its viewport, and SyncTeX, asked the other way, says where
+//! that line is in the other PDF.
+//!
+//! Between versions the line itself moves, so it is carried across with a diff
+//! of the file as each version has it. The diff is the patience kind: lines
+//! that occur exactly once on each side are paired in order, and a line between
+//! two pairs keeps its distance from the pair above it. That is cheap on a
+//! thesis-length file, and it is right where it matters — at the headings,
+//! labels and sentences that make a passage recognisable, which are the lines
+//! that are unique.
+
+use std::collections::HashMap;
+
+/// The 0-based line of `new` that line `line` of `old` became, or `None` when
+/// the passage around it has no counterpart at all.
+pub fn map_line(old: &str, new: &str, line: usize) -> Option<usize> {
+    let old: Vec<&str> = old.lines().map(str::trim_end).collect();
+    let new: Vec<&str> = new.lines().map(str::trim_end).collect();
+    if line >= old.len() || new.is_empty() {
+        return None;
+    }
+    if old == new {
+        return Some(line);
+    }
+
+    let pairs = unique_pairs(&old, &new);
+    if pairs.is_empty() {
+        return None;
+    }
+    if let Some(&(_, to)) = pairs.iter().find(|&&(from, _)| from == line) {
+        return Some(to);
+    }
+
+    // The pairs either side of the line bound where it can have gone.
+    let above = pairs.iter().rev().find(|&&(from, _)| from < line).copied();
+    let below = pairs.iter().find(|&&(from, _)| from > line).copied();
+    let start = above.map_or(0, |(_, to)| to + 1);
+    let end = below.map_or(new.len(), |(_, to)| to);
+    if start >= end {
+        // Everything between the two pairs was deleted: the nearest thing to
+        // the passage is where it used to sit.
+        return Some(start.min(new.len() - 1));
+    }
+
+    // Counted from the pair above, or back from the one below when the line
+    // comes before every pair.
+    let guess = match (above, below) {
+        (Some((from, to)), _) => to + (line - from),
+        (None, Some((from, to))) => to.saturating_sub(from - line),
+        (None, None) => line,
+    }
+    .clamp(start, end - 1);
+    // A line that is still there, word for word, is better than the guess;
+    // the nearest copy of it inside the gap, since blank lines and `\item`s
+    // repeat.
+    Some(
+        (start..end)
+            .filter(|&candidate| new[candidate] == old[line] && !old[line].trim().is_empty())
+            .min_by_key(|&candidate| candidate.abs_diff(guess))
+            .unwrap_or(guess),
+    )
+}
+
+/// The lines that occur once in each file and in the same order in both, as
+/// (old, new) indices: the longest increasing run of them by their new index.
+fn unique_pairs(old: &[&str], new: &[&str]) -> Vec<(usize, usize)> {
+    let mut counts: HashMap<&str, (usize, usize, usize)> = HashMap::new();
+    for (index, line) in old.iter().enumerate() {
+        let entry = counts.entry(*line).or_insert((0, 0, 0));
+        entry.0 += 1;
+        entry.2 = index;
+    }
+    let mut candidates = Vec::new();
+    for line in new {
+        if let Some(entry) = counts.get_mut(line) {
+            entry.1 += 1;
+        }
+    }
+    for (index, line) in new.iter().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Some(&(1, 1, from)) = counts.get(line) {
+            candidates.push((from, index));
+        }
+    }
+    candidates.sort_unstable();
+    longest_increasing(&candidates)
+}
+
+/// Patience sorting: the longest subsequence of `pairs`, already in old order,
+/// whose new indices increase.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // `tails[k]` is the index of the pair ending the best run of length k + 1.
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous = vec![None; pairs.len()];
+    for (index, &(_, to)) in pairs.iter().enumerate() {
+        let at = tails.partition_point(|&tail| pairs[tail].1 < to);
+        if at > 0 {
+            previous[index] = Some(tails[at - 1]);
+        }
+        if at == tails.len() {
+            tails.push(index);
+        } else {
+            tails[at] = index;
+        }
+    }
+    let mut run = Vec::with_capacity(tails.len());
+    let mut next = tails.last().copied();
+    while let Some(index) = next {
+        run.push(pairs[index]);
+        next = previous[index];
+    }
+    run.reverse();
+    run
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: &str = "\\section{Introduction}\nWe study things.\n\n\\section{Method}\nFirst we measure.\nThen we think.\n\n\\section{Results}\nIt worked.\n";
+
+    #[test]
+    fn a_line_follows_its_passage_past_an_insertion_above_it() {
+        let new = "\\section{Introduction}\nWe study things.\nA new paragraph.\nAnd another.\n\n\\section{Method}\nFirst we measure.\nThen we think.\n\n\\section{Results}\nIt worked.\n";
+        assert_eq!(map_line(OLD, new, 4), Some(6));
+        assert_eq!(map_line(OLD, new, 8), Some(10));
+        assert_eq!(map_line(OLD, OLD, 5), Some(5));
+    }
+
+    #[test]
+    fn an_edited_line_keeps_its_place_between_the_lines_around_it() {
+        let new = "\\section{Results}\nIt worked.\n\n\\section{Introduction}\nWe study things.\n\n\\section{Method}\nFirst, we measure carefully.\nThen we think.\n";
+        assert_eq!(
+            map_line(OLD, new, 4),
+            Some(7),
+            "edited, but between the same two lines"
+        );
+        assert_eq!(map_line(OLD, new, 0), Some(3), "moved sections move it");
+    }
+
+    #[test]
+    fn a_deleted_passage_lands_where_it_used_to_be() {
+        let new = "\\section{Introduction}\nWe study things.\n\n\\section{Results}\nIt worked.\n";
+        assert_eq!(map_line(OLD, new, 5), Some(2));
+        assert_eq!(map_line(OLD, "", 2), None);
+        assert_eq!(map_line(OLD, "Nothing\nin common\n", 2), None);
+    }
+
+    #[test]
+    fn pairs_are_the_longest_run_in_order() {
+        let pairs = [(0, 3), (1, 0), (2, 1), (3, 4), (4, 2)];
+        assert_eq!(longest_increasing(&pairs), vec![(1, 0), (2, 1), (4, 2)]);
+    }
+}
@@ -0,0 +1,487 @@
+//! What a submission system will say about a PDF, said before it is submitted.
+//!
+//! IEEE PDF eXpress and ACM TAPS both reject a paper whose fonts are not all
+//! embedded, and the one that is not is nearly always in a figure: a plot saved
+//! with Helvetica referenced rather than included, which LaTeX passes through
+//! untouched. Finding that out used to mean uploading the PDF to a web service
+//! and waiting. Everything those services check first is in the PDF's own
+//! objects, so Press reads them: the fonts each page and each included figure
+//! uses, the images and the resolution they are printed at, the version in the
+//! header and the document's metadata.
+//!
+//! [`inspect`] runs on a render worker, where MuPDF lives. [`assess`] turns
+//! what it found into findings, and is where the venues' rules are written
+//! down.
+
+use std::collections::BTreeMap;
+
+use mupdf::{
+    MetadataName,
+    pdf::{PdfDocument, PdfObject},
+};
+
+use crate::{
+    error::{AppError, AppResult},
+    model::{
+        FontReport, ImageReport, PdfMetadata, PreflightFinding, PreflightReport, Severity, Venue,
+    },
+    render::{self, Region},
+};
+
+/// Form XObjects inside form XObjects: a figure that includes a figure. Past
+/// this the nesting is a cycle, which a malformed PDF can contain.
+const MAX_DEPTH: usize = 8;
+/// What both venues ask of colour and greyscale images.
+const MIN_DPI: f32 = 300.0;
+/// And of line art, which is one bit per pixel and shows its steps sooner.
+const MIN_MONOCHROME_DPI: f32 = 600.0;
+/// How close an image's shape on the page has to be to its shape in pixels
+/// for the two to be taken as the same image.
+const ASPECT_TOLERANCE: f32 = 0.02;
+/// Pages named in a finding before the rest are summarised.
+const PAGES_NAMED: usize = 5;
+
+/// Reads everything a preflight needs from the PDF, and assesses it.
+pub fn inspect(document: &PdfDocument) -> AppResult<PreflightReport> {
+    let page_count = render::page_count(document)?;
+    let mut fonts = BTreeMap::<(String, String), FontReport>::new();
+    let mut images = Vec::new();
+
+    for index in 0..page_count {
+        let page = document
+            .find_page(index as i32)
+            .map_err(|error| AppError::Build(format!("could not read a page: {error}")))?;
+        let mut found = Vec::new();
+        if let Some(resources) = dictionary(&page, "Resources") {
+            walk(&resources, index + 1, 0, &mut fonts, &mut found);
+        }
+        if !found.is_empty() {
+            // Only the drawing says how large an image is printed, and MuPDF's
+            // text layer is what has read the drawing.
+            let placed = render::layout(document, index)
+                .map(|layout| layout.images)
+                .unwrap_or_default();
+            resolve_dpi(&mut found, &placed);
+        }
+        images.extend(found);
+    }
+
+    let metadata = |name| {
+        document
+            .metadata(name)
+            .ok()
+            .map(|value| value.trim().to_owned())
+            .filter(|value| !value.is_empty())
+    };
+    let pdf_version = metadata(MetadataName::Format)
+        .map(|format| format.trim_start_matches("PDF").trim().to_owned());
+    let encrypted = metadata(MetadataName::Encryption).is_some_and(|value| value != "None");
+
+    let mut report = PreflightReport {
+        pdf_version,
+        encrypted,
+        page_count,
+        metadata: PdfMetadata {
+            title: metadata(MetadataName::Title),
+            author: metadata(MetadataName::Author),
+            subject: metadata(MetadataName::Subject),
+            keywords: metadata(MetadataName::Keywords),
+            creator: metadata(MetadataName::Creator),
+            producer: metadata(MetadataName::Producer),
+        },
+        fonts: fonts.into_values().collect(),
+        images,
+        findings: Vec::new(),
+    };
+    report.findings = assess(&report);
+    Ok(report)
+}
+
+/// The fonts and images in one resource dictionary, and in the resources of
+/// every form it draws.
+fn walk(
+    resources: &PdfObject,
+    page: usize,
+    depth: usize,
+    fonts: &mut BTreeMap<(String, String), FontReport>,
+    images: &mut Vec<ImageReport>,
+) {
+    if depth > MAX_DEPTH {
+        return;
+    }
+    if let Some(entries) = dictionary(resources, "Font") {
+        for font in values(&entries) {
+            let report = font_report(&font, page);
+            let key = (report.name.clone(), report.kind.clone());
+            let entry = fonts.entry(key).or_insert(report);
+            if !entry.pages.contains(&page) {
+                entry.pages.push(page);
+            }
+        }
+    }
+    if let Some(entries) = dictionary(resources, "XObject") {
+        for object in values(&entries) {
+            match name(&object, "Subtype").as_deref() {
+                Some("Image") => {
+                    let monochrome = object
+                        .get_dict("ImageMask")
+                        .ok()
+                        .flatten()
+                        .and_then(|mask| mask.as_bool().ok())
+                        .unwrap_or(false)
+                        || integer(&object, "BitsPerComponent") == Some(1);
+                    images.push(ImageReport {
+                        page,
+                        width: integer(&object, "Width").unwrap_or_default(),
+                        height: integer(&object, "Height").unwrap_or_default(),
+                        dpi: None,
+                        color_space: name(&object, "ColorSpace").or_else(|| {
+                            // An ICC or indexed space is an array led by its kind.
+                            let space = dictionary(&object, "ColorSpace")?;
+                            let first = space.get_array(0).ok()??;
+                            Some(String::from_utf8_lossy(first.as_name().ok()?).into_owned())
+                        }),
+                        monochrome,
+                    });
+                }
+                Some("Form") => {
+                    if let Some(inner) = dictionary(&object, "Resources") {
+                        walk(&inner, page, depth + 1, fonts, images);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn font_report(font: &PdfObject, page: usize) -> FontReport {
+    let kind = name(font, "Subtype").unwrap_or_else(|| "Unknown".to_owned());
+    let base = name(font, "BaseFont")
+        .or_else(|| name(font, "Name"))
+        .unwrap_or_else(|| format!("(unnamed {kind})"));
+    let (subset, name) = match base.split_once('+') {
+        Some((tag, rest))
+            if tag.len() == 6 && tag.chars().all(|letter| letter.is_ascii_uppercase()) =>
+        {
+            (true, rest.to_owned())
+        }
+        _ => (false, base),
+    };
+    // A composite font keeps its descriptor on the font it is built from.
+    let descriptor = if kind == "Type0" {
+        dictionary(font, "DescendantFonts")
+            .and_then(|fonts| fonts.get_array(0).ok().flatten())
+            .and_then(|descendant| dictionary(&descendant, "FontDescriptor"))
+    } else {
+        dictionary(font, "FontDescriptor")
+    };
+    let embedded = kind == "Type3"
+        || descriptor.is_some_and(|descriptor| {
+            ["FontFile", "FontFile2", "FontFile3"]
+                .iter()
+                .any(|key| dictionary(&descriptor, key).is_some())
+        });
+    FontReport {
+        name,
+        kind,
+        embedded,
+        subset,
+        pages: vec![page],
+    }
+}
+
+/// Pairs each image with a place it is drawn on the page, by shape. Two images
+/// of one shape on a page cannot be told apart this way, and are left without
+/// a resolution rather than given each other's.
+fn resolve_dpi(images: &mut [ImageReport], placed: &[Region]) {
+    for image in images.iter_mut() {
+        if image.width <= 0 || image.height <= 0 {
+            continue;
+        }
+        let aspect = image.width as f32 / image.height as f32;
+        let matching = placed
+            .iter()
+            .filter(|region| region.width > 0.0 && region.height > 0.0)
+            .filter(|region| {
+                ((region.width / region.height) / aspect - 1.0).abs() <= ASPECT_TOLERANCE
+            })
+            .collect::<Vec<_>>();
+        if let [region] = matching.as_slice() {
+            image.dpi = Some(image.width as f32 / (region.width / 72.0));
+        }
+    }
+}
+
+/// Everything in `report` that IEEE PDF eXpress or ACM TAPS would reject or
+/// complain about.
+pub fn assess(report: &PreflightReport) -> Vec<PreflightFinding> {
+    let both = vec![Venue::IeeePdfExpress, Venue::AcmTaps];
+    let mut findings = Vec::new();
+
+    for font in report.fonts.iter().filter(|font| !font.embedded) {
+        findings.push(PreflightFinding {
+            severity: Severity::Error,
+            message: format!(
+                "{} is not embedded ({}). It usually comes from an included figure; \
+                 export the figure with its fonts embedded.",
+                font.name,
+                pages(&font.pages)
+            ),
+            venues: both.clone(),
+        });
+    }
+    for font in report.fonts.iter().filter(|font| font.kind == "Type3") {
+        findings.push(PreflightFinding {
+            severity: Severity::Error,
+            message: format!(
+                "{} is a Type 3 font ({}). These are usually bitmap fonts from dvips or \
+                 a plot saved without outline fonts.",
+                font.name,
+                pages(&font.pages)
+            ),
+            venues: vec![Venue::IeeePdfExpress],
+        });
+    }
+
+    let low = report
+        .images
+        .iter()
+        .filter(|image| {
+            let minimum = if image.monochrome {
+                MIN_MONOCHROME_DPI
+            } else {
+                MIN_DPI
+            };
+            image.dpi.is_some_and(|dpi| dpi < minimum)
+        })
+        .collect::<Vec<_>>();
+    if !low.is_empty() {
+        let lowest = low
+            .iter()
+            .filter_map(|image| image.dpi)
+            .fold(f32::INFINITY, f32::min);
+        let mut on = low.iter().map(|image| image.page).collect::<Vec<_>>();
+        on.dedup();
+        findings.push(PreflightFinding {
+            severity: Severity::Warning,
+            message: format!(
+                "{} printed below {MIN_DPI:.0} dpi, the lowest at {lowest:.0} dpi ({})",
+                match low.len() {
+                    1 => "An image is".to_owned(),
+                    count => format!("{count} images are"),
+                },
+                pages(&on)
+            ),
+            venues: both.clone(),
+        });
+    }
+
+    if report.encrypted {
+        findings.push(PreflightFinding {
+            severity: Severity::Error,
+            message: "The PDF is encrypted. Submission systems need to open and edit it."
+                .to_owned(),
+            venues: both.clone(),
+        });
+    }
+    let unaccepted =
+        |stated: &&str| version(stated).is_some_and(|number| !(1.4..=1.7).contains(&number));
+    if let Some(stated) = report.pdf_version.as_deref().filter(unaccepted) {
+        findings.push(PreflightFinding {
+            severity: Severity::Error,
+            message: format!("The PDF is version {stated}; PDF eXpress accepts 1.4 to 1.7."),
+            venues: vec![Venue::IeeePdfExpress],
+        });
+    }
+
+    for (field, value) in [
+        ("title", &report.metadata.title),
+        ("author", &report.metadata.author),
+    ] {
+        if value.is_none() {
+            findings.push(PreflightFinding {
+                severity: Severity::Warning,
+                message: format!(
+                    "The PDF has no {field} in its metadata. hyperref's `pdf{field}` sets it."
+                ),
+                venues: vec![Venue::AcmTaps],
+            });
+        }
+    }
+
+    findings
+}
+
+fn version(text: &str) -> Option<f32> {
+    text.split_whitespace().next()?.parse().ok()
+}
+
+/// "page 3", "pages 1, 2 and 4", "pages 1, 2, 3, 4, 5 and 12 more".
+fn pages(pages: &[usize]) -> String {
+    let named = pages
+        .iter()
+        .take(PAGES_NAMED)
+        .map(usize::to_string)
+        .collect::<Vec<_>>();
+    match (named.as_slice(), pages.len().saturating_sub(PAGES_NAMED)) {
+        ([], _) => "no pages".to_owned(),
+        ([only], _) => format!("page {only}"),
+        (named, 0) => {
+            let (last, rest) = named.split_last().expect("more than one page");
+            format!("pages {} and {last}", rest.join(", "))
+        }
+        (named, more) => format!("pages {} and {more} more", named.join(", ")),
+    }
+}
+
+fn dictionary(object: &PdfObject, key: &str) -> Option<PdfObject> {
+    object.get_dict(key).ok().flatten()
+}
+
+fn name(object: &PdfObject, key: &str) -> Option<String> {
+    let value = dictionary(object, key)?;
+    let bytes = value.as_name().ok()?;
+    Some(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn integer(object: &PdfObject, key: &str) -> Option<i64> {
+    dictionary(object, key)?.as_int().ok().map(i64::from)
+}
+
+/// The values of a dictionary, in order.
+fn values(object: &PdfObject) -> Vec<PdfObject> {
+    let length = object.len().unwrap_or_default();
+    (0..length)
+        .filter_map(|index| object.get_dict_val(index as i32).ok().flatten())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font(name: &str, kind: &str, embedded: bool, pages: Vec<usize>) -> FontReport {
+        FontReport {
+            name: name.to_owned(),
+            kind: kind.to_owned(),
+            embedded,
+            subset: embedded,
+            pages,
+        }
+    }
+
+    fn image(page: usize, dpi: Option<f32>, monochrome: bool) -> ImageReport {
+        ImageReport {
+            page,
+            width: 600,
+            height: 400,
+            dpi,
+            color_space: Some("DeviceRGB".to_owned()),
+            monochrome,
+        }
+    }
+
+    fn clean() -> PreflightReport {
+        PreflightReport {
+            pdf_version: Some("1.5".to_owned()),
+            encrypted: false,
+            page_count: 8,
+            metadata: PdfMetadata {
+                title: Some("A Paper".to_owned()),
+                author: Some("A. Author".to_owned()),
+                ..PdfMetadata::default()
+            },
+            fonts: vec![font("CMR10", "Type1", true, vec![1, 2, 3])],
+            images: vec![image(2, Some(450.0), false)],
+            findings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn a_clean_paper_passes() {
+        assert!(assess(&clean()).is_empty());
+    }
+
+    #[test]
+    fn a_figure_with_helvetica_referenced_is_rejected_by_both() {
+        let mut report = clean();
+        report
+            .fonts
+            .push(font("Helvetica", "Type1", false, vec![4, 6]));
+
+        let findings = assess(&report);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Error);
+        assert!(
+            findings[0]
+                .message
+                .starts_with("Helvetica is not embedded (pages 4 and 6)")
+        );
+        assert_eq!(
+            findings[0].venues,
+            vec![Venue::IeeePdfExpress, Venue::AcmTaps]
+        );
+    }
+
+    #[test]
+    fn type_three_fonts_old_versions_and_coarse_images_are_flagged() {
+        let mut report = clean();
+        report.fonts.push(font("T3Font_0", "Type3", true, vec![5]));
+        report.pdf_version = Some("1.3".to_owned());
+        report.images = vec![
+            image(2, Some(150.0), false),
+            image(2, Some(400.0), true),
+            image(3, None, false),
+        ];
+        report.metadata.title = None;
+
+        let messages = assess(&report)
+            .into_iter()
+            .map(|finding| finding.message)
+            .collect::<Vec<_>>();
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.contains("Type 3 font (page 5)"))
+        );
+        assert!(
+            messages
+                .iter()
+                .any(|message| message.contains("version 1.3"))
+        );
+        assert!(messages.iter().any(|message| {
+            message
+                .starts_with("2 images are printed below 300 dpi, the lowest at 150 dpi (page 2)")
+        }));
+        assert!(messages.iter().any(|message| message.contains("no title")));
+        assert_eq!(messages.len(), 4);
+    }
+
+    #[test]
+    fn an_image_is_matched_to_its_placement_by_shape() {
+        let mut images = vec![image(1, None, false)];
+        let placed = [
+            Region {
+                x: 72.0,
+                y: 72.0,
+                width: 144.0,
+                height: 96.0,
+            },
+            Region {
+                x: 72.0,
+                y: 300.0,
+                width: 400.0,
+                height: 100.0,
+            },
+        ];
+
+        resolve_dpi(&mut images, &placed);
+        // 600 pixels across two inches.
+        assert_eq!(images[0].dpi, Some(300.0));
+
+        let mut ambiguous = vec![image(1, None, false)];
+        resolve_dpi(&mut ambiguous, &[placed[0], placed[0]]);
+        assert_eq!(ambiguous[0].dpi, None);
+    }
+}
@@ -0,0 +1,351 @@
+//! The prose of a document, picked out of its source.
+//!
+//! Anything that reads what an author wrote rather than what TeX made of it —
+//! the spelling check, the word count — needs the same thing first: the
+//! source with commands, math, verbatim, front matter and code blanked out.
+//! Blanked rather than removed, line for line and column for column, so that
+//! whatever is found in the result is at the same place in the file.
+
+use crate::{
+    anchors::{self, LineKind},
+    documents::strip_comment,
+};
+
+/// Environments whose contents are not prose. A trailing `*` is ignored.
+const SKIPPED_ENVIRONMENTS: &[&str] = &[
+    "verbatim",
+    "Verbatim",
+    "lstlisting",
+    "minted",
+    "comment",
+    "filecontents",
+    "equation",
+    "align",
+    "alignat",
+    "flalign",
+    "gather",
+    "multline",
+    "eqnarray",
+    "displaymath",
+    "math",
+    "tikzpicture",
+    "thebibliography",
+];
+
+/// Commands whose arguments are names, keys or paths rather than prose. Every
+/// argument group that follows one is skipped.
+const ARGUMENT_COMMANDS: &[&str] = &[
+    "label",
+    "ref",
+    "eqref",
+    "pageref",
+    "autoref",
+    "cref",
+    "Cref",
+    "nameref",
+    "cite",
+    "citep",
+    "citet",
+    "citeauthor",
+    "citeyear",
+    "parencite",
+    "textcite",
+    "autocite",
+    "footcite",
+    "nocite",
+    "bibitem",
+    "documentclass",
+    "usepackage",
+    "RequirePackage",
+    "input",
+    "include",
+    "includeonly",
+    "subfile",
+    "includegraphics",
+    "graphicspath",
+    "url",
+    "href",
+    "bibliography",
+    "bibliographystyle",
+    "addbibresource",
+    "begin",
+    "end",
+    "newcommand",
+    "renewcommand",
+    "providecommand",
+    "newenvironment",
+    "renewenvironment",
+    "DeclareMathOperator",
+    "newtheorem",
+    "setlength",
+    "addtolength",
+    "setcounter",
+    "hypersetup",
+    "definecolor",
+    "color",
+    "pagestyle",
+    "thispagestyle",
+    "vspace",
+    "hspace",
+];
+
+/// A LaTeX source with everything that is not prose blanked out. The preamble
+/// goes with it, when there is one.
+pub fn latex(text: &str) -> Vec<String> {
+    let mut scanner = LatexScanner {
+        // A chapter pulled in with `\input` has no preamble; the whole file is
+        // body.
+        in_body: !text.contains("\\begin{document}"),
+        environment: None,
+        math: None,
+    };
+    text.lines().map(|line| scanner.line(line)).collect()
+}
+
+struct LatexScanner {
+    in_body: bool,
+    /// A skipped environment that is still open, star included.
+    environment: Option<String>,
+    /// What closes the math that is still open.
+    math: Option<&'static str>,
+}
+
+impl LatexScanner {
+    fn line(&mut self, line: &str) -> String {
+        let characters = line.chars().collect::<Vec<_>>();
+        let mut prose = vec![' '; characters.len()];
+        if !self.in_body {
+            self.in_body = line.contains("\\begin{document}");
+            return prose.into_iter().collect();
+        }
+        let end = strip_comment(line).chars().count();
+        let characters = &characters[..end];
+        let mut index = 0;
+        while index < end {
+            if let Some(environment) = &self.environment {
+                let closing = format!("\\end{{{environment}}}");
+                match find(characters, index, &closing) {
+                    Some(at) => {
+                        index = at + closing.chars().count();
+                        self.environment = None;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            if let Some(closing) = self.math {
+                match find(characters, index, closing) {
+                    Some(at) => {
+                        index = at + closing.chars().count();
+                        self.math = None;
+                    }
+                    None => break,
+                }
+                continue;
+            }
+            match characters[index] {
+                '$' if characters.get(index + 1) == Some(&'$') => {
+                    self.math = Some("$$");
+                    index += 2;
+                }
+                '$' => {
+                    self.math = Some("$");
+                    index += 1;
+                }
+                '\\' => index = self.command(characters, index),
+                character => {
+                    prose[index] = character;
+                    index += 1;
+                }
+            }
+        }
+        prose.into_iter().collect()
+    }
+
+    /// Steps over the command at `start`, and over its arguments when they are
+    /// not prose. Answers where scanning resumes.
+    fn command(&mut self, characters: &[char], start: usize) -> usize {
+        let index = start + 1;
+        let Some(&next) = characters.get(index) else {
+            return index;
+        };
+        if !next.is_ascii_alphabetic() {
+            match next {
+                '(' => self.math = Some("\\)"),
+                '[' => self.math = Some("\\]"),
+                _ => {}
+            }
+            return index + 1;
+        }
+        let name_end = index
+            + characters[index..]
+                .iter()
+                .take_while(|character| character.is_ascii_alphabetic())
+                .count();
+        let name = characters[index..name_end].iter().collect::<String>();
+        match name.as_str() {
+            // `\verb|x|`: the delimiter is whatever follows.
+            "verb" => {
+                let open = name_end + usize::from(characters.get(name_end) == Some(&'*'));
+                let Some(&delimiter) = characters.get(open) else {
+                    return open;
+                };
+                characters[open + 1..]
+                    .iter()
+                    .position(|character| *character == delimiter)
+                    .map_or(characters.len(), |at| open + 1 + at + 1)
+            }
+            "begin" => {
+                let Some((environment, after)) = group(characters, name_end) else {
+                    return name_end;
+                };
+                if SKIPPED_ENVIRONMENTS.contains(&environment.trim_end_matches('*')) {
+                    self.environment = Some(environment);
+                    return after;
+                }
+                skip_arguments(characters, after)
+            }
+            name if ARGUMENT_COMMANDS.contains(&name) => skip_arguments(characters, name_end),
+            _ => name_end,
+        }
+    }
+}
+
+/// The braced group at `index`, and where it ends.
+fn group(characters: &[char], index: usize) -> Option<(String, usize)> {
+    if characters.get(index) != Some(&'{') {
+        return None;
+    }
+    let close = closing(characters, index)?;
+    Some((characters[index + 1..close].iter().collect(), close + 1))
+}
+
+/// Steps over a star and every `[...]` or `{...}` group that follows, and
+/// answers where they end. An argument that runs past the end of the line
+/// takes the rest of the line with it.
+pub fn skip_arguments(characters: &[char], mut index: usize) -> usize {
+    if characters.get(index) == Some(&'*') {
+        index += 1;
+    }
+    while matches!(characters.get(index), Some('[' | '{')) {
+        match closing(characters, index) {
+            Some(close) => index = close + 1,
+            None => return characters.len(),
+        }
+    }
+    index
+}
+
+/// The bracket that closes the one at `open`, counting nesting and skipping
+/// escaped brackets.
+pub fn closing(characters: &[char], open: usize) -> Option<usize> {
+    let (opener, closer) = match characters[open] {
+        '[' => ('[', ']'),
+        _ => ('{', '}'),
+    };
+    let mut depth = 0;
+    let mut index = open;
+    while index < characters.len() {
+        match characters[index] {
+            '\\' => index += 1,
+            character if character == opener => depth += 1,
+            character if character == closer => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(index);
+                }
+            }
+            _ => {}
+        }
+        index += 1;
+    }
+    None
+}
+
+pub fn find(characters: &[char], from: usize, needle: &str) -> Option<usize> {
+    let needle = needle.chars().collect::<Vec<_>>();
+    (from..=characters.len().checked_sub(needle.len())?)
+        .find(|&at| characters[at..at + needle.len()] == needle[..])
+}
+
+/// The markdown counterpart, following the block structure `anchors` already
+/// reads: front matter and fenced code are skipped whole, and within a block
+/// inline code, math, link targets, tags, raw LaTeX and citations are.
+pub fn markdown(text: &str) -> Vec<String> {
+    let mut display_math = false;
+    anchors::lines(text)
+        .into_iter()
+        .map(|line| {
+            let characters = line.text.chars().collect::<Vec<_>>();
+            let mut prose = vec![' '; characters.len()];
+            if line.kind == LineKind::Text {
+                markdown_line(&characters, &mut prose, &mut display_math);
+            }
+            prose.into_iter().collect()
+        })
+        .collect()
+}
+
+fn markdown_line(characters: &[char], prose: &mut [char], display_math: &mut bool) {
+    let mut index = 0;
+    while index < characters.len() {
+        if *display_math {
+            match find(characters, index, "$$") {
+                Some(at) => {
+                    index = at + 2;
+                    *display_math = false;
+                }
+                None => return,
+            }
+            continue;
+        }
+        let character = characters[index];
+        let skip_to = match character {
+            '`' => {
+                let run = characters[index..]
+                    .iter()
+                    .take_while(|tick| **tick == '`')
+                    .count();
+                let fence = "`".repeat(run);
+                find(characters, index + run, &fence).map_or(characters.len(), |at| at + run)
+            }
+            '$' if characters.get(index + 1) == Some(&'$') => {
+                *display_math = true;
+                index + 2
+            }
+            // pandoc only reads `$...$` as math when it closes on the line.
+            '$' => find(characters, index + 1, "$").map_or(index + 1, |at| at + 1),
+            '\\' if characters
+                .get(index + 1)
+                .is_some_and(char::is_ascii_alphabetic) =>
+            {
+                let name_end = index
+                    + 1
+                    + characters[index + 1..]
+                        .iter()
+                        .take_while(|letter| letter.is_ascii_alphabetic())
+                        .count();
+                let name = characters[index + 1..name_end].iter().collect::<String>();
+                if ARGUMENT_COMMANDS.contains(&name.as_str()) {
+                    skip_arguments(characters, name_end)
+                } else {
+                    name_end
+                }
+            }
+            '\\' => index + 2,
+            '<' => find(characters, index, ">").map_or(index + 1, |at| at + 1),
+            '(' if index > 0 && characters[index - 1] == ']' => {
+                find(characters, index, ")").map_or(characters.len(), |at| at + 1)
+            }
+            // pandoc's attributes: `{#id .class}`, `{-}`.
+            '{' if matches!(characters.get(index + 1), Some('#' | '.' | '-')) => {
+                find(characters, index, "}").map_or(index + 1, |at| at + 1)
+            }
+            _ => {
+                prose[index] = character;
+                index + 1
+            }
+        };
+        index = skip_to.min(characters.len());
+    }
+}
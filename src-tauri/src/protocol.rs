@@ -6,7 +6,7 @@
 //! a PNG encode in Rust and a decode in the webview — both of which would cost
 //! more than drawing the page did.
 
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 use tauri::{
     Manager, Runtime, UriSchemeContext, UriSchemeResponder,
@@ -16,10 +16,15 @@ use tauri::{
 use crate::{
     AppState,
     error::{AppError, AppResult},
+    render::{MOST_THUMBNAIL_WIDTH, Tile},
 };
 
 pub const SCHEME: &str = "press";
 
+/// Deeper than a whole page may be drawn: a tile only ever draws a window's
+/// worth of pixels however far in the reader has zoomed.
+const MOST_TILE_SCALE: f32 = 64.0;
+
 pub fn handle<R: Runtime>(
     context: UriSchemeContext<'_, R>,
     request: Request<Vec<u8>>,
@@ -80,6 +85,56 @@ async fn serve<R: Runtime>(
             let invert = query_value(uri.query(), "invert").is_some_and(|value| value == "1");
             render(app, artifact_id, page, scale, invert).await
         }
+        // /tile/{artifact}/{revision}/{index}?scale=8&x=4096&y=2048&width=512&height=512&invert=1
+        // Part of a page at a scale too deep to draw it whole. The rectangle
+        // is in device pixels of the page drawn at `scale`.
+        ["tile", artifact, _revision, index] => {
+            let artifact_id = artifact
+                .parse::<i64>()
+                .map_err(|_| AppError::InvalidInput("malformed artifact id".into()))?;
+            let page = index
+                .parse::<usize>()
+                .map_err(|_| AppError::InvalidInput("malformed page number".into()))?;
+            let scale = query_value(uri.query(), "scale")
+                .and_then(|value| value.parse::<f32>().ok())
+                .filter(|scale| scale.is_finite() && *scale > 0.0 && *scale <= MOST_TILE_SCALE)
+                .ok_or_else(|| AppError::InvalidInput("missing or unusable scale".into()))?;
+            let pixels = |key: &str| {
+                query_value(uri.query(), key)
+                    .and_then(|value| value.parse::<u32>().ok())
+                    .ok_or_else(|| AppError::InvalidInput(format!("missing or unusable {key}")))
+            };
+            let tile = Tile {
+                x: pixels("x")?,
+                y: pixels("y")?,
+                width: pixels("width")?,
+                height: pixels("height")?,
+            };
+            let invert = query_value(uri.query(), "invert").is_some_and(|value| value == "1");
+            let path = resolve(app, artifact_id).await?;
+            let state = app.state::<AppState>();
+            let rendered = state.renderer.tile(path, page, scale, tile, invert).await?;
+            ok(frame(rendered))
+        }
+        // /thumbnail/{artifact}/{revision}/{index}?width=160&invert=1
+        // A small PNG of one page, for a strip of them or a version's preview.
+        ["thumbnail", artifact, _revision, index] => {
+            let artifact_id = artifact
+                .parse::<i64>()
+                .map_err(|_| AppError::InvalidInput("malformed artifact id".into()))?;
+            let page = index
+                .parse::<usize>()
+                .map_err(|_| AppError::InvalidInput("malformed page number".into()))?;
+            let width = query_value(uri.query(), "width")
+                .and_then(|value| value.parse::<u32>().ok())
+                .filter(|width| (1..=MOST_THUMBNAIL_WIDTH).contains(width))
+                .ok_or_else(|| AppError::InvalidInput("missing or unusable width".into()))?;
+            let invert = query_value(uri.query(), "invert").is_some_and(|value| value == "1");
+            let path = resolve(app, artifact_id).await?;
+            let state = app.state::<AppState>();
+            let png = state.renderer.thumbnail(path, page, width, invert).await?;
+            respond(png, "image/png")
+        }
         // /preview/{digest}/{index}?scale=2.6&invert=1
         // A preset's compiled sample. The digest is the address and the file
         // name both, so there is no registry to consult — but it arrives from
@@ -132,20 +187,22 @@ async fn render<R: Runtime>(
 ///
 /// The rasteriser leaves exactly this much room at the front of the page it
 /// draws, so this writes the header rather than copying a page to make space
-/// for it.
-fn frame(rendered: crate::render::RenderedPage) -> Vec<u8> {
-    let mut header = [0_u8; PAGE_HEADER_BYTES];
-    header[..4].copy_from_slice(&rendered.width.to_le_bytes());
-    header[4..].copy_from_slice(&rendered.height.to_le_bytes());
-    rendered.into_framed(header)
+/// for it. A page the cache still holds is copied once, here, since the cache's
+/// own must stay as it is.
+fn frame(rendered: Arc<crate::render::RenderedPage>) -> Vec<u8> {
+    Arc::unwrap_or_clone(rendered).framed()
 }
 
 pub const PAGE_HEADER_BYTES: usize = crate::render::RenderedPage::PREFIX;
 
 fn ok(body: Vec<u8>) -> AppResult<Response<Vec<u8>>> {
+    respond(body, "application/octet-stream")
+}
+
+fn respond(body: Vec<u8>, content_type: &str) -> AppResult<Response<Vec<u8>>> {
     Response::builder()
         .status(StatusCode::OK)
-        .header("Content-Type", "application/octet-stream")
+        .header("Content-Type", content_type)
         // The page is fetched from a different origin than the document: the
         // dev server is http://localhost:5173 and the bundled app is
         // tauri://localhost, while this is press://localhost. Without this the
@@ -173,7 +230,7 @@ pub async fn resolve<R: Runtime>(
     if let Some(path) = state.viewing.path(artifact_id) {
         return Ok(path);
     }
-    let repository = std::sync::Arc::clone(&state.repository);
+    let repository = Arc::clone(&state.repository);
     let stored = tauri::async_runtime::spawn_blocking(move || repository.artifact(artifact_id))
         .await
         .map_err(|error| AppError::Task(error.to_string()))??;
@@ -5,11 +5,25 @@
 //! rest of the application talks to it over a channel. That is also what keeps
 //! rasterisation off the UI thread, which is the whole point.
 
-use std::path::{Path, PathBuf};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
-use mupdf::{Colorspace, Document, Matrix, TextExtractOptions, TextPageFlags};
+use mupdf::{
+    Colorspace, Device, Document, Matrix, Pixmap, TextBlockType, TextExtractOptions, TextPageFlags,
+    pdf::PdfDocument,
+};
 
-use crate::error::{AppError, AppResult};
+use crate::{
+    error::{AppError, AppResult},
+    model::{
+        Annotation, ImageFormat, OutlineEntry, PageRegion, PreflightReport, PrintOptions,
+        SearchMatch,
+    },
+    outline,
+    search::Query,
+};
 
 /// Words carry enough structure for a selection overlay without one DOM node
 /// per glyph.
@@ -39,6 +53,7 @@ pub struct PageGeometry {
 ///
 /// The buffer opens with [`RenderedPage::PREFIX`] free bytes. Whoever ships the
 /// page writes its dimensions there; see [`into_framed`](Self::into_framed).
+#[derive(Clone)]
 pub struct RenderedPage {
     pub width: u32,
     pub height: u32,
@@ -69,6 +84,37 @@ impl RenderedPage {
         &mut self.buffer[Self::PREFIX..]
     }
 
+    /// How much memory the page holds.
+    pub fn byte_size(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// A page as [`framed`](Self::framed) wrote it, if that is what `buffer`
+    /// is: its own dimensions in front and exactly that many pixels after.
+    pub fn from_framed(buffer: Vec<u8>) -> Option<Self> {
+        let header = buffer.get(..Self::PREFIX)?;
+        let width = u32::from_le_bytes(header[..4].try_into().ok()?);
+        let height = u32::from_le_bytes(header[4..].try_into().ok()?);
+        let expected = (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(4)?
+            .checked_add(Self::PREFIX)?;
+        (buffer.len() == expected).then_some(Self {
+            width,
+            height,
+            buffer,
+        })
+    }
+
+    /// The whole buffer with the page's dimensions in front, as the webview
+    /// reads it: two little-endian `u32`s, width then height.
+    pub fn framed(self) -> Vec<u8> {
+        let mut header = [0_u8; Self::PREFIX];
+        header[..4].copy_from_slice(&self.width.to_le_bytes());
+        header[4..].copy_from_slice(&self.height.to_le_bytes());
+        self.into_framed(header)
+    }
+
     /// The whole buffer, with `prefix` written into the space kept for it. No
     /// copy: this is the same allocation MuPDF's samples were expanded into.
     pub fn into_framed(mut self, prefix: [u8; Self::PREFIX]) -> Vec<u8> {
@@ -98,17 +144,50 @@ pub struct Link {
     pub uri: Option<String>,
 }
 
-/// A search hit, as a rectangle in PDF points.
+/// One printed line: where it sits and the size it is set in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextLine {
+    pub text: String,
+    /// In PDF points, with the origin at the top left of the page.
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+    /// The size most of the line is set in, in points. A subscript or a
+    /// footnote mark is smaller than the line it sits in, and says nothing
+    /// about what size the text is.
+    pub size: f32,
+}
+
+/// One entry in the PDF's own outline, flattened: `level` is how deeply it was
+/// nested.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bookmark {
+    pub title: String,
+    pub level: usize,
+    /// 1-based, when it leads somewhere in this document.
+    pub page: Option<usize>,
+    pub top: Option<f32>,
+}
+
+/// Where something sits on a page, in PDF points from the top left.
 #[derive(Debug, Clone, Copy, PartialEq)]
-pub struct Hit {
-    pub page: usize,
+pub struct Region {
     pub x: f32,
     pub y: f32,
     pub width: f32,
     pub height: f32,
 }
 
-fn mupdf_error(context: &str, error: mupdf::Error) -> AppError {
+/// What is printed on one page: its lines of text, and the images between
+/// them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageLayout {
+    pub lines: Vec<TextLine>,
+    pub images: Vec<Region>,
+}
+
+pub fn mupdf_error(context: &str, error: mupdf::Error) -> AppError {
     AppError::Build(format!("{context}: {error}"))
 }
 
@@ -119,6 +198,14 @@ pub fn open(path: &Path) -> AppResult<Document> {
     Document::open(path).map_err(|error| mupdf_error("could not open the PDF", error))
 }
 
+/// The same file, opened for its objects rather than its pages.
+pub fn open_pdf(path: &Path) -> AppResult<PdfDocument> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| AppError::InvalidInput("PDF path is not valid UTF-8".into()))?;
+    PdfDocument::open(path).map_err(|error| mupdf_error("could not open the PDF", error))
+}
+
 pub fn page_count(document: &Document) -> AppResult<usize> {
     document
         .page_count()
@@ -174,6 +261,288 @@ pub fn render_page(
     Ok(page)
 }
 
+/// The longest side a tile may have, in pixels. A viewer asks for tiles about
+/// the size of its window or smaller; past this is not a tile.
+const MOST_TILE_PIXELS: u32 = 4_096;
+
+/// A device-pixel rectangle of a page drawn at some scale: the part of it a
+/// deeply zoomed viewer can actually see.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Rasterises the part of one page that `tile` covers, exactly as that part of
+/// [`render_page`] at the same scale would come out.
+///
+/// The tile is in device pixels rather than points, so neighbouring tiles meet
+/// on the pixel grid and no seam shows between them. Whatever of it lies past
+/// the page is cut off; a tile wholly off the page is refused.
+pub fn render_tile(
+    document: &Document,
+    index: usize,
+    scale: f32,
+    tile: Tile,
+    invert: bool,
+) -> AppResult<RenderedPage> {
+    let page = document
+        .load_page(index as i32)
+        .map_err(|error| mupdf_error("could not load the page", error))?;
+    let bounds = page
+        .bounds()
+        .map_err(|error| mupdf_error("could not measure the page", error))?;
+    if tile.width.max(tile.height) > MOST_TILE_PIXELS {
+        return Err(AppError::InvalidInput("the tile is too large".into()));
+    }
+    // The page's own size at this scale, rounded the way a whole-page
+    // rasterisation rounds it.
+    let page_width = ((bounds.x1 - bounds.x0) * scale).ceil() as u32;
+    let page_height = ((bounds.y1 - bounds.y0) * scale).ceil() as u32;
+    let width = tile.width.min(page_width.saturating_sub(tile.x));
+    let height = tile.height.min(page_height.saturating_sub(tile.y));
+    if width == 0 || height == 0 {
+        return Err(AppError::InvalidInput("the tile is not on the page".into()));
+    }
+
+    let mut pixmap = Pixmap::new_with_w_h(
+        &Colorspace::device_rgb(),
+        width as i32,
+        height as i32,
+        false,
+    )
+    .map_err(|error| mupdf_error("could not make room for the tile", error))?;
+    pixmap
+        .clear_with(255)
+        .map_err(|error| mupdf_error("could not clear the tile", error))?;
+    // Scaled, then moved so the tile's corner is the pixmap's.
+    let matrix = Matrix::new(
+        scale,
+        0.0,
+        0.0,
+        scale,
+        -bounds.x0 * scale - tile.x as f32,
+        -bounds.y0 * scale - tile.y as f32,
+    );
+    {
+        let device = Device::from_pixmap(&pixmap)
+            .map_err(|error| mupdf_error("could not draw the tile", error))?;
+        page.run(&device, &matrix)
+            .map_err(|error| mupdf_error("could not draw the page", error))?;
+    }
+    let mut rendered = RenderedPage {
+        width,
+        height,
+        buffer: to_rgba(pixmap.samples(), pixmap.n()),
+    };
+    if invert {
+        darken(rendered.samples_mut());
+    }
+    Ok(rendered)
+}
+
+/// The widest a thumbnail may be, in pixels. Past this it is a page, and
+/// should be asked for as one.
+pub const MOST_THUMBNAIL_WIDTH: u32 = 512;
+
+/// A page drawn `width` pixels wide, as a PNG: small enough for a strip of
+/// every page or a preview beside each version, and an image the webview
+/// decodes on its own.
+pub fn thumbnail(
+    document: &Document,
+    index: usize,
+    width: u32,
+    invert: bool,
+) -> AppResult<Vec<u8>> {
+    if width == 0 || width > MOST_THUMBNAIL_WIDTH {
+        return Err(AppError::InvalidInput(format!(
+            "a thumbnail is 1 to {MOST_THUMBNAIL_WIDTH} pixels wide"
+        )));
+    }
+    let page = document
+        .load_page(index as i32)
+        .map_err(|error| mupdf_error("could not load the page", error))?;
+    let bounds = page
+        .bounds()
+        .map_err(|error| mupdf_error("could not measure the page", error))?;
+    let scale = width as f32 / (bounds.x1 - bounds.x0);
+    let height = ((bounds.y1 - bounds.y0) * scale).ceil().max(1.0);
+    // With alpha, so the samples are four to a pixel the way `darken` reads
+    // them; cleared opaque, so the page is still on paper.
+    let mut pixmap =
+        Pixmap::new_with_w_h(&Colorspace::device_rgb(), width as i32, height as i32, true)
+            .map_err(|error| mupdf_error("could not make room for the thumbnail", error))?;
+    pixmap
+        .clear_with(255)
+        .map_err(|error| mupdf_error("could not clear the thumbnail", error))?;
+    let matrix = Matrix::new(
+        scale,
+        0.0,
+        0.0,
+        scale,
+        -bounds.x0 * scale,
+        -bounds.y0 * scale,
+    );
+    {
+        let device = Device::from_pixmap(&pixmap)
+            .map_err(|error| mupdf_error("could not draw the thumbnail", error))?;
+        page.run(&device, &matrix)
+            .map_err(|error| mupdf_error("could not draw the page", error))?;
+    }
+    if invert {
+        darken(pixmap.samples_mut());
+    }
+    let mut png = Vec::new();
+    pixmap
+        .write_to(&mut png, mupdf::ImageFormat::PNG)
+        .map_err(|error| mupdf_error("could not encode the thumbnail", error))?;
+    Ok(png)
+}
+
+/// The longest side an exported image may have, in pixels. A figure blown up
+/// for a poster is well inside it; past it is a slip of the resolution field,
+/// and MuPDF would try to allocate it all the same.
+const MOST_EXPORT_PIXELS: f32 = 16_384.0;
+
+/// A page, or the part of it `region` covers, as the bytes of an image file.
+///
+/// A PNG is drawn the way [`render_page`] draws, onto opaque white, but at
+/// `dpi` and never inverted: it is going on a slide, not into a dark room. It
+/// carries its resolution, so a slide program places it at the size it had on
+/// paper. An SVG is MuPDF's own drawing of the page — text stays text and
+/// rules stay lines — and `dpi` means nothing to it.
+pub fn export_image(
+    document: &Document,
+    index: usize,
+    format: ImageFormat,
+    dpi: f32,
+    region: Option<PageRegion>,
+) -> AppResult<Vec<u8>> {
+    let page = document
+        .load_page(index as i32)
+        .map_err(|error| mupdf_error("could not load the page", error))?;
+    let bounds = page
+        .bounds()
+        .map_err(|error| mupdf_error("could not measure the page", error))?;
+    let whole = PageRegion {
+        x: 0.0,
+        y: 0.0,
+        width: bounds.x1 - bounds.x0,
+        height: bounds.y1 - bounds.y0,
+    };
+    let region = match region {
+        Some(region) => within(region, whole)
+            .ok_or_else(|| AppError::InvalidInput("the region is not on the page".into()))?,
+        None => whole,
+    };
+
+    match format {
+        ImageFormat::Png => {
+            let scale = dpi / 72.0;
+            let (width, height) = (
+                (region.width * scale).ceil(),
+                (region.height * scale).ceil(),
+            );
+            if !(scale > 0.0) || width.max(height) > MOST_EXPORT_PIXELS {
+                return Err(AppError::InvalidInput(format!(
+                    "{dpi} dpi makes an image too large to export"
+                )));
+            }
+            let mut pixmap = Pixmap::new_with_w_h(
+                &Colorspace::device_rgb(),
+                width as i32,
+                height as i32,
+                false,
+            )
+            .map_err(|error| mupdf_error("could not make room for the image", error))?;
+            pixmap
+                .clear_with(255)
+                .map_err(|error| mupdf_error("could not clear the image", error))?;
+            pixmap.set_resolution(dpi.round() as i32, dpi.round() as i32);
+            // Scaled, then moved so the region's corner is the image's.
+            let matrix = Matrix::new(
+                scale,
+                0.0,
+                0.0,
+                scale,
+                -(bounds.x0 + region.x) * scale,
+                -(bounds.y0 + region.y) * scale,
+            );
+            {
+                let device = Device::from_pixmap(&pixmap)
+                    .map_err(|error| mupdf_error("could not draw the image", error))?;
+                page.run(&device, &matrix)
+                    .map_err(|error| mupdf_error("could not draw the page", error))?;
+            }
+            let mut png = Vec::new();
+            pixmap
+                .write_to(&mut png, mupdf::ImageFormat::PNG)
+                .map_err(|error| mupdf_error("could not encode the image", error))?;
+            Ok(png)
+        }
+        ImageFormat::Svg => {
+            let svg = page
+                .to_svg(&Matrix::IDENTITY)
+                .map_err(|error| mupdf_error("could not draw the page as SVG", error))?;
+            Ok(clip_svg(&svg, region).into_bytes())
+        }
+    }
+}
+
+/// The part of `region` that is on the page, if any of it is.
+fn within(region: PageRegion, page: PageRegion) -> Option<PageRegion> {
+    let left = region.x.max(0.0);
+    let top = region.y.max(0.0);
+    let right = (region.x + region.width).min(page.width);
+    let bottom = (region.y + region.height).min(page.height);
+    (right > left && bottom > top).then_some(PageRegion {
+        x: left,
+        y: top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// Narrows an SVG of a whole page to `region` by moving its window rather than
+/// its drawing: everything outside is still in the file, and simply not shown.
+/// That keeps the SVG device's output untouched, which is the part that is
+/// hard to get right.
+fn clip_svg(svg: &str, region: PageRegion) -> String {
+    let Some(start) = svg.find("<svg") else {
+        return svg.to_owned();
+    };
+    let Some(length) = svg[start..].find('>') else {
+        return svg.to_owned();
+    };
+    let mut tag = svg[start..start + length].to_owned();
+    for (name, value) in [
+        ("width", format!("{}pt", region.width)),
+        ("height", format!("{}pt", region.height)),
+        (
+            "viewBox",
+            format!(
+                "{} {} {} {}",
+                region.x, region.y, region.width, region.height
+            ),
+        ),
+    ] {
+        tag = with_attribute(&tag, name, &value);
+    }
+    format!("{}{tag}{}", &svg[..start], &svg[start + length..])
+}
+
+fn with_attribute(tag: &str, name: &str, value: &str) -> String {
+    let key = format!(" {name}=\"");
+    let Some(at) = tag.find(&key) else {
+        return format!("{tag} {name}=\"{value}\"");
+    };
+    let from = at + key.len();
+    let to = tag[from..].find('"').map_or(tag.len(), |end| from + end);
+    format!("{}{value}{}", &tag[..from], &tag[to..])
+}
+
 /// Expands MuPDF's samples to RGBA, behind the space [`RenderedPage::PREFIX`]
 /// keeps for the header.
 fn to_rgba(samples: &[u8], components: u8) -> Vec<u8> {
@@ -357,6 +726,92 @@ pub fn words(document: &Document, index: usize) -> AppResult<Vec<Word>> {
         .collect())
 }
 
+/// The lines and images on one page, from MuPDF's structured text.
+pub fn layout(document: &Document, index: usize) -> AppResult<PageLayout> {
+    let page = document
+        .load_page(index as i32)
+        .map_err(|error| mupdf_error("could not load the page", error))?;
+    let text = page
+        .to_text_page(TextPageFlags::PRESERVE_IMAGES)
+        .map_err(|error| mupdf_error("could not extract text", error))?;
+    let mut layout = PageLayout::default();
+    for block in text.blocks() {
+        if block.r#type() == TextBlockType::Image {
+            let bounds = block.bounds();
+            layout.images.push(Region {
+                x: bounds.x0,
+                y: bounds.y0,
+                width: bounds.x1 - bounds.x0,
+                height: bounds.y1 - bounds.y0,
+            });
+            continue;
+        }
+        for line in block.lines() {
+            let mut content = String::new();
+            // Tenths of a point, so that sizes a rounding error apart are one.
+            let mut sizes = HashMap::<i32, usize>::new();
+            for character in line.chars() {
+                let Some(glyph) = character.char() else {
+                    continue;
+                };
+                content.push(glyph);
+                if !glyph.is_whitespace() {
+                    *sizes
+                        .entry((character.size() * 10.0).round() as i32)
+                        .or_default() += 1;
+                }
+            }
+            let Some((size, _)) = sizes
+                .into_iter()
+                .max_by_key(|(size, count)| (*count, *size))
+            else {
+                continue;
+            };
+            let bounds = line.bounds();
+            layout.lines.push(TextLine {
+                text: content.trim().to_owned(),
+                x: bounds.x0,
+                y: bounds.y0,
+                width: bounds.x1 - bounds.x0,
+                height: bounds.y1 - bounds.y0,
+                size: size as f32 / 10.0,
+            });
+        }
+    }
+    Ok(layout)
+}
+
+/// The document's bookmarks, in reading order. Empty when it has none.
+pub fn bookmarks(document: &Document) -> AppResult<Vec<Bookmark>> {
+    fn flatten(outlines: Vec<mupdf::Outline>, level: usize, into: &mut Vec<Bookmark>) {
+        for outline in outlines {
+            into.push(Bookmark {
+                title: outline.title,
+                level,
+                page: outline.dest.map(|dest| dest.loc.page_number as usize + 1),
+                top: outline.dest.and_then(|dest| destination_top(dest.kind)),
+            });
+            flatten(outline.down, level + 1, into);
+        }
+    }
+    let outlines = document
+        .outlines()
+        .map_err(|error| mupdf_error("could not read the outline", error))?;
+    let mut bookmarks = Vec::new();
+    flatten(outlines, 0, &mut bookmarks);
+    Ok(bookmarks)
+}
+
+/// A page's text, line by line, for finding headings on it.
+pub fn page_text(document: &Document, index: usize) -> AppResult<String> {
+    Ok(layout(document, index)?
+        .lines
+        .into_iter()
+        .map(|line| line.text)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
 /// The links on one page, in PDF points.
 pub fn links(document: &Document, index: usize) -> AppResult<Vec<Link>> {
     let page = document
@@ -383,6 +838,15 @@ pub fn links(document: &Document, index: usize) -> AppResult<Vec<Link>> {
         .collect())
 }
 
+/// The page a named destination is on, 1-based: what hyperref calls the
+/// anchor of a `\label`. `None` when the PDF has no such name.
+pub fn named_destination(document: &Document, name: &str) -> AppResult<Option<usize>> {
+    let location = document
+        .resolve_link(&format!("#nameddest={name}"))
+        .map_err(|error| mupdf_error("could not resolve the destination", error))?;
+    Ok(location.map(|location| location.page_number as usize + 1))
+}
+
 /// How far down the target page a destination asks for, when it asks at all.
 /// The kinds that fit a page to the window have no vertical position of their
 /// own, and a link into the top of a page is the sensible reading of those.
@@ -395,58 +859,67 @@ fn destination_top(kind: mupdf::DestinationKind) -> Option<f32> {
     }
 }
 
-const MAX_HITS_PER_PAGE: u32 = 500;
-
-/// MuPDF's own search. This is the same routine zathura uses.
-pub fn search_page(document: &Document, index: usize, needle: &str) -> AppResult<Vec<Hit>> {
-    if needle.is_empty() {
-        return Ok(Vec::new());
-    }
-    let page = document
-        .load_page(index as i32)
-        .map_err(|error| mupdf_error("could not load the page", error))?;
-    let quads = page
-        .search(needle, MAX_HITS_PER_PAGE)
-        .map_err(|error| mupdf_error("could not search the page", error))?;
-    Ok(quads
-        .iter()
-        .map(|quad| {
-            // A quad is four corners; for a highlight the enclosing box is enough.
-            let left = quad.ul.x.min(quad.ll.x);
-            let right = quad.ur.x.max(quad.lr.x);
-            let top = quad.ul.y.min(quad.ur.y);
-            let bottom = quad.ll.y.max(quad.lr.y);
-            Hit {
-                page: index,
-                x: left,
-                y: top,
-                width: right - left,
-                height: bottom - top,
-            }
-        })
-        .collect())
+/// Everything `query` finds on one page: see `search`.
+pub fn search_page(
+    document: &Document,
+    index: usize,
+    query: &Query,
+) -> AppResult<Vec<SearchMatch>> {
+    Ok(crate::search::find(index, &words(document, index)?, query))
 }
 
 // -- the pool -------------------------------------------------------------
 
 use std::{
     collections::VecDeque,
-    sync::{Arc, Condvar, Mutex},
+    sync::{
+        Arc, Condvar, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use tokio::sync::oneshot;
 
+use crate::{
+    cache::{PageCache, PageKey, ThumbnailCache},
+    model::{JobLatency, RenderMetrics},
+};
+
 /// Documents kept open per worker. Reopening costs about 4ms, so a handful is
 /// plenty to keep scrolling and a side-by-side comparison warm.
 const DOCUMENTS_PER_WORKER: usize = 4;
 
+/// What the documents open across every worker may weigh together: see
+/// [`OpenDocuments`]. A handful of papers come nowhere near it; two scanned
+/// books do.
+const MOST_OPEN_DOCUMENT_BYTES: usize = 512 << 20;
+
+/// How long a worker beyond the fewest waits for work before it goes. Long
+/// enough to see a reader through a pause between pages, so threads are not
+/// started and stopped on every scroll.
+const IDLE_RETIREMENT: Duration = Duration::from_secs(30);
+
 /// How many pages may be waiting to be drawn before the oldest is given up on.
+/// A tile counts as a page: it is asked for the same way and abandoned the
+/// same way.
 ///
 /// Far more than can be near the window at once, which is what makes dropping
 /// safe: for a page to be given up on, this many *newer* pages must have been
 /// asked for since, and a reader that has moved that far has moved off it.
 const MOST_PENDING_RENDERS: usize = 32;
 
+/// How many pages nobody has asked for may be waiting. A neighbour either side
+/// of what is on screen, and the opening pages of a new build, with room over.
+const MOST_SPECULATIVE: usize = 8;
+
+/// How many thumbnails may be waiting before the oldest is given up on. A strip
+/// scrolled quickly asks for far more than it ends up showing.
+const MOST_PENDING_THUMBNAILS: usize = 64;
+
+/// The pages of a fresh build drawn before the viewer asks for them.
+const PREWARM_PAGES: usize = 3;
+
 pub enum Job {
     Render {
         path: PathBuf,
@@ -457,6 +930,25 @@ pub enum Job {
         invert: bool,
         reply: oneshot::Sender<AppResult<RenderedPage>>,
     },
+    /// Part of a page: see [`render_tile`]. Queued and given up on as a page
+    /// is, since it is asked for the same way.
+    Tile {
+        path: PathBuf,
+        page: usize,
+        scale: f32,
+        tile: Tile,
+        invert: bool,
+        reply: oneshot::Sender<AppResult<RenderedPage>>,
+    },
+    /// See [`thumbnail`]. Drawn behind every page, which is what the reader
+    /// is actually looking at.
+    Thumbnail {
+        path: PathBuf,
+        page: usize,
+        width: u32,
+        invert: bool,
+        reply: oneshot::Sender<AppResult<Vec<u8>>>,
+    },
     Geometry {
         path: PathBuf,
         reply: oneshot::Sender<AppResult<Vec<PageGeometry>>>,
@@ -473,8 +965,48 @@ pub enum Job {
     },
     Search {
         path: PathBuf,
-        needle: String,
-        reply: oneshot::Sender<AppResult<Vec<Hit>>>,
+        page: usize,
+        query: Query,
+        reply: oneshot::Sender<AppResult<Vec<SearchMatch>>>,
+    },
+    Preflight {
+        path: PathBuf,
+        reply: oneshot::Sender<AppResult<PreflightReport>>,
+    },
+    Embed {
+        path: PathBuf,
+        files: Vec<(String, Vec<u8>)>,
+        provenance: crate::bundle::Provenance,
+        reply: oneshot::Sender<AppResult<Vec<u8>>>,
+    },
+    Impose {
+        path: PathBuf,
+        options: PrintOptions,
+        reply: oneshot::Sender<AppResult<Vec<u8>>>,
+    },
+    Annotate {
+        path: PathBuf,
+        annotations: Vec<Annotation>,
+        reply: oneshot::Sender<AppResult<Vec<u8>>>,
+    },
+    Outline {
+        path: PathBuf,
+        /// The `.aux` the PDF was built with, when there is one.
+        contents: Option<String>,
+        reply: oneshot::Sender<AppResult<Vec<OutlineEntry>>>,
+    },
+    Image {
+        path: PathBuf,
+        page: usize,
+        format: ImageFormat,
+        dpi: f32,
+        region: Option<PageRegion>,
+        reply: oneshot::Sender<AppResult<Vec<u8>>>,
+    },
+    Destinations {
+        path: PathBuf,
+        names: Vec<String>,
+        reply: oneshot::Sender<AppResult<Vec<Option<usize>>>>,
     },
 }
 
@@ -488,15 +1020,56 @@ impl Job {
     fn abandoned(&self) -> bool {
         match self {
             Self::Render { reply, .. } => reply.is_closed(),
+            Self::Tile { reply, .. } => reply.is_closed(),
+            Self::Thumbnail { reply, .. } => reply.is_closed(),
             Self::Geometry { reply, .. } => reply.is_closed(),
             Self::Words { reply, .. } => reply.is_closed(),
             Self::Links { reply, .. } => reply.is_closed(),
             Self::Search { reply, .. } => reply.is_closed(),
+            Self::Preflight { reply, .. } => reply.is_closed(),
+            Self::Embed { reply, .. } => reply.is_closed(),
+            Self::Impose { reply, .. } => reply.is_closed(),
+            Self::Annotate { reply, .. } => reply.is_closed(),
+            Self::Outline { reply, .. } => reply.is_closed(),
+            Self::Destinations { reply, .. } => reply.is_closed(),
+            Self::Image { reply, .. } => reply.is_closed(),
         }
     }
 
     fn is_render(&self) -> bool {
-        matches!(self, Self::Render { .. })
+        matches!(self, Self::Render { .. } | Self::Tile { .. })
+    }
+
+    /// A whole document read or written at once, which takes seconds where a
+    /// page takes milliseconds.
+    fn is_whole_document(&self) -> bool {
+        matches!(
+            self,
+            Self::Preflight { .. }
+                | Self::Embed { .. }
+                | Self::Impose { .. }
+                | Self::Annotate { .. }
+        )
+    }
+
+    /// What the job is called in the latency figures.
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Render { .. } => "render",
+            Self::Tile { .. } => "tile",
+            Self::Thumbnail { .. } => "thumbnail",
+            Self::Geometry { .. } => "geometry",
+            Self::Words { .. } => "words",
+            Self::Links { .. } => "links",
+            Self::Search { .. } => "search",
+            Self::Preflight { .. } => "preflight",
+            Self::Embed { .. } => "embed",
+            Self::Impose { .. } => "impose",
+            Self::Annotate { .. } => "annotate",
+            Self::Outline { .. } => "outline",
+            Self::Destinations { .. } => "destinations",
+            Self::Image { .. } => "image",
+        }
     }
 }
 
@@ -512,16 +1085,57 @@ impl Job {
 /// still drawn, harmlessly and last, unless more than [`MOST_PENDING_RENDERS`]
 /// are waiting — at which point the oldest is given up on, because by then it is
 /// certainly not being looked at.
+///
+/// A job over a whole document waits in a lane of its own, oldest first, and
+/// never takes the last thread: a preflight and an export started together
+/// would otherwise hold every page behind them for as long as they both take.
+///
+/// The queue also keeps count of the threads taking from it. There are never
+/// fewer than `fewest`; past that, one more is asked for whenever work is
+/// waiting that no idle thread is there to take, up to `most`, and one that has
+/// waited [`IDLE_RETIREMENT`] for work goes again.
 #[derive(Default)]
 struct Queue {
     pending: Mutex<Pending>,
     ready: Condvar,
+    fewest: usize,
+    most: usize,
 }
 
 #[derive(Default)]
 struct Pending {
     jobs: VecDeque<Job>,
+    /// Thumbnails, newest first like pages, but only taken when nothing in
+    /// `jobs` is waiting.
+    background: VecDeque<Job>,
+    /// Pages drawn in case they are wanted, oldest first. Only taken when
+    /// nothing else is waiting.
+    speculative: VecDeque<Job>,
+    /// Jobs over a whole document, oldest first: see `Job::is_whole_document`.
+    whole: VecDeque<Job>,
+    /// Of those, the ones being worked on.
+    whole_running: usize,
     closed: bool,
+    /// Threads taking from the queue, started or about to be.
+    workers: usize,
+    /// Of those, the ones waiting for work.
+    idle: usize,
+}
+
+impl Pending {
+    fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+            && self.background.is_empty()
+            && self.speculative.is_empty()
+            && self.whole.is_empty()
+    }
+
+    /// Whether another job over a whole document may start and still leave a
+    /// thread for pages. A pool of one thread has none to leave.
+    fn may_start_whole(&self, most: usize) -> bool {
+        let room = self.workers.saturating_sub(1).max(usize::from(most <= 1));
+        self.whole_running < room
+    }
 }
 
 impl Queue {
@@ -532,10 +1146,30 @@ impl Queue {
         if pending.closed {
             return false;
         }
+        if matches!(job, Job::Thumbnail { .. }) {
+            pending.background.push_back(job);
+            while pending.background.len() > MOST_PENDING_THUMBNAILS {
+                pending.background.pop_front();
+            }
+            drop(pending);
+            self.ready.notify_one();
+            return true;
+        }
+        if job.is_whole_document() {
+            pending.whole.push_back(job);
+            drop(pending);
+            self.ready.notify_one();
+            return true;
+        }
+        // A page asked for is where the reader actually is; whatever was
+        // guessed from where they were is guessed again after it.
+        if job.is_render() {
+            pending.speculative.clear();
+        }
         pending.jobs.push_back(job);
-        // Only pages are given up on. Geometry, links, words and search are each
-        // asked for once and waited on, so dropping one would fail something
-        // nobody has walked away from.
+        // Only pages are given up on. Everything else is asked for once and
+        // waited on, so dropping one would fail something nobody has walked
+        // away from.
         while pending.jobs.iter().filter(|job| job.is_render()).count() > MOST_PENDING_RENDERS {
             let Some(oldest) = pending.jobs.iter().position(Job::is_render) else {
                 break;
@@ -547,16 +1181,89 @@ impl Queue {
         true
     }
 
+    /// Queues a page nobody is waiting on, behind everything somebody is.
+    fn speculate(&self, job: Job) -> bool {
+        let Ok(mut pending) = self.pending.lock() else {
+            return false;
+        };
+        if pending.closed {
+            return false;
+        }
+        pending.speculative.push_back(job);
+        while pending.speculative.len() > MOST_SPECULATIVE {
+            pending.speculative.pop_front();
+        }
+        drop(pending);
+        self.ready.notify_one();
+        true
+    }
+
+    /// The next job, waiting for one if there is none. `None` when the queue
+    /// has closed, or when this thread has been idle long enough to go and
+    /// there are more than the fewest without it.
     fn take(&self) -> Option<Job> {
         let mut pending = self.pending.lock().ok()?;
         loop {
             if let Some(job) = pending.jobs.pop_back() {
                 return Some(job);
             }
+            if pending.may_start_whole(self.most)
+                && let Some(job) = pending.whole.pop_front()
+            {
+                pending.whole_running += 1;
+                return Some(job);
+            }
+            if let Some(job) = pending.background.pop_back() {
+                return Some(job);
+            }
+            if let Some(job) = pending.speculative.pop_front() {
+                return Some(job);
+            }
             if pending.closed {
                 return None;
             }
-            pending = self.ready.wait(pending).ok()?;
+            pending.idle += 1;
+            let (woken, waited) = self.ready.wait_timeout(pending, IDLE_RETIREMENT).ok()?;
+            pending = woken;
+            pending.idle -= 1;
+            if waited.timed_out() && pending.is_empty() && pending.workers > self.fewest {
+                pending.workers -= 1;
+                return None;
+            }
+        }
+    }
+
+    /// Counts one more thread in, if the work waiting calls for it: more jobs
+    /// somebody is waiting on than threads to take them, none of them idle,
+    /// and room for another. The caller starts the thread.
+    fn enlist(&self) -> bool {
+        let Ok(mut pending) = self.pending.lock() else {
+            return false;
+        };
+        let waiting = pending.jobs.len() + pending.background.len() + pending.whole.len();
+        let wanted = !pending.closed
+            && pending.idle == 0
+            && pending.workers < self.most
+            && waiting > pending.workers;
+        if wanted {
+            pending.workers += 1;
+        }
+        wanted
+    }
+
+    /// Counts a job over a whole document as done, which may let the next one
+    /// start.
+    fn finish_whole(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.whole_running = pending.whole_running.saturating_sub(1);
+        }
+        self.ready.notify_one();
+    }
+
+    /// Counts a thread out that was counted in and could not be started.
+    fn discharge(&self) {
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.workers = pending.workers.saturating_sub(1);
         }
     }
 
@@ -564,6 +1271,9 @@ impl Queue {
         if let Ok(mut pending) = self.pending.lock() {
             pending.closed = true;
             pending.jobs.clear();
+            pending.background.clear();
+            pending.speculative.clear();
+            pending.whole.clear();
         }
         self.ready.notify_all();
     }
@@ -572,28 +1282,112 @@ impl Queue {
 /// A few threads, each owning its own MuPDF context and open documents.
 pub struct RenderPool {
     queue: Arc<Queue>,
+    /// What the workers' open documents weigh between them.
+    documents: Arc<OpenDocuments>,
+    latencies: Arc<Latencies>,
+    /// For naming threads: each started is numbered after the last.
+    started: AtomicUsize,
+    /// Pages already drawn, in front of the threads: see `cache`.
+    cache: Arc<PageCache>,
+    /// Thumbnails already drawn: see `cache`.
+    thumbnails: ThumbnailCache,
+    /// The scale and ink the viewer last asked for, which is what a page it
+    /// has not asked for yet will most likely be wanted at.
+    last_view: Mutex<Option<(f32, bool)>>,
 }
 
 impl RenderPool {
+    /// Exactly `workers` threads, however much is waiting.
     pub fn new(workers: usize) -> Self {
-        let queue = Arc::new(Queue::default());
-        for index in 0..workers.max(1) {
-            let queue = Arc::clone(&queue);
-            std::thread::Builder::new()
-                .name(format!("press-render-{index}"))
-                .spawn(move || worker(&queue))
+        Self::between(workers, workers)
+    }
+
+    /// `fewest` threads to begin with, and more as work waits for them, up
+    /// to `most`.
+    fn between(fewest: usize, most: usize) -> Self {
+        let fewest = fewest.max(1);
+        let pool = Self {
+            queue: Arc::new(Queue {
+                fewest,
+                most: most.max(fewest),
+                ..Queue::default()
+            }),
+            documents: Arc::new(OpenDocuments::new(MOST_OPEN_DOCUMENT_BYTES)),
+            latencies: Arc::new(Latencies::default()),
+            started: AtomicUsize::new(0),
+            cache: Arc::new(PageCache::in_memory(crate::cache::DEFAULT_MEGABYTES)),
+            thumbnails: ThumbnailCache::default(),
+            last_view: Mutex::new(None),
+        };
+        for _ in 0..fewest {
+            if let Ok(mut pending) = pool.queue.pending.lock() {
+                pending.workers += 1;
+            }
+            pool.start_worker()
                 .expect("could not start a render thread");
         }
-        Self { queue }
+        pool
     }
 
-    /// Sized to keep a couple of pages in flight without starving the rest of
-    /// the machine during a build.
+    /// Starts a thread the queue has already counted in.
+    fn start_worker(&self) -> std::io::Result<()> {
+        let queue = Arc::clone(&self.queue);
+        let documents = Arc::clone(&self.documents);
+        let latencies = Arc::clone(&self.latencies);
+        let index = self.started.fetch_add(1, Ordering::Relaxed);
+        std::thread::Builder::new()
+            .name(format!("press-render-{index}"))
+            .spawn(move || worker(&queue, documents, &latencies))
+            .map(|_| ())
+    }
+
+    /// Another thread, when what is waiting calls for one. A thread that
+    /// cannot be started leaves the work to the ones there are.
+    fn grow(&self) {
+        if self.queue.enlist() && self.start_worker().is_err() {
+            self.queue.discharge();
+        }
+    }
+
+    /// How the pool is doing: its threads, what they hold open, and how long
+    /// each kind of job has been taking.
+    pub fn metrics(&self) -> RenderMetrics {
+        RenderMetrics {
+            workers: self
+                .queue
+                .pending
+                .lock()
+                .map_or(0, |pending| pending.workers),
+            open_document_bytes: self.documents.weight() as u64,
+            jobs: self.latencies.summary(),
+        }
+    }
+
+    /// The same pool with `cache` in front of it in place of the memory-only
+    /// one it starts with.
+    pub fn with_cache(mut self, cache: PageCache) -> Self {
+        self.cache = Arc::new(cache);
+        self
+    }
+
+    /// The same pool keeping the thumbnails it draws in `thumbnails`.
+    pub fn with_thumbnails(mut self, thumbnails: ThumbnailCache) -> Self {
+        self.thumbnails = thumbnails;
+        self
+    }
+
+    pub fn cache(&self) -> &PageCache {
+        &self.cache
+    }
+
+    /// Two threads to keep a couple of pages in flight, and as many as the
+    /// machine has to spare when a fast scroll or a strip of thumbnails queues
+    /// up more — but never so many that a build is starved.
     pub fn with_default_size() -> Self {
         let parallelism = std::thread::available_parallelism()
             .map(|value| value.get())
             .unwrap_or(4);
-        Self::new(parallelism.saturating_sub(1).clamp(2, 4))
+        Self::between(2, parallelism.saturating_sub(1).clamp(2, 6))
     }
 
     async fn submit<T>(
@@ -604,17 +1398,61 @@ impl RenderPool {
         if !self.queue.push(make(reply)) {
             return Err(AppError::Task("the render pool has stopped".into()));
         }
+        self.grow();
         receive
             .await
             .map_err(|_| AppError::Task("the render pool dropped a request".into()))?
     }
 
+    /// A page drawn, or the same page as it was drawn last, if the file has
+    /// not changed since. Shared with the cache, which keeps it either way.
     pub async fn render(
         &self,
         path: PathBuf,
         page: usize,
         scale: f32,
         invert: bool,
+    ) -> AppResult<Arc<RenderedPage>> {
+        // Measured before the page is drawn, so a file rewritten in between
+        // leaves the page kept against the older stamp: a miss next time, not
+        // a wrong page.
+        let stamp = tokio::fs::metadata(&path)
+            .await
+            .ok()
+            .map(|metadata| Stamp::from(&metadata));
+        if let Ok(mut last_view) = self.last_view.lock() {
+            *last_view = Some((scale, invert));
+        }
+        let key = PageKey::new(&path, page, scale, invert);
+        let rendered = match stamp {
+            Some(stamp) => match self.cache.get(&key, stamp).await {
+                Some(kept) => kept,
+                None => {
+                    let rendered = Arc::new(self.draw(path.clone(), page, scale, invert).await?);
+                    self.cache.put(key, stamp, &rendered);
+                    rendered
+                }
+            },
+            None => Arc::new(self.draw(path.clone(), page, scale, invert).await?),
+        };
+        // The reader reads on, or back. Either neighbour is drawn while the
+        // threads have nothing better to do, so turning the page is a cache
+        // hit; a page past either end fails to load, which costs nothing.
+        if let Some(stamp) = stamp {
+            self.speculate(&path, page + 1, scale, invert, stamp);
+            if let Some(previous) = page.checked_sub(1) {
+                self.speculate(&path, previous, scale, invert, stamp);
+            }
+        }
+        Ok(rendered)
+    }
+
+    async fn draw(
+        &self,
+        path: PathBuf,
+        page: usize,
+        scale: f32,
+        invert: bool,
     ) -> AppResult<RenderedPage> {
         self.submit(|reply| Job::Render {
             path,
@@ -626,6 +1464,102 @@ impl RenderPool {
         .await
     }
 
+    /// Draws the opening pages of a freshly published PDF into the cache, at
+    /// the scale the viewer last asked for, so the first pages it shows of the
+    /// new build are already there. Nothing, before the viewer has asked for
+    /// any page at all.
+    pub fn prewarm(&self, path: &Path) {
+        let Some((scale, invert)) = self.last_view.lock().ok().and_then(|last| *last) else {
+            return;
+        };
+        let Some(stamp) = Stamp::of(path) else {
+            return;
+        };
+        for page in 0..PREWARM_PAGES {
+            self.speculate(path, page, scale, invert, stamp);
+        }
+    }
+
+    /// Queues a page nobody has asked for, to be drawn into the cache when the
+    /// threads are otherwise idle, unless it is already there. Given up on as
+    /// soon as a real request arrives: see `Queue::push`.
+    fn speculate(&self, path: &Path, page: usize, scale: f32, invert: bool, stamp: Stamp) {
+        let key = PageKey::new(path, page, scale, invert);
+        if self.cache.holds(&key, stamp) {
+            return;
+        }
+        let (reply, receive) = oneshot::channel();
+        let job = Job::Render {
+            path: path.to_path_buf(),
+            page,
+            scale,
+            invert,
+            reply,
+        };
+        if !self.queue.speculate(job) {
+            return;
+        }
+        let cache = Arc::clone(&self.cache);
+        tauri::async_runtime::spawn(async move {
+            if let Ok(Ok(rendered)) = receive.await {
+                cache.put(key, stamp, &Arc::new(rendered));
+            }
+        });
+    }
+
+    pub async fn tile(
+        &self,
+        path: PathBuf,
+        page: usize,
+        scale: f32,
+        tile: Tile,
+        invert: bool,
+    ) -> AppResult<RenderedPage> {
+        self.submit(|reply| Job::Tile {
+            path,
+            page,
+            scale,
+            tile,
+            invert,
+            reply,
+        })
+        .await
+    }
+
+    /// A page's thumbnail as a PNG, from the thumbnail cache when the file has
+    /// not changed since it was drawn.
+    pub async fn thumbnail(
+        &self,
+        path: PathBuf,
+        page: usize,
+        width: u32,
+        invert: bool,
+    ) -> AppResult<Vec<u8>> {
+        let stamp = tokio::fs::metadata(&path)
+            .await
+            .ok()
+            .map(|metadata| Stamp::from(&metadata));
+        let key = PageKey::thumbnail(&path, page, width, invert);
+        if let Some(stamp) = stamp
+            && let Some(kept) = self.thumbnails.get(&key, stamp).await
+        {
+            return Ok(kept);
+        }
+        let png = self
+            .submit(|reply| Job::Thumbnail {
+                path,
+                page,
+                width,
+                invert,
+                reply,
+            })
+            .await?;
+        if let Some(stamp) = stamp {
+            self.thumbnails.put(key, stamp, png.clone());
+        }
+        Ok(png)
+    }
+
     pub async fn geometry(&self, path: PathBuf) -> AppResult<Vec<PageGeometry>> {
         self.submit(|reply| Job::Geometry { path, reply }).await
     }
@@ -638,10 +1572,108 @@ impl RenderPool {
         self.submit(|reply| Job::Links { path, page, reply }).await
     }
 
-    pub async fn search(&self, path: PathBuf, needle: String) -> AppResult<Vec<Hit>> {
+    /// One page at a time, so that a search over a long document can show
+    /// what it has found so far, and never holds a worker for long.
+    pub async fn search(
+        &self,
+        path: PathBuf,
+        page: usize,
+        query: Query,
+    ) -> AppResult<Vec<SearchMatch>> {
         self.submit(|reply| Job::Search {
             path,
-            needle,
+            page,
+            query,
+            reply,
+        })
+        .await
+    }
+
+    /// What a submission checker would find in the PDF: see `preflight`.
+    pub async fn preflight(&self, path: PathBuf) -> AppResult<PreflightReport> {
+        self.submit(|reply| Job::Preflight { path, reply }).await
+    }
+
+    /// The PDF with a version's source attached: see `bundle`.
+    pub async fn embed(
+        &self,
+        path: PathBuf,
+        files: Vec<(String, Vec<u8>)>,
+        provenance: crate::bundle::Provenance,
+    ) -> AppResult<Vec<u8>> {
+        self.submit(|reply| Job::Embed {
+            path,
+            files,
+            provenance,
+            reply,
+        })
+        .await
+    }
+
+    /// The PDF with a reader's annotations written into it: see `annotations`.
+    pub async fn annotate(
+        &self,
+        path: PathBuf,
+        annotations: Vec<Annotation>,
+    ) -> AppResult<Vec<u8>> {
+        self.submit(|reply| Job::Annotate {
+            path,
+            annotations,
+            reply,
+        })
+        .await
+    }
+
+    /// A new PDF of the document set up for printing: see `impose`.
+    pub async fn impose(&self, path: PathBuf, options: PrintOptions) -> AppResult<Vec<u8>> {
+        self.submit(|reply| Job::Impose {
+            path,
+            options,
+            reply,
+        })
+        .await
+    }
+
+    /// The bookmarks, or headings read from `contents` when there are none:
+    /// see `outline`.
+    pub async fn outline(
+        &self,
+        path: PathBuf,
+        contents: Option<String>,
+    ) -> AppResult<Vec<OutlineEntry>> {
+        self.submit(|reply| Job::Outline {
+            path,
+            contents,
+            reply,
+        })
+        .await
+    }
+
+    /// The page each of `names` is on, in order: see `named_destination`.
+    pub async fn destinations(
+        &self,
+        path: PathBuf,
+        names: Vec<String>,
+    ) -> AppResult<Vec<Option<usize>>> {
+        self.submit(|reply| Job::Destinations { path, names, reply })
+            .await
+    }
+
+    /// A page or part of one as an image file: see `export_image`.
+    pub async fn image(
+        &self,
+        path: PathBuf,
+        page: usize,
+        format: ImageFormat,
+        dpi: f32,
+        region: Option<PageRegion>,
+    ) -> AppResult<Vec<u8>> {
+        self.submit(|reply| Job::Image {
+            path,
+            page,
+            format,
+            dpi,
+            region,
             reply,
         })
         .await
@@ -655,37 +1687,161 @@ impl Drop for RenderPool {
     fn drop(&mut self) {
         self.queue.close();
     }
-}
+}
+
+/// What makes a cached document still the document on disk.
+///
+/// A path alone cannot say. Press's own builds publish under a fresh
+/// `build-<stamp>.pdf` every time, so for those the path is enough — but a PDF
+/// Press is only showing is rebuilt under its own name by whatever owns it, and
+/// that is the whole point of watching one. MuPDF reads objects from the stream
+/// as they are asked for, so a handle held across a rewrite resolves the new
+/// bytes through the old cross-reference table: not a stale page, a wrong one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stamp {
+    modified: Option<std::time::SystemTime>,
+    len: u64,
+}
+
+impl Stamp {
+    /// A file that cannot be measured gets a stamp that equals nothing, itself
+    /// included, so it is reopened rather than trusted.
+    pub fn of(path: &Path) -> Option<Self> {
+        std::fs::metadata(path)
+            .ok()
+            .map(|metadata| Self::from(&metadata))
+    }
+
+    /// The stamp as text, for a name that has to change when the file does.
+    /// `None` when the file system keeps no modification time, since a rewrite
+    /// of the same length would then go unnoticed.
+    pub fn token(&self) -> Option<String> {
+        let since = self.modified?.duration_since(std::time::UNIX_EPOCH).ok()?;
+        Some(format!("{}-{}", since.as_nanos(), self.len))
+    }
+}
+
+impl From<&std::fs::Metadata> for Stamp {
+    fn from(metadata: &std::fs::Metadata) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+        }
+    }
+}
+
+/// What the documents open across every worker weigh together, against a
+/// budget.
+///
+/// The weight is approximate: a document is counted at the size of its file.
+/// MuPDF reads a PDF's streams as it needs them rather than all at once, but
+/// what it decodes from them stays in its store, and a scanned book is nothing
+/// but images — so the file's size is a fair measure of what keeping one open
+/// comes to, and a paper of a few hundred kilobytes hardly counts at all.
+///
+/// No worker can close another's documents, since each belongs to its own
+/// thread's context. So each keeps the total in view and, when it is over,
+/// gives up its own least recently used.
+struct OpenDocuments {
+    weight: AtomicUsize,
+    budget: usize,
+}
+
+impl OpenDocuments {
+    fn new(budget: usize) -> Self {
+        Self {
+            weight: AtomicUsize::new(0),
+            budget,
+        }
+    }
+
+    fn weight(&self) -> usize {
+        self.weight.load(Ordering::Relaxed)
+    }
+
+    fn over(&self) -> bool {
+        self.weight() > self.budget
+    }
+
+    fn add(&self, weight: usize) {
+        self.weight.fetch_add(weight, Ordering::Relaxed);
+    }
+
+    fn remove(&self, weight: usize) {
+        let _ = self
+            .weight
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |total| {
+                Some(total.saturating_sub(weight))
+            });
+    }
+}
+
+/// How long each kind of job has taken, from a worker taking it to its answer
+/// being sent.
+#[derive(Default)]
+struct Latencies {
+    kinds: Mutex<Vec<(&'static str, Latency)>>,
+}
+
+#[derive(Default, Clone, Copy)]
+struct Latency {
+    count: u64,
+    total: Duration,
+    longest: Duration,
+    last: Duration,
+}
+
+impl Latencies {
+    fn record(&self, kind: &'static str, took: Duration) {
+        let Ok(mut kinds) = self.kinds.lock() else {
+            return;
+        };
+        let position = match kinds.iter().position(|(known, _)| *known == kind) {
+            Some(position) => position,
+            None => {
+                kinds.push((kind, Latency::default()));
+                kinds.len() - 1
+            }
+        };
+        let latency = &mut kinds[position].1;
+        latency.count += 1;
+        latency.total += took;
+        latency.longest = latency.longest.max(took);
+        latency.last = took;
+    }
 
-/// What makes a cached document still the document on disk.
-///
-/// A path alone cannot say. Press's own builds publish under a fresh
-/// `build-<stamp>.pdf` every time, so for those the path is enough — but a PDF
-/// Press is only showing is rebuilt under its own name by whatever owns it, and
-/// that is the whole point of watching one. MuPDF reads objects from the stream
-/// as they are asked for, so a handle held across a rewrite resolves the new
-/// bytes through the old cross-reference table: not a stale page, a wrong one.
-#[derive(Clone, Copy, PartialEq, Eq)]
-struct Stamp {
-    modified: Option<std::time::SystemTime>,
-    len: u64,
+    fn summary(&self) -> Vec<JobLatency> {
+        let milliseconds = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let Ok(kinds) = self.kinds.lock() else {
+            return Vec::new();
+        };
+        let mut summary = kinds
+            .iter()
+            .map(|(kind, latency)| JobLatency {
+                kind: (*kind).to_owned(),
+                count: latency.count,
+                mean_ms: milliseconds(latency.total) / latency.count.max(1) as f64,
+                longest_ms: milliseconds(latency.longest),
+                last_ms: milliseconds(latency.last),
+            })
+            .collect::<Vec<_>>();
+        summary.sort_by(|left, right| left.kind.cmp(&right.kind));
+        summary
+    }
 }
 
-impl Stamp {
-    /// A file that cannot be measured gets a stamp that equals nothing, itself
-    /// included, so it is reopened rather than trusted.
-    fn of(path: &Path) -> Option<Self> {
-        let metadata = std::fs::metadata(path).ok()?;
-        Some(Self {
-            modified: metadata.modified().ok(),
-            len: metadata.len(),
-        })
-    }
+/// Asks MuPDF to let go of half of what it has decoded on this thread. The
+/// store is a cache of its own, one per context, and a thread that has drawn a
+/// few pages of a scanned book holds on to every image it decoded for them.
+fn shrink_store() {
+    let _ = mupdf::Context::get().shrink_store(50);
 }
 
 struct Entry {
     path: PathBuf,
     stamp: Option<Stamp>,
+    /// What this document counts for in [`OpenDocuments`].
+    weight: usize,
     document: Document,
 }
 
@@ -693,12 +1849,43 @@ struct Entry {
 /// made it, so this is deliberately not shared.
 struct DocumentCache {
     entries: VecDeque<Entry>,
+    shared: Arc<OpenDocuments>,
+}
+
+impl Drop for DocumentCache {
+    /// A worker that retires takes its documents with it.
+    fn drop(&mut self) {
+        for entry in &self.entries {
+            self.shared.remove(entry.weight);
+        }
+    }
 }
 
 impl DocumentCache {
-    fn new() -> Self {
+    fn new(shared: Arc<OpenDocuments>) -> Self {
         Self {
             entries: VecDeque::new(),
+            shared,
+        }
+    }
+
+    fn evict_oldest(&mut self) {
+        if let Some(oldest) = self.entries.pop_front() {
+            self.shared.remove(oldest.weight);
+        }
+    }
+
+    /// Gives up documents, oldest first, until this thread holds no more than
+    /// its share and the workers together are within budget — short of the
+    /// one in use. If that is still too much, the rest is MuPDF's store.
+    fn trim(&mut self) {
+        while self.entries.len() > DOCUMENTS_PER_WORKER
+            || (self.entries.len() > 1 && self.shared.over())
+        {
+            self.evict_oldest();
+        }
+        if self.shared.over() {
+            shrink_store();
         }
     }
 
@@ -714,32 +1901,41 @@ impl DocumentCache {
             let entry = self.entries.remove(position).expect("position is in range");
             if fresh {
                 self.entries.push_back(entry);
+                // Another thread may have opened something large since.
+                self.trim();
                 return Ok(&self.entries.back().expect("just moved").document);
             }
             // Rewritten under the same name: what is open describes the file
             // that used to be there. Dropped, and opened again below.
+            self.shared.remove(entry.weight);
         }
 
         let document = open(path)?;
+        let weight = stamp.map_or(0, |stamp| stamp.len as usize);
+        self.shared.add(weight);
         self.entries.push_back(Entry {
             path: path.to_path_buf(),
             stamp,
+            weight,
             document,
         });
-        while self.entries.len() > DOCUMENTS_PER_WORKER {
-            self.entries.pop_front();
-        }
+        self.trim();
         Ok(&self.entries.back().expect("just inserted").document)
     }
 }
 
-fn worker(queue: &Queue) {
-    let mut cache = DocumentCache::new();
+fn worker(queue: &Queue, documents: Arc<OpenDocuments>, latencies: &Latencies) {
+    let mut cache = DocumentCache::new(documents);
     // The queue's lock is held only to take a job, never across the work itself.
     while let Some(job) = queue.take() {
+        let whole = job.is_whole_document();
         if job.abandoned() {
+            if whole {
+                queue.finish_whole();
+            }
             continue;
         }
+        let (kind, started) = (job.kind(), Instant::now());
 
         match job {
             Job::Render {
@@ -754,6 +1950,31 @@ fn worker(queue: &Queue) {
                     .and_then(|document| render_page(document, page, scale, invert));
                 let _ = reply.send(result);
             }
+            Job::Tile {
+                path,
+                page,
+                scale,
+                tile,
+                invert,
+                reply,
+            } => {
+                let result = cache
+                    .get(&path)
+                    .and_then(|document| render_tile(document, page, scale, tile, invert));
+                let _ = reply.send(result);
+            }
+            Job::Thumbnail {
+                path,
+                page,
+                width,
+                invert,
+                reply,
+            } => {
+                let result = cache
+                    .get(&path)
+                    .and_then(|document| thumbnail(document, page, width, invert));
+                let _ = reply.send(result);
+            }
             Job::Geometry { path, reply } => {
                 let result = cache.get(&path).and_then(geometry);
                 let _ = reply.send(result);
@@ -768,26 +1989,108 @@ fn worker(queue: &Queue) {
             }
             Job::Search {
                 path,
-                needle,
+                page,
+                query,
+                reply,
+            } => {
+                let result = cache
+                    .get(&path)
+                    .and_then(|document| search_page(document, page, &query));
+                let _ = reply.send(result);
+            }
+            Job::Preflight { path, reply } => {
+                // Opened apart from the cache: the objects need MuPDF's PDF
+                // layer, and a preflight is asked for too rarely to keep one.
+                let result =
+                    open_pdf(&path).and_then(|document| crate::preflight::inspect(&document));
+                let _ = reply.send(result);
+            }
+            Job::Embed {
+                path,
+                files,
+                provenance,
+                reply,
+            } => {
+                // A fresh copy, changed in memory and written out whole: the
+                // cached document is only ever read, and so is the file.
+                let result = open_pdf(&path).and_then(|mut document| {
+                    crate::bundle::embed(&mut document, &files, &provenance)
+                });
+                let _ = reply.send(result);
+            }
+            Job::Annotate {
+                path,
+                annotations,
+                reply,
+            } => {
+                // A fresh copy, for the same reason as an embed.
+                let result = open_pdf(&path).and_then(|mut document| {
+                    crate::annotations::write(&mut document, &annotations)
+                });
+                let _ = reply.send(result);
+            }
+            Job::Impose {
+                path,
+                options,
+                reply,
+            } => {
+                // Apart from the cache for the same reason as a preflight:
+                // grafting pages needs MuPDF's PDF layer.
+                let result =
+                    open_pdf(&path).and_then(|document| crate::impose::write(&document, &options));
+                let _ = reply.send(result);
+            }
+            Job::Outline {
+                path,
+                contents,
+                reply,
+            } => {
+                let result = cache.get(&path).and_then(|document| {
+                    let bookmarks = outline::from_bookmarks(bookmarks(document)?);
+                    let Some(contents) = contents.filter(|_| bookmarks.is_empty()) else {
+                        return Ok(bookmarks);
+                    };
+                    let pages = (0..page_count(document)?)
+                        .map(|index| page_text(document, index))
+                        .collect::<AppResult<Vec<_>>>()?;
+                    Ok(outline::from_contents(&contents, &pages))
+                });
+                let _ = reply.send(result);
+            }
+            Job::Image {
+                path,
+                page,
+                format,
+                dpi,
+                region,
                 reply,
             } => {
+                let result = cache
+                    .get(&path)
+                    .and_then(|document| export_image(document, page, format, dpi, region));
+                let _ = reply.send(result);
+            }
+            Job::Destinations { path, names, reply } => {
                 let result = cache.get(&path).and_then(|document| {
-                    let count = page_count(document)?;
-                    let mut hits = Vec::new();
-                    for index in 0..count {
-                        hits.extend(search_page(document, index, &needle)?);
-                    }
-                    Ok(hits)
+                    names
+                        .iter()
+                        .map(|name| named_destination(document, name))
+                        .collect()
                 });
                 let _ = reply.send(result);
             }
         }
+        latencies.record(kind, started.elapsed());
+        if whole {
+            queue.finish_whole();
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::model::SearchOptions;
     use std::time::Instant;
 
     fn inverted(red: u8, green: u8, blue: u8) -> [u8; 3] {
@@ -971,6 +2274,175 @@ mod tests {
         pdf.is_file().then_some((directory, pdf))
     }
 
+    /// hyperref's bookmarks are the outline when there are any. Without them,
+    /// the `.aux` the build wrote stands in, placed on the page the heading
+    /// is printed on.
+    #[test]
+    fn an_outline_comes_from_bookmarks_or_else_from_the_aux() {
+        let (Some((_linked_guard, linked)), Some((_plain_guard, plain))) =
+            (linked_fixture(), fixture())
+        else {
+            eprintln!("skipping: latexmk is not installed");
+            return;
+        };
+
+        let document = open(&linked).unwrap();
+        let marked = bookmarks(&document).unwrap();
+        assert_eq!(marked.len(), 1, "{marked:?}");
+        assert!(marked[0].title.ends_with("Later"));
+        assert_eq!(marked[0].page, Some(2));
+
+        let document = open(&plain).unwrap();
+        assert!(bookmarks(&document).unwrap().is_empty());
+        let aux = std::fs::read_to_string(plain.with_extension("aux")).unwrap();
+        let pages = (0..page_count(&document).unwrap())
+            .map(|index| page_text(&document, index).unwrap())
+            .collect::<Vec<_>>();
+        let derived = outline::from_contents(&aux, &pages);
+        assert_eq!(derived.len(), 1, "{derived:?}");
+        assert_eq!(derived[0].title, "1 Introduction");
+        assert_eq!(derived[0].page, 1);
+    }
+
+    #[test]
+    fn a_clipped_svg_shows_only_its_region() {
+        let svg = "<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                   width=\"612pt\" height=\"792pt\" viewBox=\"0 0 612 792\">\n<g/></svg>";
+        let page = PageRegion {
+            x: 0.0,
+            y: 0.0,
+            width: 612.0,
+            height: 792.0,
+        };
+        // Hanging off the right of the page: only the part on it is kept.
+        let region = within(
+            PageRegion {
+                x: 500.0,
+                y: 100.0,
+                width: 200.0,
+                height: 50.5,
+            },
+            page,
+        )
+        .unwrap();
+        assert_eq!(
+            clip_svg(svg, region),
+            "<?xml version=\"1.0\"?>\n<svg xmlns=\"http://www.w3.org/2000/svg\" \
+             width=\"112pt\" height=\"50.5pt\" viewBox=\"500 100 112 50.5\">\n<g/></svg>"
+        );
+
+        let beside = PageRegion { x: 700.0, ..region };
+        assert_eq!(within(beside, page), None);
+    }
+
+    #[test]
+    fn exports_a_page_and_a_region_of_it_as_images() {
+        let Some((_guard, pdf)) = fixture() else {
+            eprintln!("skipping: latexmk is not installed");
+            return;
+        };
+        let document = open(&pdf).unwrap();
+        let page = geometry(&document).unwrap()[0];
+
+        let whole = export_image(&document, 0, ImageFormat::Png, 144.0, None).unwrap();
+        assert_eq!(&whole[..8], b"\x89PNG\r\n\x1a\n");
+        // Twice the page's size in points, as 144 dpi is twice 72.
+        let width = u32::from_be_bytes(whole[16..20].try_into().unwrap());
+        assert_eq!(width, (page.width * 2.0).ceil() as u32);
+
+        let corner = PageRegion {
+            x: 72.0,
+            y: 72.0,
+            width: 144.0,
+            height: 36.0,
+        };
+        let clipped = export_image(&document, 0, ImageFormat::Png, 300.0, Some(corner)).unwrap();
+        let width = u32::from_be_bytes(clipped[16..20].try_into().unwrap());
+        let height = u32::from_be_bytes(clipped[20..24].try_into().unwrap());
+        assert_eq!((width, height), (600, 150));
+
+        let svg = String::from_utf8(
+            export_image(&document, 0, ImageFormat::Svg, 0.0, Some(corner)).unwrap(),
+        )
+        .unwrap();
+        assert!(svg.contains("viewBox=\"72 72 144 36\""), "{svg}");
+
+        assert!(matches!(
+            export_image(&document, 0, ImageFormat::Png, 100_000.0, None),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+
+    /// A tile is the same pixels as that part of the whole page, cut off
+    /// where the page ends.
+    #[test]
+    fn a_tile_is_a_window_onto_the_whole_page() {
+        let Some((_guard, pdf)) = fixture() else {
+            eprintln!("skipping: latexmk is not installed");
+            return;
+        };
+        let document = open(&pdf).unwrap();
+        let whole = render_page(&document, 0, 1.5, false).unwrap();
+        let window = Tile {
+            x: 100,
+            y: 120,
+            width: 64,
+            height: 48,
+        };
+        let tile = render_tile(&document, 0, 1.5, window, false).unwrap();
+        assert_eq!((tile.width, tile.height), (64, 48));
+        let stride = whole.width as usize * 4;
+        for row in 0..48 {
+            let start = (120 + row) * stride + 100 * 4;
+            assert_eq!(
+                &tile.samples()[row * 64 * 4..(row + 1) * 64 * 4],
+                &whole.samples()[start..start + 64 * 4],
+                "row {row}"
+            );
+        }
+
+        let hanging = Tile {
+            x: whole.width - 10,
+            ..window
+        };
+        assert_eq!(
+            render_tile(&document, 0, 1.5, hanging, false)
+                .unwrap()
+                .width,
+            10
+        );
+        let beside = Tile {
+            x: whole.width,
+            ..window
+        };
+        assert!(matches!(
+            render_tile(&document, 0, 1.5, beside, false),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn a_thumbnail_is_a_small_png_of_the_whole_page() {
+        let Some((_guard, pdf)) = fixture() else {
+            eprintln!("skipping: latexmk is not installed");
+            return;
+        };
+        let document = open(&pdf).unwrap();
+        let page = geometry(&document).unwrap()[0];
+        for invert in [false, true] {
+            let png = thumbnail(&document, 0, 120, invert).unwrap();
+            assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+            let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+            let height = u32::from_be_bytes(png[20..24].try_into().unwrap());
+            assert_eq!(width, 120);
+            assert_eq!(height, (page.height * 120.0 / page.width).ceil() as u32);
+        }
+        assert!(matches!(
+            thumbnail(&document, 0, MOST_THUMBNAIL_WIDTH + 1, false),
+            Err(AppError::InvalidInput(_))
+        ));
+    }
+
     /// Both kinds come back, told apart by whether MuPDF could resolve them,
     /// and positioned in the same top-left space as everything else the viewer
     /// is given.
@@ -1084,12 +2556,13 @@ mod tests {
         assert!(introduction.x >= 0.0 && introduction.x < pages[0].width);
 
         // Search finds the word on page two and not on page one.
-        assert!(search_page(&document, 0, "kestrel").unwrap().is_empty());
-        let hits = search_page(&document, 1, "kestrel").unwrap();
+        let kestrel = Query::new("kestrel", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        assert!(search_page(&document, 0, &kestrel).unwrap().is_empty());
+        let hits = search_page(&document, 1, &kestrel).unwrap();
         assert_eq!(hits.len(), 1);
-        assert!(hits[0].width > 0.0);
-
-        assert!(search_page(&document, 0, "").unwrap().is_empty());
+        assert!(hits[0].rects[0].width > 0.0);
     }
 
     #[tokio::test]
@@ -1114,7 +2587,16 @@ mod tests {
         let extracted = pool.words(pdf.clone(), 0).await.unwrap();
         assert!(extracted.iter().any(|word| word.text == "Introduction"));
 
-        let hits = pool.search(pdf.clone(), "kestrel".into()).await.unwrap();
+        let kestrel = Query::new("kestrel", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        assert!(
+            pool.search(pdf.clone(), 0, kestrel.clone())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let hits = pool.search(pdf.clone(), 1, kestrel).await.unwrap();
         assert_eq!(hits.len(), 1);
         assert_eq!(hits[0].page, 1);
 
@@ -1172,6 +2654,201 @@ mod tests {
         assert_eq!(taken, [2, 1, 0], "newest first");
     }
 
+    /// A guessed-at page waits behind every page asked for, and a page asked
+    /// for throws the guesses out.
+    #[test]
+    fn speculation_waits_for_idle_threads_and_yields_to_requests() {
+        let queue = Queue::default();
+        let render = |page| {
+            let (reply, receive) = oneshot::channel();
+            let job = Job::Render {
+                path: PathBuf::from("/paper.pdf"),
+                page,
+                scale: 1.0,
+                invert: false,
+                reply,
+            };
+            (job, receive)
+        };
+        let (asked, _asked) = render(0);
+        let (next, _next) = render(1);
+        let (after, _after) = render(2);
+        assert!(queue.push(asked));
+        assert!(queue.speculate(next));
+        assert!(queue.speculate(after));
+
+        let taken = std::iter::from_fn(|| queue.take())
+            .take(2)
+            .map(|job| match job {
+                Job::Render { page, .. } => page,
+                _ => unreachable!("only renders were queued"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(taken, [0, 1], "asked for, then guessed in order");
+
+        let (asked, _asked) = render(5);
+        assert!(queue.push(asked));
+        let waiting = queue.pending.lock().unwrap();
+        assert!(waiting.speculative.is_empty(), "the reader has moved on");
+        assert_eq!(waiting.jobs.len(), 1);
+    }
+
+    /// A thread is added while there is more waiting than threads to take it
+    /// and none of them idle, and never past the most.
+    #[test]
+    fn the_queue_asks_for_threads_as_work_waits_for_them() {
+        let queue = Queue {
+            fewest: 1,
+            most: 3,
+            ..Queue::default()
+        };
+        queue.pending.lock().unwrap().workers = 1;
+        let mut replies = Vec::new();
+        let mut ask = |page| {
+            let (reply, receive) = oneshot::channel();
+            replies.push(receive);
+            assert!(queue.push(Job::Render {
+                path: PathBuf::from("/paper.pdf"),
+                page,
+                scale: 1.0,
+                invert: false,
+                reply,
+            }));
+        };
+        ask(0);
+        assert!(!queue.enlist(), "one thread for one page");
+        ask(1);
+        assert!(queue.enlist());
+        assert!(!queue.enlist(), "counted in already");
+        for page in 2..8 {
+            ask(page);
+        }
+        assert!(queue.enlist());
+        assert!(!queue.enlist(), "three at most");
+        assert_eq!(queue.pending.lock().unwrap().workers, 3);
+
+        queue.pending.lock().unwrap().idle = 1;
+        queue.discharge();
+        assert!(!queue.enlist(), "an idle thread takes it first");
+    }
+
+    /// However many whole-document jobs are waiting, a page asked for finds a
+    /// thread to draw it.
+    #[test]
+    fn whole_document_jobs_leave_a_thread_for_pages() {
+        let queue = Queue {
+            fewest: 2,
+            most: 2,
+            ..Queue::default()
+        };
+        queue.pending.lock().unwrap().workers = 2;
+        let mut replies = Vec::new();
+        for _ in 0..2 {
+            let (reply, receive) = oneshot::channel();
+            replies.push(receive);
+            assert!(queue.push(Job::Preflight {
+                path: PathBuf::from("/paper.pdf"),
+                reply,
+            }));
+        }
+        assert!(matches!(queue.take(), Some(Job::Preflight { .. })));
+
+        let (reply, _page) = oneshot::channel();
+        assert!(queue.push(Job::Render {
+            path: PathBuf::from("/paper.pdf"),
+            page: 0,
+            scale: 1.0,
+            invert: false,
+            reply,
+        }));
+        assert!(matches!(queue.take(), Some(Job::Render { .. })));
+        {
+            let pending = queue.pending.lock().unwrap();
+            assert_eq!(pending.whole.len(), 1, "the second waits for the first");
+            assert!(!pending.may_start_whole(queue.most));
+        }
+
+        queue.finish_whole();
+        assert!(matches!(queue.take(), Some(Job::Preflight { .. })));
+    }
+
+    #[test]
+    fn latencies_are_summed_up_per_kind_of_job() {
+        let latencies = Latencies::default();
+        latencies.record("render", Duration::from_millis(10));
+        latencies.record("words", Duration::from_millis(5));
+        latencies.record("render", Duration::from_millis(30));
+        let summary = latencies.summary();
+        assert_eq!(
+            summary
+                .iter()
+                .map(|latency| latency.kind.as_str())
+                .collect::<Vec<_>>(),
+            ["render", "words"]
+        );
+        assert_eq!(summary[0].count, 2);
+        assert!((summary[0].mean_ms - 20.0).abs() < 1e-9);
+        assert!((summary[0].longest_ms - 30.0).abs() < 1e-9);
+        assert!((summary[0].last_ms - 30.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn open_documents_are_weighed_against_one_budget() {
+        let documents = OpenDocuments::new(100);
+        documents.add(60);
+        assert!(!documents.over());
+        documents.add(60);
+        assert!(documents.over());
+        documents.remove(60);
+        documents.remove(1_000);
+        assert_eq!(documents.weight(), 0, "never below nothing");
+    }
+
+    /// A thumbnail is somebody's, so it is not thrown out like a guess; but a
+    /// page is what the reader is looking at, so it goes first.
+    #[test]
+    fn thumbnails_wait_behind_pages_and_ahead_of_guesses() {
+        let queue = Queue::default();
+        let (reply, _guessed) = oneshot::channel();
+        assert!(queue.speculate(Job::Render {
+            path: PathBuf::from("/paper.pdf"),
+            page: 9,
+            scale: 1.0,
+            invert: false,
+            reply,
+        }));
+        let (reply, _strip) = oneshot::channel();
+        assert!(queue.push(Job::Thumbnail {
+            path: PathBuf::from("/paper.pdf"),
+            page: 3,
+            width: 120,
+            invert: false,
+            reply,
+        }));
+        let (reply, _page) = oneshot::channel();
+        assert!(queue.push(Job::Render {
+            path: PathBuf::from("/paper.pdf"),
+            page: 0,
+            scale: 1.0,
+            invert: false,
+            reply,
+        }));
+
+        let taken = std::iter::from_fn(|| queue.take())
+            .take(2)
+            .map(|job| match job {
+                Job::Render { page, .. } => ("page", page),
+                Job::Thumbnail { page, .. } => ("thumbnail", page),
+                _ => unreachable!("only pages and thumbnails were queued"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(taken, [("page", 0), ("thumbnail", 3)]);
+        assert!(
+            queue.pending.lock().unwrap().speculative.is_empty(),
+            "the page asked for threw the guess out"
+        );
+    }
+
     /// A scroll that never stops must not pile up work without limit. What is
     /// given up on is the oldest page, which is the one furthest from the
     /// window — and never a one-shot request that something is still waiting on.
@@ -1238,6 +2915,9 @@ mod tests {
 
         let pool = RenderPool::new(1);
         assert_eq!(pool.geometry(live.clone()).await.unwrap().len(), 2);
+        // Drawn once, so that a page kept from the old file is there to be
+        // wrongly handed back.
+        pool.render(live.clone(), 0, 1.0, false).await.unwrap();
 
         std::fs::write(&live, std::fs::read(&second).unwrap()).unwrap();
 
@@ -1339,9 +3019,12 @@ mod tests {
         let words_time = started.elapsed();
 
         let started = Instant::now();
+        let the = Query::new("the", SearchOptions::default())
+            .unwrap()
+            .unwrap();
         let mut hits = 0;
         for index in 0..count {
-            hits += search_page(&document, index, "the").unwrap().len();
+            hits += search_page(&document, index, &the).unwrap().len();
         }
         let search_time = started.elapsed();
 
@@ -4,12 +4,15 @@
 //! project, so compiling a version out of the history is the same code path as
 //! compiling the working tree.
 //!
-//! Two rules this module enforces, both from hard experience:
+//! Three rules this module enforces, all from hard experience:
 //!
 //! * A successful build always publishes. Whether it has already been
 //!   superseded by another save is the queue's business; throwing away a
 //!   finished PDF means a document that saves faster than it compiles never
 //!   updates at all.
+//! * Advice never holds a PDF back. Nothing [`check`] finds decides whether a
+//!   build succeeded, so it runs once the PDF is out, and what it says follows
+//!   the page rather than the page waiting on it.
 //! * The child's pid is only ever signalled while its handle is still alive, so
 //!   a reaped pid can never be recycled and signalled by mistake.
 
@@ -32,7 +35,7 @@ use tokio::{
 use crate::{
     diagnostics::{self, ProgressParser, ProgressSnapshot},
     error::{AppError, AppResult},
-    model::{Diagnostic, DocumentKind, Project, Severity},
+    model::{Diagnostic, DocumentKind, DocumentStatistics, PageConstraints, Project, Severity},
     sources::PreparedSource,
     toolchain::{augmented_path, resolve_executable},
 };
@@ -201,8 +204,10 @@ pub async fn run(
                         diagnostics: vec![Diagnostic {
                             file: Some(inputs.source.file_name.clone()),
                             line: None,
+                            column: None,
                             severity: Severity::Error,
                             message: summary.clone(),
+                            source: None,
                         }],
                         summary,
                     });
@@ -356,6 +361,109 @@ pub async fn run(
     })
 }
 
+/// What the checks behind a published build read, beyond what the build did.
+pub struct CheckInputs<'a> {
+    pub project: &'a Project,
+    pub source: &'a PreparedSource,
+    /// The build's `-outdir`, where latexmk's recorder output is.
+    pub work_directory: PathBuf,
+    /// Whether chktex runs at all, as the reader has it set.
+    pub lint: bool,
+    /// chktex warning numbers this project leaves out of its lint stage.
+    pub lint_ignores: Vec<u32>,
+    /// Words the spelling check accepts for this project.
+    pub dictionary: Vec<String>,
+    /// The published PDF's page count, carried into the statistics.
+    pub page_count: Option<i64>,
+    /// The published PDF itself.
+    pub pdf: PathBuf,
+    /// The venue's limits, checked against the PDF.
+    pub constraints: PageConstraints,
+}
+
+/// What [`check`] found.
+pub struct Advice {
+    pub diagnostics: Vec<Diagnostic>,
+    pub statistics: DocumentStatistics,
+}
+
+/// Advice about a build that compiled and has been published: style from
+/// chktex, spelling from whichever checker is installed, the venue's limits,
+/// and the counts the history shows beside each build.
+///
+/// `None` when the build is cancelled before the advice is in.
+pub async fn check(inputs: CheckInputs<'_>, cancel: &Cancel) -> Option<Advice> {
+    let checks = async {
+        // For LaTeX both read the files the build did. chktex leaves markdown
+        // alone — the LaTeX it would read is pandoc's, and nothing it says
+        // about that is something the author could act on.
+        let kind = inputs.project.kind();
+        let checked = match kind {
+            DocumentKind::Latex => {
+                let recorder = inputs
+                    .work_directory
+                    .join(format!("{}.fls", inputs.project.job_name()));
+                let recorded = tokio::fs::read_to_string(&recorder)
+                    .await
+                    .unwrap_or_default();
+                crate::lint::used_sources(&recorded, &inputs.source.directory)
+            }
+            DocumentKind::Markdown => vec![inputs.source.file_name.clone()],
+        };
+        let lint = async {
+            if inputs.lint && kind == DocumentKind::Latex {
+                crate::lint::run(&inputs.source.directory, &checked, &inputs.lint_ignores).await
+            } else {
+                Vec::new()
+            }
+        };
+        let spelling =
+            crate::spelling::run(&inputs.source.directory, &checked, kind, &inputs.dictionary);
+        // Counted from the same files, so a word count covers what the PDF
+        // holds and not the drafts beside it.
+        let statistics =
+            crate::statistics::collect(&inputs.source.directory, &checked, kind, inputs.page_count);
+        let compliance = async {
+            if inputs.constraints.is_empty() {
+                return Vec::new();
+            }
+            // Where the references start is in the contents lines the `.aux`
+            // carries. Not the `.toc`: that is written only for a document
+            // that prints one, and one left from an older build would be
+            // believed.
+            let aux = format!("{}.aux", inputs.project.job_name());
+            let contents = tokio::fs::read_to_string(inputs.work_directory.join(aux))
+                .await
+                .unwrap_or_default();
+            let pdf = inputs.pdf.clone();
+            let constraints = inputs.constraints.clone();
+            let checked = tauri::async_runtime::spawn_blocking(move || {
+                crate::compliance::check(&pdf, &constraints, &contents)
+            })
+            .await;
+            // A PDF MuPDF cannot read is one the viewer will say so about.
+            match checked {
+                Ok(Ok(findings)) => findings,
+                _ => Vec::new(),
+            }
+        };
+        // Each waits on a process or a read of its own, so they run side by
+        // side.
+        let (mut diagnostics, misspelled, statistics, compliance) =
+            tokio::join!(lint, spelling, statistics, compliance);
+        diagnostics.extend(misspelled);
+        diagnostics.extend(compliance);
+        Advice {
+            diagnostics,
+            statistics,
+        }
+    };
+    tokio::select! {
+        advice = checks => Some(advice),
+        () = cancel.cancelled() => None,
+    }
+}
+
 /// Moves diagnostics off the generated LaTeX and onto the markdown the author
 /// actually wrote.
 ///
@@ -531,6 +639,13 @@ async fn publish(
             break;
         }
     }
+    // The headings as LaTeX recorded them, for an outline of a PDF that was
+    // built without bookmarks.
+    let _ = tokio::fs::copy(
+        work_directory.join(format!("{job_name}.aux")),
+        artifact_directory.join(format!("build-{stamp}.aux")),
+    )
+    .await;
     // For markdown, SyncTeX can only name pandoc's output. The anchors in it
     // are what carry an answer back to the markdown the author wrote.
     if let Ok(latex) =
@@ -555,8 +670,8 @@ async fn publish(
 
 /// The name every file of one publication shares.
 ///
-/// `publish` writes `build-<stamp>.pdf` and, beside it, `build-<stamp>.synctex.gz`
-/// and `build-<stamp>.lines`. `file_stem` would leave the `.synctex` on the
+/// `publish` writes `build-<stamp>.pdf` and, beside it, `build-<stamp>.synctex.gz`,
+/// `build-<stamp>.aux` and `build-<stamp>.lines`. `file_stem` would leave the `.synctex` on the
 /// first of those, so the name is taken up to its first dot instead — which is
 /// exactly the part `publish` composes.
 pub fn publication_stem(path: &Path) -> Option<&str> {
@@ -1100,20 +1215,26 @@ mod tests {
             Diagnostic {
                 file: Some("/cache/work/essay.tex".into()),
                 line: Some(214),
+                column: None,
                 severity: Severity::Error,
                 message: "Undefined control sequence.".into(),
+                source: None,
             },
             Diagnostic {
                 file: Some("essay.tex".into()),
                 line: Some(9),
+                column: None,
                 severity: Severity::Warning,
                 message: "Overfull hbox".into(),
+                source: None,
             },
             Diagnostic {
                 file: Some("/usr/local/texlive/article.cls".into()),
                 line: Some(5),
+                column: None,
                 severity: Severity::Error,
                 message: "Something in a class file.".into(),
+                source: None,
             },
         ];
         attribute_to_source(&mut diagnostics, generated, "essay.md");
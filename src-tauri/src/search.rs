@@ -0,0 +1,334 @@
+//! Searching a document's text, with the options an editor's search has.
+//!
+//! MuPDF's own search is literal, ignores case whether asked to or not, and
+//! stops at the end of a line. This one reads the words MuPDF extracts instead
+//! — the same ones the selection overlay is built from — joined back into the
+//! running text of the page: a line break becomes a space, a word broken
+//! across lines with a hyphen becomes the word again, and a ligature becomes
+//! the letters it was set from. Every kind of search is then one regular
+//! expression over that text, and a match is mapped back through the words it
+//! touched to the rectangles to highlight.
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{
+    error::{AppError, AppResult},
+    extract,
+    model::{SearchHit, SearchMatch, SearchOptions},
+    render::Word,
+};
+
+/// Past this a page is all matches, and a highlight on every word tells the
+/// reader nothing a narrower search would not.
+const MAX_MATCHES_PER_PAGE: usize = 500;
+/// Characters of context either side of a match in the results list.
+const CONTEXT: usize = 40;
+/// A regular expression bigger than this compiled is refused, so a pasted
+/// pattern cannot hold a worker for long.
+const MOST_PATTERN_SIZE: usize = 1 << 20;
+
+/// What to look for, compiled once for every page it is run over.
+#[derive(Debug, Clone)]
+pub struct Query {
+    pattern: Regex,
+}
+
+impl Query {
+    /// `None` for a needle with nothing in it to find.
+    pub fn new(needle: &str, options: SearchOptions) -> AppResult<Option<Self>> {
+        if needle.trim().is_empty() {
+            return Ok(None);
+        }
+        let mut pattern = if options.regex {
+            needle.to_owned()
+        } else {
+            // The running text has a single space wherever the page had any
+            // white space at all, a line break included.
+            needle
+                .split_whitespace()
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(r"\s+")
+        };
+        if options.whole_word {
+            pattern = format!(r"\b(?:{pattern})\b");
+        }
+        let pattern = RegexBuilder::new(&pattern)
+            .case_insensitive(!options.case_sensitive)
+            .size_limit(MOST_PATTERN_SIZE)
+            .build()
+            .map_err(|error| {
+                AppError::InvalidInput(format!("the search is not a valid pattern: {error}"))
+            })?;
+        Ok(Some(Self { pattern }))
+    }
+
+    /// The compiled expression, for searching text that is not a page.
+    pub fn pattern(&self) -> &Regex {
+        &self.pattern
+    }
+}
+
+/// Where one word's text sits in the running text.
+struct Piece {
+    /// Byte offsets into the running text.
+    start: usize,
+    end: usize,
+    word: usize,
+    /// Characters in the word as expanded, counting a hyphen the text left
+    /// out, among which its width is shared.
+    glyphs: usize,
+}
+
+/// A page's words as running text, and where each came from.
+struct Running {
+    text: String,
+    pieces: Vec<Piece>,
+}
+
+fn running(words: &[Word]) -> Running {
+    let mut text = String::new();
+    let mut pieces = Vec::with_capacity(words.len());
+    for (index, word) in words.iter().enumerate() {
+        let next = words.get(index + 1);
+        // "ﬁnal" is found by "final", as the reader typed it.
+        let expanded = extract::expand_ligatures(word.text.trim());
+        let glyphs = expanded.chars().count();
+        let mut content = expanded.as_str();
+        let joined =
+            next.is_some_and(|next| next.line != word.line && extract::hyphenated(content, next));
+        if joined {
+            content = &content[..content.len() - 1];
+        }
+        if content.is_empty() {
+            continue;
+        }
+        let start = text.len();
+        text.push_str(content);
+        pieces.push(Piece {
+            start,
+            end: text.len(),
+            word: index,
+            glyphs,
+        });
+        if !joined && next.is_some() {
+            text.push(' ');
+        }
+    }
+    Running { text, pieces }
+}
+
+/// Everything `query` matches among a page's words. `page` is 0-based, as the
+/// rest of the viewer counts them.
+pub fn find(page: usize, words: &[Word], query: &Query) -> Vec<SearchMatch> {
+    let running = running(words);
+    query
+        .pattern
+        .find_iter(&running.text)
+        .filter(|found| !found.is_empty())
+        .take(MAX_MATCHES_PER_PAGE)
+        .map(|found| SearchMatch {
+            page,
+            rects: rects(page, words, &running, found.start(), found.end()),
+            before: before(&running.text[..found.start()]),
+            text: found.as_str().to_owned(),
+            after: after(&running.text[found.end()..]),
+        })
+        .collect()
+}
+
+/// The rectangles covering bytes `start..end` of the running text: the part of
+/// each word inside it, merged along each line.
+fn rects(
+    page: usize,
+    words: &[Word],
+    running: &Running,
+    start: usize,
+    end: usize,
+) -> Vec<SearchHit> {
+    let mut rects = Vec::<(usize, SearchHit)>::new();
+    for piece in &running.pieces {
+        if piece.end <= start || piece.start >= end {
+            continue;
+        }
+        let word = &words[piece.word];
+        // Glyphs are taken as equally wide, which is close enough to box part
+        // of a word; the hyphen left out of the text still has its share, and
+        // a ligature has one share for each letter it stands for.
+        let glyphs = piece.glyphs.max(1) as f32;
+        let text = &running.text[piece.start..piece.end];
+        let first = text[..start.saturating_sub(piece.start)].chars().count() as f32;
+        let last = text[..end.min(piece.end) - piece.start].chars().count() as f32;
+        let left = word.x + word.width * first / glyphs;
+        let right = word.x + word.width * last / glyphs;
+        match rects.last_mut() {
+            Some((line, rect)) if *line == word.line => {
+                let top = rect.y.min(word.y);
+                let bottom = (rect.y + rect.height).max(word.y + word.height);
+                rect.width = right.max(rect.x + rect.width) - rect.x.min(left);
+                rect.x = rect.x.min(left);
+                rect.y = top;
+                rect.height = bottom - top;
+            }
+            _ => rects.push((
+                word.line,
+                SearchHit {
+                    page,
+                    x: left,
+                    y: word.y,
+                    width: right - left,
+                    height: word.height,
+                },
+            )),
+        }
+    }
+    rects.into_iter().map(|(_, rect)| rect).collect()
+}
+
+/// The end of `text`, cut back to a whole word when it had to be cut.
+fn before(text: &str) -> String {
+    let count = text.chars().count();
+    if count <= CONTEXT {
+        return text.trim_start().to_owned();
+    }
+    let cut = text.chars().skip(count - CONTEXT).collect::<String>();
+    match cut.split_once(' ') {
+        Some((_, rest)) => rest.to_owned(),
+        None => cut,
+    }
+}
+
+/// The start of `text`, cut back to a whole word when it had to be cut.
+fn after(text: &str) -> String {
+    if text.chars().count() <= CONTEXT {
+        return text.trim_end().to_owned();
+    }
+    let cut = text.chars().take(CONTEXT).collect::<String>();
+    match cut.rsplit_once(' ') {
+        Some((rest, _)) => rest.to_owned(),
+        None => cut,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Words laid out left to right, ten points a glyph, a line per row.
+    fn page(lines: &[&str]) -> Vec<Word> {
+        let mut words = Vec::new();
+        for (line, text) in lines.iter().enumerate() {
+            let mut x = 0.0;
+            for word in text.split(' ') {
+                let width = word.chars().count() as f32 * 10.0;
+                words.push(Word {
+                    text: word.to_owned(),
+                    x,
+                    y: line as f32 * 20.0,
+                    width,
+                    height: 12.0,
+                    line,
+                });
+                x += width + 10.0;
+            }
+        }
+        words
+    }
+
+    fn search(words: &[Word], needle: &str, options: SearchOptions) -> Vec<String> {
+        let query = Query::new(needle, options).unwrap().unwrap();
+        find(0, words, &query)
+            .into_iter()
+            .map(|found| found.text)
+            .collect()
+    }
+
+    #[test]
+    fn case_whole_words_and_patterns_are_each_honoured() {
+        let words = page(&["The kestrel and the Kestrels", "hover, then kestrel 42"]);
+        let plain = SearchOptions::default();
+        assert_eq!(search(&words, "kestrel", plain).len(), 3);
+
+        let cased = SearchOptions {
+            case_sensitive: true,
+            ..plain
+        };
+        assert_eq!(search(&words, "Kestrel", cased), vec!["Kestrel"]);
+
+        let whole = SearchOptions {
+            whole_word: true,
+            ..plain
+        };
+        assert_eq!(search(&words, "kestrel", whole), vec!["kestrel", "kestrel"]);
+
+        let pattern = SearchOptions {
+            regex: true,
+            ..plain
+        };
+        assert_eq!(
+            search(&words, r"kestrel\s+\d+", pattern),
+            vec!["kestrel 42"]
+        );
+        // Taken literally unless asked otherwise.
+        assert!(search(&words, r"\d+", plain).is_empty());
+
+        assert!(Query::new("(", pattern).is_err());
+        assert!(Query::new("  ", plain).unwrap().is_none());
+    }
+
+    #[test]
+    fn a_match_runs_across_a_line_break_and_a_hyphen() {
+        let words = page(&["the whole pipe-", "line, then Well-", "Known names"]);
+
+        let query = Query::new("whole pipeline", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        let found = find(3, &words, &query);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].text, "whole pipeline");
+        // One rectangle on each line, each starting where the match does.
+        let rects = &found[0].rects;
+        assert_eq!(rects.len(), 2);
+        assert_eq!((rects[0].page, rects[0].x, rects[0].y), (3, 40.0, 0.0));
+        assert_eq!((rects[1].x, rects[1].y), (0.0, 20.0));
+        assert_eq!(rects[1].width, 40.0);
+
+        // A capital after the hyphen keeps it.
+        assert!(search(&words, "wellknown", SearchOptions::default()).is_empty());
+        assert_eq!(
+            search(&words, "Well- Known", SearchOptions::default()),
+            vec!["Well- Known"]
+        );
+    }
+
+    #[test]
+    fn part_of_a_word_is_boxed_and_shown_with_its_context() {
+        let words = page(&["a long preamble of several words before the kestrels arrive"]);
+        let query = Query::new("kestrel", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        let found = &find(0, &words, &query)[0];
+
+        let kestrels = words.iter().find(|word| word.text == "kestrels").unwrap();
+        assert_eq!(found.rects[0].x, kestrels.x);
+        assert_eq!(found.rects[0].width, 70.0);
+
+        assert_eq!(found.before, "preamble of several words before the ");
+        assert_eq!(found.after, "s arrive");
+    }
+
+    #[test]
+    fn ligatures_are_searched_as_their_letters() {
+        let words = page(&["the \u{FB01}nal o\u{FB03}ce"]);
+        let query = Query::new("final office", SearchOptions::default())
+            .unwrap()
+            .unwrap();
+        let found = find(0, &words, &query);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].text, "final office");
+        // Boxed from the first word's left edge to the second's right.
+        let (first, last) = (&words[1], &words[2]);
+        assert_eq!(found[0].rects[0].x, first.x);
+        assert_eq!(found[0].rects[0].width, last.x + last.width - first.x);
+    }
+}
@@ -0,0 +1,470 @@
+//! Spelling, checked on this machine.
+//!
+//! The prose of a document, as `prose` picks it out of the source, is handed to
+//! whichever of hunspell and aspell is installed, through the ispell pipe both
+//! speak.
+//! Nothing is sent anywhere: an unpublished paper stays on the disk it was
+//! written on.
+//!
+//! Findings are diagnostics tagged [`SOURCE`], with the column as well as the
+//! line, so they land in the build panel and in an editor's quickfix list the
+//! same way chktex's do. A word in the project's personal dictionary, which
+//! lives in Press's database, is never reported.
+//!
+//! Repeated words — "the the" — are found here as well. That needs no checker
+//! at all, so it happens whether or not one is installed.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    model::{Diagnostic, DocumentKind, Severity},
+    prose,
+    toolchain::{augmented_path, resolve_executable},
+};
+
+/// The tag on every diagnostic found here.
+pub const SOURCE: &str = "spelling";
+/// A whole thesis is a few thousand distinct words, which either checker gets
+/// through in well under a second.
+const TIMEOUT: Duration = Duration::from_secs(20);
+/// As for the build's own log: past this a list is a wall.
+const MAX_FINDINGS: usize = 200;
+/// How many suggestions a finding carries. The first is usually the answer.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Checks the prose of `files`, relative to `directory`, as `kind`.
+pub async fn run(
+    directory: &Path,
+    files: &[String],
+    kind: DocumentKind,
+    dictionary: &[String],
+) -> Vec<Diagnostic> {
+    let known = dictionary
+        .iter()
+        .map(|word| word.to_lowercase())
+        .collect::<HashSet<_>>();
+    let mut occurrences = Vec::new();
+    let mut findings = Vec::new();
+    for file in files {
+        let Ok(text) = tokio::fs::read_to_string(directory.join(file)).await else {
+            continue;
+        };
+        let prose = match kind {
+            DocumentKind::Latex => prose::latex(&text),
+            DocumentKind::Markdown => prose::markdown(&text),
+        };
+        let found = words(&prose);
+        findings.extend(repeated(&found, &prose).into_iter().map(|word| {
+            finding(
+                file,
+                &word,
+                format!("\"{}\" is repeated", word.text.to_lowercase()),
+            )
+        }));
+        occurrences.extend(
+            found
+                .into_iter()
+                .filter(|word| !known.contains(&word.text.to_lowercase()))
+                .map(|word| (file, word)),
+        );
+    }
+
+    let unique = occurrences
+        .iter()
+        .map(|(_, word)| word.text.clone())
+        .collect::<HashSet<_>>();
+    let misspelled = check(unique).await;
+    for (file, word) in &occurrences {
+        let Some(suggestions) = misspelled.get(&word.text) else {
+            continue;
+        };
+        let message = if suggestions.is_empty() {
+            format!("Unrecognised word \"{}\"", word.text)
+        } else {
+            format!(
+                "Unrecognised word \"{}\"; perhaps {}",
+                word.text,
+                suggestions.join(", ")
+            )
+        };
+        findings.push(finding(file, word, message));
+    }
+
+    findings.sort_by(|left, right| {
+        (&left.file, left.line, left.column).cmp(&(&right.file, right.line, right.column))
+    });
+    findings.truncate(MAX_FINDINGS);
+    findings
+}
+
+fn finding(file: &str, word: &Word, message: String) -> Diagnostic {
+    Diagnostic {
+        file: Some(file.to_owned()),
+        line: Some(word.line),
+        column: Some(word.column),
+        severity: Severity::Warning,
+        message,
+        source: Some(SOURCE.to_owned()),
+    }
+}
+
+/// The first of hunspell and aspell that is installed, with the arguments that
+/// put it in ispell's pipe mode reading UTF-8.
+fn checker() -> Option<(PathBuf, &'static [&'static str])> {
+    const HUNSPELL: &[&str] = &["-a", "-i", "utf-8"];
+    const ASPELL: &[&str] = &["-a", "--encoding=utf-8"];
+    resolve_executable("hunspell")
+        .map(|hunspell| (hunspell, HUNSPELL))
+        .or_else(|| resolve_executable("aspell").map(|aspell| (aspell, ASPELL)))
+}
+
+/// Asks the checker about each word once, and answers with the ones it did not
+/// know and what it suggested instead. Nothing, when there is no checker.
+async fn check(words: HashSet<String>) -> HashMap<String, Vec<String>> {
+    let Some((executable, arguments)) = checker() else {
+        return HashMap::new();
+    };
+    if words.is_empty() {
+        return HashMap::new();
+    }
+    let mut command = Command::new(&executable);
+    command.env("PATH", augmented_path(&executable));
+    command.args(arguments);
+    command.stdin(Stdio::piped());
+    command.stdout(Stdio::piped()).stderr(Stdio::null());
+    command.kill_on_drop(true);
+    let Ok(mut child) = command.spawn() else {
+        return HashMap::new();
+    };
+
+    // One word a line, each behind `^`, which tells the pipe that what follows
+    // is text to check rather than one of its own commands.
+    let mut input = String::new();
+    for word in &words {
+        input.push('^');
+        input.push_str(word);
+        input.push('\n');
+    }
+    // Written from its own task, while the output is read here: the checker
+    // answers as it goes, and with both pipes left to fill, a long document
+    // would leave each side waiting on the other.
+    if let Some(mut stdin) = child.stdin.take() {
+        tauri::async_runtime::spawn(async move {
+            let _ = stdin.write_all(input.as_bytes()).await;
+        });
+    }
+    match tokio::time::timeout(TIMEOUT, child.wait_with_output()).await {
+        Ok(Ok(output)) => parse_pipe(&String::from_utf8_lossy(&output.stdout)),
+        _ => HashMap::new(),
+    }
+}
+
+/// Reads ispell's pipe output: `& word count offset: one, two` for a word
+/// with suggestions, `# word offset` for one without, and lines starting `*`,
+/// `+` or `-` for words it knew.
+fn parse_pipe(output: &str) -> HashMap<String, Vec<String>> {
+    let mut misspelled = HashMap::new();
+    for line in output.lines() {
+        if let Some(rest) = line.strip_prefix("& ") {
+            let Some((head, suggestions)) = rest.split_once(':') else {
+                continue;
+            };
+            let Some(word) = head.split_whitespace().next() else {
+                continue;
+            };
+            let suggestions = suggestions
+                .split(',')
+                .map(str::trim)
+                .filter(|suggestion| !suggestion.is_empty())
+                .take(MAX_SUGGESTIONS)
+                .map(ToOwned::to_owned)
+                .collect();
+            misspelled.insert(word.to_owned(), suggestions);
+        } else if let Some(rest) = line.strip_prefix("# ")
+            && let Some(word) = rest.split_whitespace().next()
+        {
+            misspelled.insert(word.to_owned(), Vec::new());
+        }
+    }
+    misspelled
+}
+
+/// One word of prose and where it starts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Word {
+    text: String,
+    /// 1-based.
+    line: u32,
+    /// 1-based, in characters.
+    column: u32,
+}
+
+/// The words worth checking in lines of prose.
+///
+/// A token that looks like an address is skipped whole, and so is a word that
+/// is really an acronym, a name in camel case or part of an identifier: the
+/// checker would flag every one of them, and none of them is a spelling.
+fn words(prose: &[String]) -> Vec<Word> {
+    let mut found = Vec::new();
+    for (index, line) in prose.iter().enumerate() {
+        let characters = line.chars().collect::<Vec<_>>();
+        let mut cursor = 0;
+        while cursor < characters.len() {
+            if characters[cursor].is_whitespace() {
+                cursor += 1;
+                continue;
+            }
+            let token_end = cursor
+                + characters[cursor..]
+                    .iter()
+                    .take_while(|character| !character.is_whitespace())
+                    .count();
+            let token = characters[cursor..token_end].iter().collect::<String>();
+            if !(token.contains("://") || token.contains('@') || token.starts_with("www.")) {
+                found.extend(token_words(&characters[cursor..token_end]).into_iter().map(
+                    |(offset, text)| Word {
+                        text,
+                        line: index as u32 + 1,
+                        column: (cursor + offset) as u32 + 1,
+                    },
+                ));
+            }
+            cursor = token_end;
+        }
+    }
+    found
+}
+
+/// Runs of letters within one token, joined across an apostrophe between two
+/// letters so "don't" is one word.
+fn token_words(token: &[char]) -> Vec<(usize, String)> {
+    let mut found = Vec::new();
+    let mut cursor = 0;
+    while cursor < token.len() {
+        if !token[cursor].is_alphabetic() {
+            cursor += 1;
+            continue;
+        }
+        let start = cursor;
+        while cursor < token.len() {
+            let character = token[cursor];
+            let joins = matches!(character, '\'' | '\u{2019}')
+                && token
+                    .get(cursor + 1)
+                    .is_some_and(|next| next.is_alphabetic())
+                && cursor > start;
+            if character.is_alphabetic() || joins {
+                cursor += 1;
+            } else {
+                break;
+            }
+        }
+        let touches_digit = start
+            .checked_sub(1)
+            .and_then(|before| token.get(before))
+            .is_some_and(char::is_ascii_digit)
+            || token.get(cursor).is_some_and(char::is_ascii_digit);
+        let word = token[start..cursor].iter().collect::<String>();
+        let shouty = word.chars().skip(1).any(char::is_uppercase);
+        if word.chars().count() > 1 && !touches_digit && !shouty {
+            found.push((start, word));
+        }
+    }
+    found
+}
+
+/// A word that follows itself with nothing but space between, across a line
+/// break but not across a blank line or a skipped command.
+fn repeated(words: &[Word], prose: &[String]) -> Vec<Word> {
+    let mut found = Vec::new();
+    for pair in words.windows(2) {
+        let (first, second) = (&pair[0], &pair[1]);
+        if first.text.to_lowercase() != second.text.to_lowercase() {
+            continue;
+        }
+        if between(prose, first, second).is_some_and(|gap| gap.trim().is_empty()) {
+            found.push(second.clone());
+        }
+    }
+    found
+}
+
+/// The prose between the end of one word and the start of the next, with line
+/// breaks as `\n`. `None` when a line between them held no prose at all, which
+/// is something skipped — a command, a comment — rather than a break in a
+/// sentence.
+fn between(prose: &[String], first: &Word, second: &Word) -> Option<String> {
+    let first_line = first.line as usize - 1;
+    let second_line = second.line as usize - 1;
+    let after = first.column as usize - 1 + first.text.chars().count();
+    if first_line == second_line {
+        return Some(
+            prose[first_line]
+                .chars()
+                .skip(after)
+                .take(second.column as usize - 1 - after)
+                .collect(),
+        );
+    }
+    let mut gap = prose[first_line].chars().skip(after).collect::<String>();
+    for line in &prose[first_line + 1..second_line] {
+        if line.trim().is_empty() {
+            return None;
+        }
+        gap.push('\n');
+        gap.push_str(line);
+    }
+    gap.push('\n');
+    gap.extend(prose[second_line].chars().take(second.column as usize - 1));
+    Some(gap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checked(words: &[Word]) -> Vec<&str> {
+        words.iter().map(|word| word.text.as_str()).collect()
+    }
+
+    #[test]
+    fn latex_prose_leaves_commands_math_and_verbatim_behind() {
+        let source = "\\documentclass{article}\n\
+                      \\usepackage{amsmath}\n\
+                      \\begin{document}\n\
+                      \\section{Introduction} as in~\\cite{knuth84}, see \\ref{fig:plot}.\n\
+                      Let $x = \\alpha$ be \\emph{given}. % a commment\n\
+                      \\begin{equation}\n\
+                      E = mc^2 \\label{eq:energy}\n\
+                      \\end{equation}\n\
+                      \\begin{verbatim}\n\
+                      teh code\n\
+                      \\end{verbatim}\n\
+                      Done \\verb|nope| here.\n\
+                      \\end{document}\n";
+        let prose = prose::latex(source);
+        assert_eq!(prose.len(), source.lines().count(), "line for line");
+        let found = words(&prose);
+        assert_eq!(
+            checked(&found),
+            [
+                "Introduction",
+                "as",
+                "in",
+                "see",
+                "Let",
+                "be",
+                "given",
+                "Done",
+                "here"
+            ]
+        );
+        // Columns are where the word is in the file.
+        let given = found.iter().find(|word| word.text == "given").unwrap();
+        assert_eq!((given.line, given.column), (5, 27));
+    }
+
+    #[test]
+    fn markdown_prose_follows_the_block_structure() {
+        let source = "---\n\
+                      title: Teh Paper\n\
+                      ---\n\
+                      \n\
+                      Some `inline codde` and $x$ with [a link](http://exmple.org).\n\
+                      \n\
+                      ```\n\
+                      fenced codde\n\
+                      ```\n\
+                      \n\
+                      $$\n\
+                      a + b\n\
+                      $$\n\
+                      As [@smith2020] says, \\textbf{bold}.\n";
+        let found = words(&prose::markdown(source));
+        assert_eq!(
+            checked(&found),
+            ["Some", "and", "with", "link", "As", "says", "bold"]
+        );
+    }
+
+    #[test]
+    fn acronyms_identifiers_and_addresses_are_not_words() {
+        let prose = vec![
+            "NASA uses LaTeX and mp3 files; mail me@example.org or www.example.org, don't."
+                .to_owned(),
+        ];
+        assert_eq!(
+            checked(&words(&prose)),
+            ["uses", "and", "files", "mail", "or", "don't"]
+        );
+    }
+
+    #[test]
+    fn finds_a_word_repeated_across_a_line_break_but_not_a_paragraph() {
+        let prose = vec![
+            "This is the".to_owned(),
+            "the end, and and that".to_owned(),
+            String::new(),
+            "that was all.".to_owned(),
+        ];
+        let found = words(&prose);
+        let repeats = repeated(&found, &prose);
+        assert_eq!(checked(&repeats), ["the", "and"]);
+        assert_eq!((repeats[0].line, repeats[0].column), (2, 1));
+    }
+
+    #[test]
+    fn reads_misspellings_and_suggestions_from_the_pipe() {
+        let output = "@(#) International Ispell Version 3.2.06 (but really Hunspell 1.7.2)\n\
+                      *\n\
+                      \n\
+                      & teh 4 0: the, ten, tech, eh\n\
+                      \n\
+                      # qwzx 0\n\
+                      \n\
+                      + walk\n";
+        let misspelled = parse_pipe(output);
+        assert_eq!(misspelled["teh"], ["the", "ten", "tech"]);
+        assert!(misspelled["qwzx"].is_empty());
+        assert_eq!(misspelled.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_words_unless_the_dictionary_has_them() {
+        if checker().is_none() {
+            eprintln!("skipping: neither hunspell nor aspell is installed");
+            return;
+        }
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(
+            directory.path().join("main.tex"),
+            "\\begin{document}\nA sentance about Zorblax.\n\\end{document}\n",
+        )
+        .unwrap();
+        let files = ["main.tex".to_owned()];
+
+        let findings = run(directory.path(), &files, DocumentKind::Latex, &[]).await;
+        let sentence = findings
+            .iter()
+            .find(|finding| finding.message.contains("\"sentance\""))
+            .expect("a misspelling is reported");
+        assert_eq!(sentence.line, Some(2));
+        assert_eq!(sentence.column, Some(3));
+        assert_eq!(sentence.source.as_deref(), Some(SOURCE));
+
+        let dictionary = ["zorblax".to_owned()];
+        let findings = run(directory.path(), &files, DocumentKind::Latex, &dictionary).await;
+        assert!(
+            !findings
+                .iter()
+                .any(|finding| finding.message.contains("Zorblax"))
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! How long a document is, counted the way its author would count it.
+//!
+//! Words are split three ways, as texcount splits them: running text,
+//! headings, and captions. A thesis limit is usually on the first. The
+//! abstract's words are counted again on their own, since a limit on the
+//! abstract is a limit on it alone: the `abstract` environment in LaTeX, the
+//! `abstract` field of the front matter in markdown. Figures, tables and
+//! display equations are counted beside them.
+//!
+//! LaTeX is read from the files the build used, through the same prose filter
+//! the spelling check uses, so the two agree about what a word is. Markdown is
+//! read from pandoc's own syntax tree, which knows a caption from a heading
+//! without Press guessing; when pandoc cannot be run, the markdown falls back to
+//! the prose filter too, with headings told apart by their `#`.
+//!
+//! Counted once per successful build and stored beside the artifact, so every
+//! version in the history carries its own numbers.
+
+use std::{path::Path, process::Stdio, sync::LazyLock, time::Duration};
+
+use regex::Regex;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::{
+    documents::strip_comment,
+    model::{DocumentKind, DocumentStatistics},
+    prose,
+    toolchain::{augmented_path, resolve_executable},
+};
+
+/// pandoc parses a long document in well under a second.
+const TIMEOUT: Duration = Duration::from_secs(20);
+
+/// Commands whose argument is a heading.
+const HEADINGS: &[&str] = &[
+    "title",
+    "part",
+    "chapter",
+    "section",
+    "subsection",
+    "subsubsection",
+    "paragraph",
+    "subparagraph",
+];
+const CAPTIONS: &[&str] = &["caption", "subcaption"];
+
+static FIGURE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\\begin\{(?:figure|wrapfigure)\*?\}").unwrap());
+static TABLE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\\begin\{table\*?\}").unwrap());
+/// `\[` opens display math, but the same two characters close a `\\`, as in
+/// `\\[2pt]`; which it is depends on the backslashes before it. See
+/// [`displays`].
+static EQUATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"\\begin\{(?:equation|align|alignat|flalign|gather|multline|eqnarray|displaymath)\*?\}|\\\[",
+    )
+    .unwrap()
+});
+
+/// Counts `files`, relative to `directory`. `pages` comes from the build.
+pub async fn collect(
+    directory: &Path,
+    files: &[String],
+    kind: DocumentKind,
+    pages: Option<i64>,
+) -> DocumentStatistics {
+    let mut statistics = DocumentStatistics {
+        pages,
+        ..DocumentStatistics::default()
+    };
+    for file in files {
+        let path = directory.join(file);
+        let counted = match kind {
+            DocumentKind::Latex => match tokio::fs::read_to_string(&path).await {
+                Ok(text) => latex(&text),
+                Err(_) => continue,
+            },
+            DocumentKind::Markdown => match pandoc_ast(directory, &path).await {
+                Some(ast) => markdown_ast(&ast),
+                None => match tokio::fs::read_to_string(&path).await {
+                    Ok(text) => markdown_text(&text),
+                    Err(_) => continue,
+                },
+            },
+        };
+        statistics.add(&counted);
+    }
+    statistics
+}
+
+/// One LaTeX file. A file with a preamble is counted from `\begin{document}`.
+fn latex(text: &str) -> DocumentStatistics {
+    let source = text.lines().collect::<Vec<_>>().join("\n");
+    let masked = prose::latex(text).join("\n").chars().collect::<Vec<_>>();
+    let characters = source.chars().collect::<Vec<_>>();
+
+    let headings = argument_spans(&characters, HEADINGS);
+    let captions = argument_spans(&characters, CAPTIONS);
+    let abstracts = environment_spans(&characters, "abstract");
+    let mut statistics = DocumentStatistics::default();
+    for (start, end) in words(&masked) {
+        let within =
+            |spans: &[(usize, usize)]| spans.iter().any(|(from, to)| *from <= start && end <= *to);
+        if within(&abstracts) {
+            statistics.abstract_words += 1;
+        }
+        if within(&headings) {
+            statistics.header_words += 1;
+        } else if within(&captions) {
+            statistics.caption_words += 1;
+        } else {
+            statistics.text_words += 1;
+        }
+    }
+
+    let body = match source.find("\\begin{document}") {
+        Some(at) => &source[at..],
+        None => &source[..],
+    };
+    let body = body
+        .lines()
+        .map(strip_comment)
+        .collect::<Vec<_>>()
+        .join("\n");
+    statistics.figures = FIGURE.find_iter(&body).count() as i64;
+    statistics.tables = TABLE.find_iter(&body).count() as i64;
+    statistics.equations = displays(&body);
+    statistics
+}
+
+/// Display equations in `body`, leaving out the `\[` that is a line break's
+/// optional argument: one preceded by an odd number of backslashes.
+fn displays(body: &str) -> i64 {
+    EQUATION
+        .find_iter(body)
+        .filter(|found| {
+            if found.as_str() != "\\[" {
+                return true;
+            }
+            let escapes = body.as_bytes()[..found.start()]
+                .iter()
+                .rev()
+                .take_while(|byte| **byte == b'\\')
+                .count();
+            escapes % 2 == 0
+        })
+        .count() as i64
+}
+
+/// Where each `name` environment sits, from `\begin` to `\end`. One left open
+/// runs to the end of the file.
+fn environment_spans(characters: &[char], name: &str) -> Vec<(usize, usize)> {
+    let (begin, end) = (format!("\\begin{{{name}}}"), format!("\\end{{{name}}}"));
+    let mut spans = Vec::new();
+    let mut index = 0;
+    while let Some(at) = prose::find(characters, index, &begin) {
+        let close = prose::find(characters, at, &end).unwrap_or(characters.len());
+        spans.push((at, close));
+        index = close.max(at + 1);
+    }
+    spans
+}
+
+/// Where the arguments of each of `commands` sit, from the command itself to
+/// the brace that closes its last argument. Across lines: a long heading wraps.
+fn argument_spans(characters: &[char], commands: &[&str]) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut index = 0;
+    while let Some(at) = prose::find(characters, index, "\\") {
+        let name_end = at
+            + 1
+            + characters[at + 1..]
+                .iter()
+                .take_while(|character| character.is_ascii_alphabetic())
+                .count();
+        let name = characters[at + 1..name_end].iter().collect::<String>();
+        index = name_end.max(at + 1);
+        if commands.contains(&name.as_str()) {
+            let end = prose::skip_arguments(characters, name_end);
+            spans.push((at, end));
+            index = end.max(index);
+        }
+    }
+    spans
+}
+
+/// Start and end of each word in masked prose: a run of anything but space
+/// and grouping that holds at least one letter or digit, as texcount counts
+/// them.
+fn words(masked: &[char]) -> Vec<(usize, usize)> {
+    let separates = |character: char| {
+        character.is_whitespace() || matches!(character, '~' | '{' | '}' | '[' | ']')
+    };
+    let mut found = Vec::new();
+    let mut index = 0;
+    while index < masked.len() {
+        if separates(masked[index]) {
+            index += 1;
+            continue;
+        }
+        let start = index;
+        while index < masked.len() && !separates(masked[index]) {
+            index += 1;
+        }
+        if masked[start..index]
+            .iter()
+            .any(|character| character.is_alphanumeric())
+        {
+            found.push((start, index));
+        }
+    }
+    found
+}
+
+/// Runs pandoc's reader alone, for the syntax tree it writes as JSON.
+async fn pandoc_ast(directory: &Path, document: &Path) -> Option<Value> {
+    let pandoc = resolve_executable("pandoc")?;
+    let mut command = Command::new(&pandoc);
+    command.current_dir(directory);
+    command.env("PATH", augmented_path(&pandoc));
+    command.args(["--from", "markdown", "--to", "json"]);
+    command.arg(document);
+    command.stdin(Stdio::null());
+    command.stdout(Stdio::piped()).stderr(Stdio::null());
+    command.kill_on_drop(true);
+    let output = tokio::time::timeout(TIMEOUT, command.output())
+        .await
+        .ok()?
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+#[derive(Clone, Copy)]
+enum Part {
+    Text,
+    Abstract,
+    Heading,
+    Caption,
+}
+
+/// Walks pandoc's blocks. The metadata — title, author, abstract in the front
+/// matter — is left out, as texcount leaves out the preamble, except that the
+/// abstract is counted toward its own number.
+fn markdown_ast(ast: &Value) -> DocumentStatistics {
+    let mut statistics = DocumentStatistics::default();
+    if let Some(blocks) = ast.get("blocks") {
+        walk(blocks, Part::Text, &mut statistics);
+    }
+    if let Some(summary) = ast.pointer("/meta/abstract") {
+        walk(summary, Part::Abstract, &mut statistics);
+    }
+    statistics
+}
+
+fn walk(node: &Value, part: Part, statistics: &mut DocumentStatistics) {
+    let object = match node {
+        Value::Array(items) => {
+            for item in items {
+                walk(item, part, statistics);
+            }
+            return;
+        }
+        Value::Object(object) => object,
+        _ => return,
+    };
+    let content = object.get("c").unwrap_or(&Value::Null);
+    match object.get("t").and_then(Value::as_str) {
+        // One word each: pandoc splits running text on its spaces.
+        Some("Str") => {
+            let counts = content
+                .as_str()
+                .is_some_and(|text| text.chars().any(char::is_alphanumeric));
+            if counts {
+                match part {
+                    Part::Text => statistics.text_words += 1,
+                    Part::Abstract => statistics.abstract_words += 1,
+                    Part::Heading => statistics.header_words += 1,
+                    Part::Caption => statistics.caption_words += 1,
+                }
+            }
+        }
+        // `[level, attr, inlines]`.
+        Some("Header") => walk(&content[2], Part::Heading, statistics),
+        // `[attr, caption, body]`, pandoc 3.
+        Some("Figure") => {
+            statistics.figures += 1;
+            walk(&content[1], Part::Caption, statistics);
+        }
+        // `[attr, caption, colspecs, head, bodies, foot]`.
+        Some("Table") => {
+            statistics.tables += 1;
+            walk(&content[1], Part::Caption, statistics);
+            walk(&content[3], part, statistics);
+            walk(&content[4], part, statistics);
+            walk(&content[5], part, statistics);
+        }
+        // Before pandoc 3 a figure is a paragraph holding one image, and its
+        // caption is the image's description.
+        Some("Para")
+            if content
+                .as_array()
+                .is_some_and(|inlines| inlines.len() == 1 && inlines[0]["t"] == "Image") =>
+        {
+            statistics.figures += 1;
+            walk(&content[0]["c"][1], Part::Caption, statistics);
+        }
+        Some("Math") => {
+            if content[0]["t"] == "DisplayMath" {
+                statistics.equations += 1;
+            }
+        }
+        Some("Code" | "CodeBlock" | "RawInline" | "RawBlock" | "Cite") => {}
+        _ => walk(content, part, statistics),
+    }
+}
+
+/// Markdown without pandoc: the prose filter's words, with a line that opens
+/// with `#` counted as a heading. The front matter is not read, so there is no
+/// abstract count.
+fn markdown_text(text: &str) -> DocumentStatistics {
+    let mut statistics = DocumentStatistics::default();
+    for (line, masked) in text.lines().zip(prose::markdown(text)) {
+        let count = words(&masked.chars().collect::<Vec<_>>()).len() as i64;
+        if line.trim_start().starts_with('#') {
+            statistics.header_words += count;
+        } else {
+            statistics.text_words += count;
+        }
+        if line.trim() == "$$" {
+            // Both fences count; an equation is a pair of them.
+            statistics.equations += 1;
+        }
+    }
+    statistics.equations /= 2;
+    statistics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latex_words_are_split_into_text_headings_and_captions() {
+        let source = "\\documentclass{article}\n\
+                      \\title{Ignored Here}\n\
+                      \\begin{document}\n\
+                      \\section{A Long\n\
+                      Heading}\n\
+                      Some running text, see Figure~\\ref{fig:a}. % not this\n\
+                      \\begin{figure}\n\
+                      \\includegraphics{plot.pdf}\n\
+                      \\caption[Short]{A plot of $x$ against time.}\n\
+                      \\end{figure}\n\
+                      \\begin{table*}\\caption{Results}\\end{table*}\n\
+                      \\begin{equation} E = mc^2 \\end{equation}\n\
+                      \\[ a^2 + b^2 \\]\n\
+                      \\end{document}\n";
+        let statistics = latex(source);
+        assert_eq!(statistics.header_words, 3);
+        assert_eq!(statistics.text_words, 5);
+        // `Short` is the caption too, as texcount has it.
+        assert_eq!(statistics.caption_words, 7);
+        assert_eq!(statistics.figures, 1);
+        assert_eq!(statistics.tables, 1);
+        assert_eq!(statistics.equations, 2);
+        assert_eq!(statistics.abstract_words, 0);
+    }
+
+    #[test]
+    fn the_abstract_is_counted_on_its_own_too() {
+        let source = "\\begin{document}\n\
+                      \\begin{abstract}\n\
+                      Three short words.\n\
+                      \\end{abstract}\n\
+                      \\section{Intro}\n\
+                      Two more.\n\
+                      \\end{document}\n";
+        let statistics = latex(source);
+        assert_eq!(statistics.abstract_words, 3);
+        assert_eq!(statistics.text_words, 5);
+        assert_eq!(statistics.header_words, 1);
+    }
+
+    #[test]
+    fn a_line_break_with_spacing_is_not_an_equation() {
+        let source = "\\begin{document}\n\
+                      First row \\\\[2pt]\n\
+                      second row\\\\\n\
+                      \\[ x \\]\n\
+                      \\\\\\[ y \\]\n\
+                      \\end{document}\n";
+        assert_eq!(latex(source).equations, 2);
+    }
+
+    #[test]
+    fn reads_pandocs_tree_for_markdown() {
+        let ast = serde_json::json!({
+            "pandoc-api-version": [1, 23],
+            "meta": {
+                "title": {"t": "MetaInlines", "c": [{"t": "Str", "c": "Ignored"}]},
+                "abstract": {"t": "MetaBlocks", "c": [{"t": "Para", "c": [
+                    {"t": "Str", "c": "In"}, {"t": "Space"}, {"t": "Str", "c": "brief."}
+                ]}]}
+            },
+            "blocks": [
+                {"t": "Header", "c": [1, ["", [], []], [
+                    {"t": "Str", "c": "Intro"}, {"t": "Space"}, {"t": "Str", "c": "Part"}
+                ]]},
+                {"t": "Para", "c": [
+                    {"t": "Str", "c": "Two"}, {"t": "Space"}, {"t": "Str", "c": "words,"},
+                    {"t": "Space"}, {"t": "Str", "c": "—"},
+                    {"t": "Code", "c": [["", [], []], "not counted"]},
+                    {"t": "Math", "c": [{"t": "DisplayMath"}, "x"]}
+                ]},
+                {"t": "Figure", "c": [["", [], []], [null, [
+                    {"t": "Plain", "c": [{"t": "Str", "c": "Caption"}, {"t": "Space"}, {"t": "Str", "c": "here"}]}
+                ]], [{"t": "Plain", "c": [{"t": "Image", "c": [["", [], []], [], ["a.png", ""]]}]}]]},
+                {"t": "Para", "c": [{"t": "Image", "c": [["", [], []], [{"t": "Str", "c": "Old"}], ["b.png", ""]]}]}
+            ]
+        });
+        let statistics = markdown_ast(&ast);
+        assert_eq!(statistics.header_words, 2);
+        assert_eq!(statistics.text_words, 2);
+        assert_eq!(statistics.caption_words, 3);
+        assert_eq!(statistics.figures, 2);
+        assert_eq!(statistics.equations, 1);
+        assert_eq!(statistics.abstract_words, 2);
+    }
+
+    #[test]
+    fn markdown_without_pandoc_still_counts() {
+        let statistics = markdown_text("# A Title\n\nOne two `three` four.\n\n$$\nx\n$$\n");
+        assert_eq!(statistics.header_words, 2);
+        assert_eq!(statistics.text_words, 3);
+        assert_eq!(statistics.equations, 1);
+    }
+}
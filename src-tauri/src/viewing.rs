@@ -90,6 +90,11 @@ impl Viewing {
         let document = crate::render::open(&canonical)?;
         crate::render::page_count(&document)?;
         drop(document);
+        // Looked for now, while the file is being read anyway, so the offer to
+        // unpack it is there when the PDF is.
+        let embedded_sources = crate::render::open_pdf(&canonical)
+            .map(|document| crate::bundle::sources(&document))
+            .unwrap_or_default();
 
         let name = canonical
             .file_name()
@@ -116,6 +121,7 @@ impl Viewing {
             name,
             path: canonical.to_string_lossy().into_owned(),
             revision: 1,
+            embedded_sources,
         })
     }
 
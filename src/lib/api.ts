@@ -1,22 +1,43 @@
-import { invoke } from '@tauri-apps/api/core';
+import { Channel, invoke } from '@tauri-apps/api/core';
 import type {
+  Annotation,
+  AnnotationPlace,
+  CacheSettings,
   EditorCommand,
   Engine,
+  HistorySearch,
   IconChoice,
+  ImageFormat,
+  LabelMatch,
+  LibraryHit,
   LooseDocument,
+  NewAnnotation,
   Preset,
   PresetList,
   PresetPreview,
+  PreviousBuild,
+  PrintOptions,
   LinkBox,
   OpenRequest,
+  OpenedProject,
+  OutlineEntry,
+  PageConstraints,
+  PagePoint,
+  PageRegion,
   PageSize,
+  PreflightReport,
   ProjectSummary,
-  SearchHit,
+  ReadingPosition,
+  RenderMetrics,
+  SearchMatch,
+  SearchOptions,
   SnapshotOutcome,
   SourcePeek,
   SourceRef,
   TextBox,
-  VersionSummary
+  TextSelection,
+  VersionSummary,
+  ViewState
 } from '$lib/types';
 
 export const api = {
@@ -36,8 +57,20 @@ export const api = {
 
   closePdf: (documentId: number) => invoke<void>('close_pdf', { documentId }),
 
+  /**
+   * Unpacks the sources an open PDF carries into `folder`, which must not
+   * already hold any of them, and adds the document among them as a project.
+   */
+  importEmbedded: (documentId: number, folder: string) =>
+    invoke<ProjectSummary>('import_embedded', { documentId, folder }),
+
+  /** With each version's view state, the one read last first. */
   openProject: (projectId: number) =>
-    invoke<ProjectSummary>('open_project', { projectId }),
+    invoke<OpenedProject>('open_project', { projectId }),
+
+  /** Where the reader is in a version; the working tree without a source reference. */
+  saveViewState: (projectId: number, view: ViewState, sourceRef?: SourceRef) =>
+    invoke<void>('save_view_state', { projectId, sourceRef, view }),
 
   closeProject: () => invoke<void>('close_project'),
 
@@ -73,6 +106,14 @@ export const api = {
   deleteSnapshot: (snapshotId: number) =>
     invoke<void>('delete_snapshot', { snapshotId }),
 
+  /** The working tree's earlier builds still kept, most recent first. */
+  previousBuilds: (projectId: number) =>
+    invoke<PreviousBuild[]>('previous_builds', { projectId }),
+
+  /** Opens a kept build the way a loose PDF is opened. */
+  openPreviousBuild: (previousId: number) =>
+    invoke<LooseDocument>('open_previous_build', { previousId }),
+
   /** Every page's size, cheap enough to lay out a whole document up front. */
   pageLayout: (artifactId: number) =>
     invoke<PageSize[]>('page_layout', { artifactId }),
@@ -85,6 +126,17 @@ export const api = {
   pageLinks: (artifactId: number, page: number) =>
     invoke<LinkBox[]>('page_links', { artifactId, page }),
 
+  /** Bookmarks, or the headings the build recorded when the PDF has none. */
+  documentOutline: (artifactId: number) =>
+    invoke<OutlineEntry[]>('document_outline', { artifactId }),
+
+  /** The `\label`s that best match a name or a printed "Figure 3", with their pages. */
+  findLabel: (artifactId: number, query: string) =>
+    invoke<LabelMatch[]>('find_label', { artifactId, query }),
+
+  /** Fonts, images and metadata, checked the way PDF eXpress and TAPS check them. */
+  preflight: (artifactId: number) => invoke<PreflightReport>('preflight', { artifactId }),
+
   /** Hands a link that leads out of the document to the system. */
   openExternal: (uri: string) => invoke<void>('open_external', { uri }),
 
@@ -96,16 +148,126 @@ export const api = {
   peekSource: (artifactId: number, page: number, x: number, y: number) =>
     invoke<SourcePeek | null>('peek_source', { artifactId, page, x, y }),
 
-  searchDocument: (artifactId: number, needle: string) =>
-    invoke<SearchHit[]>('search_document', { artifactId, needle }),
+  /**
+   * The source line at a point, zero-based page, to carry to another build.
+   * Take it before a rebuild: the rebuild replaces the PDF it was on.
+   */
+  readingPosition: (artifactId: number, page: number, x: number, y: number) =>
+    invoke<ReadingPosition | null>('reading_position', { artifactId, page, x, y }),
+
+  /** Where a reading position is in this artifact; null keeps the page number. */
+  carryPosition: (artifactId: number, position: ReadingPosition) =>
+    invoke<PagePoint | null>('carry_position', { artifactId, position }),
+
+  /** The selection as text to copy; `latexMath` puts back the source's mathematics. */
+  selectionText: (artifactId: number, selection: TextSelection, latexMath: boolean) =>
+    invoke<string>('selection_text', { artifactId, selection, latexMath }),
+
+  /**
+   * Searches the whole document, handing each page's matches to `onMatches`
+   * as soon as they are found. Resolves with the total once every page is done.
+   */
+  searchDocument: (
+    artifactId: number,
+    needle: string,
+    options: SearchOptions,
+    onMatches: (matches: SearchMatch[]) => void,
+  ) => {
+    const results = new Channel<SearchMatch[]>();
+    results.onmessage = onMatches;
+    return invoke<number>('search_document', { artifactId, needle, options, results });
+  },
+
+  /** Pages across every document that contain all of `query`'s words, best first. */
+  searchLibrary: (query: string, snapshots: boolean) =>
+    invoke<LibraryHit[]>('search_library', { query, snapshots }),
+
+  /** Which stored versions contain `needle`, and when it came and went. */
+  searchHistory: (projectId: number, needle: string, options: SearchOptions) =>
+    invoke<HistorySearch>('search_history', { projectId, needle, options }),
 
   /** Copies a built PDF into Downloads. Returns where it was written. */
   exportArtifact: (artifactId: number) =>
     invoke<string>('export_artifact', { artifactId }),
 
+  /** Writes the document's text to Downloads, and resolves with where. */
+  exportText: (artifactId: number) => invoke<string>('export_text', { artifactId }),
+
+  /**
+   * Writes a page, or the region of it given in PDF points, to Downloads as an
+   * image. `page` counts from zero; `dpi` only matters to a PNG.
+   */
+  exportImage: (
+    artifactId: number,
+    page: number,
+    format: ImageFormat,
+    options: { dpi?: number; region?: PageRegion } = {},
+  ) => invoke<string>('export_image', { artifactId, page, format, ...options }),
+
+  /** Writes a copy set up for printing to Downloads, and resolves with where. */
+  exportPrint: (artifactId: number, options: PrintOptions) =>
+    invoke<string>('export_print', { artifactId, options }),
+
+  /** Writes a stored version's PDF with its source attached to Downloads. */
+  exportWithSource: (artifactId: number) =>
+    invoke<string>('export_with_source', { artifactId }),
+
+  /** Anchored to the source line under it when SyncTeX knows one. */
+  addAnnotation: (artifactId: number, annotation: NewAnnotation) =>
+    invoke<Annotation>('add_annotation', { artifactId, annotation }),
+
+  /** On one version, or on every version without a source reference. */
+  listAnnotations: (projectId: number, sourceRef?: SourceRef) =>
+    invoke<Annotation[]>('list_annotations', { projectId, sourceRef }),
+
+  updateAnnotation: (annotationId: number, body: string) =>
+    invoke<Annotation>('update_annotation', { annotationId, body }),
+
+  deleteAnnotation: (annotationId: number) =>
+    invoke<void>('delete_annotation', { annotationId }),
+
+  /** Where one version's annotations fall in the working tree. */
+  relocateAnnotations: (projectId: number, sourceRef: SourceRef) =>
+    invoke<AnnotationPlace[]>('relocate_annotations', { projectId, sourceRef }),
+
+  /** Writes the PDF with its version's annotations in it to Downloads. */
+  exportAnnotated: (artifactId: number) =>
+    invoke<string>('export_annotated', { artifactId }),
+
   getBuildLog: (projectId: number, sourceRef?: SourceRef) =>
     invoke<string>('get_build_log', { projectId, sourceRef }),
 
+  /** chktex warning numbers the project leaves out of its lint stage. */
+  lintIgnores: (projectId: number) => invoke<number[]>('lint_ignores', { projectId }),
+
+  /** Replaces the list and rebuilds the working tree with it. */
+  setLintIgnores: (projectId: number, warnings: number[]) =>
+    invoke<void>('set_lint_ignores', { projectId, warnings }),
+
+  /** Whether LaTeX builds run chktex at all. */
+  lintEnabled: () => invoke<boolean>('lint_enabled'),
+
+  setLintEnabled: (enabled: boolean) => invoke<void>('set_lint_enabled', { enabled }),
+
+  /** The project's personal dictionary for the spelling check. */
+  projectDictionary: (projectId: number) =>
+    invoke<string[]>('project_dictionary', { projectId }),
+
+  /** Adds a word and rebuilds the working tree, so its findings go. */
+  addDictionaryWord: (projectId: number, word: string) =>
+    invoke<void>('add_dictionary_word', { projectId, word }),
+
+  removeDictionaryWord: (projectId: number, word: string) =>
+    invoke<void>('remove_dictionary_word', { projectId, word }),
+
+  /** The template limits checked against the project's PDF after each build. */
+  pageConstraints: (projectId: number) =>
+    invoke<PageConstraints>('page_constraints', { projectId }),
+
+  /** Replaces the limits and rebuilds the working tree against them. */
+  setPageConstraints: (projectId: number, constraints: PageConstraints) =>
+    invoke<void>('set_page_constraints', { projectId, constraints }),
+
   /** Runs the editor command on a document. Nothing is kept open afterwards. */
   launchEditor: (projectId: number) => invoke<string>('launch_editor', { projectId }),
 
@@ -114,6 +276,18 @@ export const api = {
   /** An empty command clears the setting, putting the button back on the default. */
   setEditorCommand: (command: string) => invoke<void>('set_editor_command', { command }),
 
+  keptBuilds: () => invoke<number>('kept_builds'),
+
+  /** Lowering the number lets the builds past it go straight away. */
+  setKeptBuilds: (count: number) => invoke<void>('set_kept_builds', { count }),
+
+  renderCache: () => invoke<CacheSettings>('render_cache'),
+
+  /** Takes effect at once: a smaller budget or a disk tier turned off empties now. */
+  setRenderCache: (settings: CacheSettings) => invoke<void>('set_render_cache', { settings }),
+
+  renderMetrics: () => invoke<RenderMetrics>('render_metrics'),
+
   /** Which of the three tiles Press wears in the Dock. */
   iconChoice: () => invoke<IconChoice>('icon_choice'),
 
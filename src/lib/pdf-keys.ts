@@ -15,7 +15,10 @@ export type ViewerAction =
   /** Back or forward through the places a jump was made from. */
   | { kind: 'jump'; sign: 1 | -1 }
   /** Draw the page for a dark room, or stop. */
-  | { kind: 'invert' };
+  | { kind: 'invert' }
+  /** zathura's marks: `m` and a letter names the place, `'` and the letter returns to it. */
+  | { kind: 'mark'; letter: string }
+  | { kind: 'recall'; letter: string };
 
 export type KeyResolution =
   | { kind: 'action'; action: ViewerAction }
@@ -27,10 +30,12 @@ export type KeyState = {
   count: string;
   /** A `g` is waiting for its second `g`. */
   awaitingG: boolean;
+  /** An `m` or a `'` is waiting for the letter it applies to. */
+  awaitingMark: 'set' | 'recall' | null;
 };
 
 export function initialKeyState(): KeyState {
-  return { count: '', awaitingG: false };
+  return { count: '', awaitingG: false, awaitingMark: null };
 }
 
 export type KeyEventLike = {
@@ -85,11 +90,17 @@ export function resolveKey(
     }
   }
 
+  if (state.awaitingMark) {
+    // A mark is named by a letter; anything else abandons it and does nothing.
+    if (!/^[a-zA-Z]$/.test(event.key)) return { resolution: { kind: 'ignored' }, state: clear };
+    return done({ kind: state.awaitingMark === 'set' ? 'mark' : 'recall', letter: event.key });
+  }
+
   // A count in progress swallows digits, including zero.
   if (/^[0-9]$/.test(event.key) && !(event.key === '0' && state.count === '')) {
     return {
       resolution: { kind: 'pending' },
-      state: { count: state.count + event.key, awaitingG: false }
+      state: { count: state.count + event.key, awaitingG: false, awaitingMark: null }
     };
   }
 
@@ -100,7 +111,7 @@ export function resolveKey(
         : done({ kind: 'goto', target: 'page', page: count });
     }
     // Anything else abandons the pending `g`, then is handled on its own.
-    return resolveKey(event, { count: state.count, awaitingG: false });
+    return resolveKey(event, { ...state, awaitingG: false });
   }
 
   switch (event.key) {
@@ -143,7 +154,14 @@ export function resolveKey(
     case 'K':
       return done({ kind: 'page', sign: -1, count });
     case 'g':
-      return { resolution: { kind: 'pending' }, state: { count: state.count, awaitingG: true } };
+      return {
+        resolution: { kind: 'pending' },
+        state: { count: state.count, awaitingG: true, awaitingMark: null }
+      };
+    case 'm':
+      return { resolution: { kind: 'pending' }, state: { ...clear, awaitingMark: 'set' } };
+    case "'":
+      return { resolution: { kind: 'pending' }, state: { ...clear, awaitingMark: 'recall' } };
     case 'G':
       return state.count === ''
         ? done({ kind: 'goto', target: 'last' })
@@ -59,6 +59,42 @@ export function pageUrl(
   return `${ORIGIN}/page/${artifactId}/${revision}/${page}?scale=${scale.toFixed(4)}${ink}`;
 }
 
+/**
+ * Part of a page, for a zoom too deep to draw the page whole. The rectangle is
+ * in device pixels of the page drawn at `scale`, so neighbouring tiles meet on
+ * the pixel grid; one hanging off the page comes back cut to it.
+ */
+export function tileUrl(
+  artifactId: number,
+  revision: number,
+  page: number,
+  scale: number,
+  tile: { x: number; y: number; width: number; height: number },
+  invert = false
+): string {
+  const ink = invert ? '&invert=1' : '';
+  const { x, y, width, height } = tile;
+  return (
+    `${ORIGIN}/tile/${artifactId}/${revision}/${page}?scale=${scale.toFixed(4)}` +
+    `&x=${x}&y=${y}&width=${width}&height=${height}${ink}`
+  );
+}
+
+/**
+ * A page as a small PNG, `width` device pixels across and no more than 512:
+ * an ordinary image, for a strip of pages or a preview beside a version.
+ */
+export function thumbnailUrl(
+  artifactId: number,
+  revision: number,
+  page: number,
+  width: number,
+  invert = false
+): string {
+  const ink = invert ? '&invert=1' : '';
+  return `${ORIGIN}/thumbnail/${artifactId}/${revision}/${page}?width=${Math.round(width)}${ink}`;
+}
+
 /**
  * A compiled preset's sample page.
  *
@@ -24,8 +24,12 @@ export type Diagnostic = {
   /** Project-relative when it could be resolved inside the project. */
   file: string | null;
   line: number | null;
+  /** One-based. Only the tools that read the source report it; TeX does not. */
+  column: number | null;
   severity: Severity;
   message: string;
+  /** What found it, when not the build itself: `chktex`, say. */
+  source: string | null;
 };
 
 export type BuildState = {
@@ -50,6 +54,78 @@ export type ArtifactSummary = {
   revision: number;
 };
 
+/** How much of what the renderer draws is kept, and whether on disk too. */
+export type CacheSettings = {
+  memoryMegabytes: number;
+  disk: boolean;
+};
+
+/** How long one kind of render job has been taking, in milliseconds. */
+export type JobLatency = {
+  kind: string;
+  count: number;
+  meanMs: number;
+  longestMs: number;
+  lastMs: number;
+};
+
+/** The render pool's threads, what they hold open, and how long jobs take. */
+export type RenderMetrics = {
+  workers: number;
+  openDocumentBytes: number;
+  jobs: JobLatency[];
+};
+
+/** A working-tree PDF a later build replaced, kept to go back to. */
+export type PreviousBuild = {
+  id: number;
+  artifactId: number;
+  /** The artifact's revision this PDF was published as. */
+  revision: number;
+  byteSize: number;
+  builtAt: number;
+  supersededAt: number;
+};
+
+/**
+ * What one build counted. Words are split texcount's way: running text,
+ * headings and captions apart, so a limit on the text is checked against the
+ * text. The abstract is counted on its own as well, for the limit set on it.
+ */
+export type DocumentStatistics = {
+  textWords: number;
+  abstractWords: number;
+  headerWords: number;
+  captionWords: number;
+  pages: number | null;
+  figures: number;
+  tables: number;
+  equations: number;
+};
+
+export type PaperSize = 'letter' | 'a4';
+
+/** Margins in PDF points, each the narrowest allowed. */
+export type Margins = {
+  top: number;
+  bottom: number;
+  left: number;
+  right: number;
+};
+
+/**
+ * A venue's limits on a project's PDF, checked after every build. Any part may
+ * be left out; a broken one is an error in the diagnostics strip.
+ */
+export type PageConstraints = {
+  /** Pages allowed before the references begin. */
+  maxPages: number | null;
+  paper: PaperSize | null;
+  /** In points. */
+  minFontSize: number | null;
+  margins: Margins | null;
+};
+
 /**
  * A project plus the state of its working tree.
  *
@@ -107,6 +183,7 @@ export type VersionSummary = {
   snapshot: SnapshotSummary | null;
   build: BuildState;
   artifact: ArtifactSummary | null;
+  statistics: DocumentStatistics | null;
 };
 
 /**
@@ -154,6 +231,73 @@ export type TextBox = {
   line: number;
 };
 
+/** A heading to jump to. `level` is 0 for the outermost; `page` is 1-based. */
+export type OutlineEntry = {
+  title: string;
+  level: number;
+  page: number;
+  /** PDF points from the top of the page, when known. */
+  top: number | null;
+};
+
+export type LabelMatch = {
+  name: string;
+  /** As the document prints it: "Figure 3". */
+  caption: string;
+  title: string | null;
+  printedPage: string;
+  /** 1-based; null when the PDF does not say where it is. */
+  page: number | null;
+};
+
+export type Venue = 'ieeePdfExpress' | 'acmTaps';
+
+export type PreflightFinding = {
+  severity: Severity;
+  message: string;
+  /** Whose checker rejects or complains about this. */
+  venues: Venue[];
+};
+
+/** One font, wherever it is used, figures included. */
+export type FontReport = {
+  /** Without the subset tag. */
+  name: string;
+  kind: string;
+  embedded: boolean;
+  subset: boolean;
+  pages: number[];
+};
+
+/** One placement of an image. `dpi` is null when it could not be measured. */
+export type ImageReport = {
+  page: number;
+  width: number;
+  height: number;
+  dpi: number | null;
+  colorSpace: string | null;
+  monochrome: boolean;
+};
+
+export type PdfMetadata = {
+  title: string | null;
+  author: string | null;
+  subject: string | null;
+  keywords: string | null;
+  creator: string | null;
+  producer: string | null;
+};
+
+export type PreflightReport = {
+  pdfVersion: string | null;
+  encrypted: boolean;
+  pageCount: number;
+  metadata: PdfMetadata;
+  fonts: FontReport[];
+  images: ImageReport[];
+  findings: PreflightFinding[];
+};
+
 export type SearchHit = {
   page: number;
   x: number;
@@ -162,6 +306,158 @@ export type SearchHit = {
   height: number;
 };
 
+export type HistoryMatch = {
+  path: string;
+  line: number;
+  column: number;
+  text: string;
+};
+
+export type HistoryVersion = {
+  snapshot: SnapshotSummary;
+  /** Every match; `matches` holds only the first few. */
+  count: number;
+  matches: HistoryMatch[];
+};
+
+export type HistorySighting = {
+  snapshot: SnapshotSummary;
+  at: HistoryMatch;
+};
+
+export type HistorySearch = {
+  /** Oldest first. */
+  versions: HistoryVersion[];
+  firstSeen: HistorySighting | null;
+  lastSeen: HistorySighting | null;
+  removedIn: SnapshotSummary | null;
+};
+
+export type ImageFormat = 'png' | 'svg';
+
+export type PrintLayout = 'single' | 'twoUp' | 'fourUp' | 'booklet';
+
+export type PrintOptions = {
+  /** As a print dialogue takes it: `1-4, 7, 10-`. Empty for every page. */
+  pages: string;
+  layout: PrintLayout;
+  cropMarks: boolean;
+};
+
+/** In PDF points from the page's top left. */
+export type PageRegion = { x: number; y: number; width: number; height: number };
+
+export type AnnotationKind = 'highlight' | 'comment';
+
+export type SourceAnchor = {
+  file: string;
+  /** 1-based. */
+  line: number;
+  /** What the line said when the annotation was made. */
+  text: string;
+};
+
+/** Pages count from zero; rectangles are in PDF points from the page's top left. */
+export type Annotation = {
+  id: number;
+  projectId: number;
+  sourceRef: SourceRef;
+  kind: AnnotationKind;
+  page: number;
+  rects: PageRegion[];
+  quote: string;
+  body: string;
+  anchor: SourceAnchor | null;
+  createdAt: number;
+  updatedAt: number;
+};
+
+export type NewAnnotation = {
+  kind: AnnotationKind;
+  page: number;
+  rects: PageRegion[];
+  quote?: string;
+  body?: string;
+};
+
+/** The passage being read, as a line of its version's source. */
+export type ReadingPosition = {
+  sourceRef: SourceRef;
+  anchor: SourceAnchor;
+};
+
+/** Pages count from zero; points are PDF points from the page's top left. */
+export type PagePoint = { page: number; x: number; y: number };
+
+export type ZoomMode = 'width' | 'page' | 'actual' | 'custom';
+
+/** Where the reader was in a version. `zoom` applies when the mode is `custom`. */
+export type ViewState = {
+  position: PagePoint;
+  zoomMode: ZoomMode;
+  zoom: number;
+  /** Null follows Press's own theme. */
+  dark: boolean | null;
+  /** Letters to the places they mark. */
+  marks: Record<string, PagePoint>;
+  /** Oldest first. */
+  jumps: PagePoint[];
+};
+
+export type SavedView = ViewState & {
+  sourceRef: SourceRef;
+  updatedAt: number;
+};
+
+export type OpenedProject = ProjectSummary & {
+  /** Most recently saved first. */
+  views: SavedView[];
+};
+
+/** `line` is 1-based; `page` counts from zero and is null when there is no place for it. */
+export type AnnotationPlace = {
+  annotationId: number;
+  line: number | null;
+  page: number | null;
+  x: number;
+  y: number;
+};
+
+/** Pages and word indices count from zero; a word range includes both ends. */
+export type TextSelection =
+  | { kind: 'region'; page: number; x: number; y: number; width: number; height: number }
+  | { kind: 'words'; fromPage: number; fromWord: number; toPage: number; toWord: number };
+
+export type SnippetPart = {
+  text: string;
+  matched: boolean;
+};
+
+export type LibraryHit = {
+  projectId: number;
+  projectName: string;
+  artifactId: number;
+  sourceRef: SourceRef;
+  /** 1-based. */
+  page: number;
+  snippet: SnippetPart[];
+};
+
+export type SearchOptions = {
+  caseSensitive: boolean;
+  wholeWord: boolean;
+  regex: boolean;
+};
+
+export type SearchMatch = {
+  page: number;
+  /** One per line the match runs across. */
+  rects: SearchHit[];
+  before: string;
+  text: string;
+  after: string;
+};
+
 /** Whether a tool is installed, and where. Nothing asks it for its version. */
 export type ToolInfo = {
   available: boolean;
@@ -191,6 +487,8 @@ export type LooseDocument = {
   path: string;
   /** Bumped when the file changes on disk. */
   revision: number;
+  /** Attachments that can be unpacked into a project; empty when there are none. */
+  embeddedSources: string[];
 };
 
 /**
@@ -282,6 +580,9 @@ export type BuildUpdate = {
   sourceRef: SourceRef;
   build: BuildState;
   artifact: ArtifactSummary | null;
+  statistics: DocumentStatistics | null;
+  /** Zero-based pages a working-tree rebuild changed, on the update after its publication; null when nothing was compared. */
+  changedPages: number[] | null;
 };
 
 export type WatcherError = {
@@ -69,6 +69,17 @@ test('control-r inverts the page', () => {
   assert.equal(type(['r']).resolution.kind, 'ignored');
 });
 
+test('m and a letter set a mark, and quote and the letter return to it', () => {
+  assert.equal(resolveKey(press('m'), initialKeyState()).resolution.kind, 'pending');
+  assert.deepEqual(type(['m', 'a']).resolution.action, { kind: 'mark', letter: 'a' });
+  assert.deepEqual(type(["'", 'a']).resolution.action, { kind: 'recall', letter: 'a' });
+  // Only a letter names a mark; anything else lets the pending one go.
+  const abandoned = type(['m', '1']);
+  assert.equal(abandoned.resolution.kind, 'ignored');
+  assert.equal(abandoned.state.awaitingMark, null);
+  assert.equal(type(['j'], abandoned.state).resolution.action.kind, 'scroll');
+});
+
 test('space pages forward and shift-space back', () => {
   assert.equal(type([' ']).resolution.action.sign, 1);
   assert.equal(type([{ key: ' ', shiftKey: true }]).resolution.action.sign, -1);